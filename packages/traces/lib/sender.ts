@@ -1,19 +1,27 @@
 import { logger } from './logger'
 import { SerializeTraceDump, TraceDump } from './serializer'
-import { GetDevWalletSocket } from './socket'
+import { GetDevWalletSocket, SendToDevWallet } from './socket'
+
+/** Dumps larger than this are sent as `transactions_dump_chunk` messages */
+export const DUMP_CHUNK_SIZE = 512 * 1024
 
 export async function SendDumpToDevWallet(dump: TraceDump) {
   const serializedDump = SerializeTraceDump(dump)
   try {
     const ws = await GetDevWalletSocket()
     if (ws) {
-      ws.send(
-        JSON.stringify({
-          type: 'transactions_dump',
-          data: serializedDump,
-        })
-      )
-      ws.close()
+      try {
+        if (serializedDump.length <= DUMP_CHUNK_SIZE) {
+          await SendToDevWallet(ws, {
+            type: 'transactions_dump',
+            data: serializedDump,
+          })
+        } else {
+          await SendDumpInChunks(ws, serializedDump)
+        }
+      } finally {
+        ws.close()
+      }
     } else {
       logger?.error('Could not connect to TON wallet')
     }
@@ -21,3 +29,19 @@ export async function SendDumpToDevWallet(dump: TraceDump) {
     logger?.error('WebSocket connection error:', wsError)
   }
 }
+
+// Each chunk waits for its ack, so a large dump never floods the wallet
+async function SendDumpInChunks(ws: WebSocket, serializedDump: string) {
+  const dumpId = `${Date.now().toString(36)}-${Math.random().toString(36).slice(2)}`
+  const total = Math.ceil(serializedDump.length / DUMP_CHUNK_SIZE)
+
+  for (let index = 0; index < total; index++) {
+    await SendToDevWallet(ws, {
+      type: 'transactions_dump_chunk',
+      dump_id: dumpId,
+      index,
+      total,
+      data: serializedDump.slice(index * DUMP_CHUNK_SIZE, (index + 1) * DUMP_CHUNK_SIZE),
+    })
+  }
+}
@@ -1,14 +1,250 @@
 import { logger } from './logger'
 
+/** Echo protocol version spoken by this package */
+export const DEV_WALLET_PROTOCOL_VERSION = 8
+
+export interface DevWalletAck {
+  type: 'ack'
+  id?: unknown
+  request: string
+}
+
+export interface DevWalletError {
+  type: 'error'
+  id?: unknown
+  code: string
+  message: string
+}
+
+export type DevWalletTransactionOutcome =
+  | { status: 'approved'; boc: string; hash: string }
+  | { status: 'rejected'; reason: string }
+  | { status: 'failed'; reason: string }
+
+export interface DevWalletTransactionResult {
+  type: 'transaction_result'
+  id: unknown
+  result: DevWalletTransactionOutcome
+}
+
+export type DevWalletTopic = 'selected_network' | 'wallets' | 'pending_messages' | 'trace_added'
+
+export interface DevWalletUpdate {
+  type: 'update'
+  topic: DevWalletTopic
+  data: unknown
+}
+
+/** Address book entry written by `LabelContractsInDevWallet` */
+export interface DevWalletAddressLabel {
+  /** Raw or user-friendly address */
+  address: string
+  title: string
+  description?: string
+}
+
+/** Identity the dev wallet pairs this client under */
+export interface DevWalletClient {
+  id: string
+  name?: string
+}
+
+const DEFAULT_CLIENT_NAME = '@tondevwallet/traces'
+
+/**
+ * Client ids and the secrets the wallet issued when pairing them. Kept in
+ * `~/.tondevwallet/clients.json` in Node and in `localStorage` in browsers.
+ */
+interface ClientStore {
+  /** Id generated for this machine or browser profile */
+  default_id?: string
+  secrets: Record<string, string>
+}
+
+const CLIENT_STORE_FILE = 'clients.json'
+const CLIENT_STORE_KEY = 'tondevwallet-clients'
+
+/** A running dev wallet, read from its discovery file */
+export interface DevWalletInstance {
+  pid: number
+  port: number
+  protocol_version: number
+  /** Handshake token that skips pairing, absent on Windows */
+  token?: string
+  app_version: string
+  started_at: number
+  /** Unix socket speaking the same protocol as newline-delimited JSON */
+  socket?: string
+}
+
+/** Environment variable overriding the discovery directory, as in the wallet */
+const DISCOVERY_DIR_ENV = 'TONDEVWALLET_DISCOVERY_DIR'
+
+let nextMessageId = 1
+
+/** Node modules, or undefined in browsers */
+function nodeModules() {
+  // Indirect, so browser bundlers do not try to resolve the Node modules
+  const nodeRequire = typeof require === 'function' ? require : undefined
+  if (!nodeRequire || typeof process === 'undefined') {
+    return undefined
+  }
+  return {
+    crypto: nodeRequire('crypto') as typeof import('crypto'),
+    fs: nodeRequire('fs') as typeof import('fs'),
+    os: nodeRequire('os') as typeof import('os'),
+    path: nodeRequire('path') as typeof import('path'),
+  }
+}
+
+function readClientStore(): ClientStore {
+  try {
+    const node = nodeModules()
+    const raw = node
+      ? node.fs.readFileSync(
+          node.path.join(node.os.homedir(), '.tondevwallet', CLIENT_STORE_FILE),
+          'utf8'
+        )
+      : globalThis.localStorage?.getItem(CLIENT_STORE_KEY)
+    if (raw) {
+      const store = JSON.parse(raw) as ClientStore
+      return { ...store, secrets: store.secrets ?? {} }
+    }
+  } catch {
+    // Not written yet
+  }
+  return { secrets: {} }
+}
+
+function writeClientStore(store: ClientStore) {
+  const raw = JSON.stringify(store, null, 2)
+  try {
+    const node = nodeModules()
+    if (node) {
+      const dir = node.path.join(node.os.homedir(), '.tondevwallet')
+      node.fs.mkdirSync(dir, { recursive: true })
+      // Readable by the user only, as the secrets stand in for the user's approval
+      node.fs.writeFileSync(node.path.join(dir, CLIENT_STORE_FILE), raw, { mode: 0o600 })
+    } else {
+      globalThis.localStorage?.setItem(CLIENT_STORE_KEY, raw)
+    }
+  } catch (error: unknown) {
+    logger?.error(
+      `Could not save the dev wallet pairing: ${error instanceof Error ? error.message : String(error)}`
+    )
+  }
+}
+
+/**
+ * The client this package pairs as by default. Its id is generated once per
+ * machine or browser profile, so two installs never share a pairing.
+ */
+export function DefaultDevWalletClient(): DevWalletClient {
+  const store = readClientStore()
+  if (!store.default_id) {
+    const node = nodeModules()
+    const random = node
+      ? node.crypto.randomBytes(8)
+      : globalThis.crypto.getRandomValues(new Uint8Array(8))
+    const suffix = Array.from(random, (b) => b.toString(16).padStart(2, '0')).join('')
+    store.default_id = `tondevwallet-traces-${suffix}`
+    writeClientStore(store)
+  }
+  return { id: store.default_id, name: DEFAULT_CLIENT_NAME }
+}
+
+function pairedSecret(clientId: string): string | undefined {
+  return readClientStore().secrets[clientId]
+}
+
+function savePairedSecret(clientId: string, secret: string) {
+  const store = readClientStore()
+  store.secrets[clientId] = secret
+  writeClientStore(store)
+}
+
+/**
+ * Running dev wallets, newest first. Each instance keeps a file in
+ * `~/.tondevwallet/instances` while its echo server listens.
+ * Only available in Node; returns an empty list in browsers.
+ */
+export function ListDevWalletInstances(): DevWalletInstance[] {
+  const node = nodeModules()
+  if (!node) {
+    return []
+  }
+
+  try {
+    const { fs, os, path } = node
+
+    const dir =
+      process.env[DISCOVERY_DIR_ENV] || path.join(os.homedir(), '.tondevwallet', 'instances')
+    if (!fs.existsSync(dir)) {
+      return []
+    }
+
+    const instances: DevWalletInstance[] = []
+    for (const name of fs.readdirSync(dir)) {
+      if (!name.endsWith('.json')) {
+        continue
+      }
+      try {
+        const instance: DevWalletInstance = JSON.parse(
+          fs.readFileSync(path.join(dir, name), 'utf8')
+        )
+        if (isRunning(instance.pid)) {
+          instances.push(instance)
+        }
+      } catch (error: unknown) {
+        logger?.error(
+          `Skipping discovery file ${name}: ${error instanceof Error ? error.message : String(error)}`
+        )
+      }
+    }
+    return instances.sort((a, b) => b.started_at - a.started_at)
+  } catch (error: unknown) {
+    logger?.error(
+      `Failed to list dev wallets: ${error instanceof Error ? error.message : String(error)}`
+    )
+    return []
+  }
+}
+
+function isRunning(pid: number): boolean {
+  try {
+    process.kill(pid, 0)
+    return true
+  } catch (error: unknown) {
+    // The process exists but belongs to another user
+    return (error as { code?: string }).code === 'EPERM'
+  }
+}
+
+/**
+ * Connect to a running dev wallet. Instances listed in discovery files are
+ * tried first, newest first, then ports from `portStart` are probed.
+ */
 export async function GetDevWalletSocket(
   portStart: number = 33000,
   portsToTest: number = 10,
-  timeout: number = 1000
+  timeout: number = 1000,
+  client: DevWalletClient = DefaultDevWalletClient()
 ): Promise<WebSocket | null> {
+  for (const instance of ListDevWalletInstances()) {
+    try {
+      const ws = await OpenDevWalletSocket(instance.port, timeout, client, undefined, instance.token)
+      if (ws) return ws
+    } catch (error: unknown) {
+      logger?.error(
+        `Failed to connect to dev wallet ${instance.pid} on port ${instance.port}: ${error instanceof Error ? error.message : String(error)}`
+      )
+    }
+  }
+
   for (let i = 0; i < portsToTest; i++) {
     const port = portStart + i
     try {
-      const ws = await OpenDevWalletSocket(port, timeout)
+      const ws = await OpenDevWalletSocket(port, timeout, client)
       if (ws) return ws
     } catch (error: unknown) {
       logger?.error(
@@ -19,15 +255,24 @@ export async function GetDevWalletSocket(
   return null
 }
 
+/**
+ * Connect to the dev wallet on `port`. The first connection of a client waits
+ * up to `pairingTimeout` for the user to approve it in the wallet, unless the
+ * `token` from the wallet's discovery file is given. The secret the wallet
+ * issues on approval is saved and sent on later connections.
+ */
 export async function OpenDevWalletSocket(
   port: number,
-  connectionTimeout: number = 1000
+  connectionTimeout: number = 1000,
+  client: DevWalletClient = DefaultDevWalletClient(),
+  pairingTimeout: number = 120_000,
+  token?: string
 ): Promise<WebSocket | null> {
   // Create a new promise that will resolve when we get a valid response or reject on timeout
   return new Promise<WebSocket>((resolve, reject) => {
     const ws = new WebSocket(`ws://localhost:${port}`)
 
-    const timeout = setTimeout(() => {
+    let timeout = setTimeout(() => {
       ws.close()
       reject(new Error('Connection timeout'))
     }, connectionTimeout)
@@ -38,6 +283,11 @@ export async function OpenDevWalletSocket(
         JSON.stringify({
           type: 'handshake',
           id: 1,
+          version: DEV_WALLET_PROTOCOL_VERSION,
+          client_id: client.id,
+          client_secret: pairedSecret(client.id),
+          client_name: client.name,
+          token,
         })
       )
     }
@@ -45,9 +295,31 @@ export async function OpenDevWalletSocket(
     ws.onmessage = function (event) {
       try {
         const response = JSON.parse(event.data)
+        if (response.type === 'pairing_pending') {
+          logger?.log('Approve this client in TON DevWallet to continue')
+          clearTimeout(timeout)
+          timeout = setTimeout(() => {
+            ws.close()
+            reject(new Error('Pairing was not approved'))
+          }, pairingTimeout)
+          return
+        }
         if (response.type === 'response' && response.name === 'tondevwallet') {
+          if (typeof response.client_secret === 'string') {
+            savePairedSecret(client.id, response.client_secret)
+          }
           clearTimeout(timeout)
+          ws.onmessage = null
+          ws.onerror = null
           resolve(ws)
+        } else if (response.type === 'error' && response.code === 'pairing_rejected') {
+          clearTimeout(timeout)
+          ws.close()
+          reject(new Error(`Pairing rejected: ${response.message}`))
+        } else if (response.type === 'error' && response.code === 'unauthorized') {
+          clearTimeout(timeout)
+          ws.close()
+          reject(new Error(`Not authorized: ${response.message}`))
         } else {
           clearTimeout(timeout)
           ws.close()
@@ -67,3 +339,154 @@ export async function OpenDevWalletSocket(
     }
   })
 }
+
+/**
+ * Send a message and wait until the wallet acknowledges it.
+ * Rejects with the wallet's error message, or on timeout.
+ */
+export function SendToDevWallet(
+  ws: WebSocket,
+  message: { type: string; [key: string]: unknown },
+  ackTimeout: number = 5000
+): Promise<DevWalletAck> {
+  const id = nextMessageId++
+
+  return new Promise<DevWalletAck>((resolve, reject) => {
+    const cleanup = () => {
+      clearTimeout(timeout)
+      ws.removeEventListener('message', onMessage)
+    }
+
+    const timeout = setTimeout(() => {
+      cleanup()
+      reject(new Error(`No acknowledgement for ${message.type}`))
+    }, ackTimeout)
+
+    const onMessage = (event: MessageEvent) => {
+      let response: DevWalletAck | DevWalletError
+      try {
+        response = JSON.parse(event.data)
+      } catch {
+        return
+      }
+      if (response.id !== id) {
+        return
+      }
+
+      cleanup()
+      if (response.type === 'ack') {
+        resolve(response)
+      } else {
+        reject(new Error(`Dev wallet rejected ${message.type}: ${response.message}`))
+      }
+    }
+
+    ws.addEventListener('message', onMessage)
+    ws.send(JSON.stringify({ ...message, id }))
+  })
+}
+
+/**
+ * Send a `proxy_transaction` and wait until the user approves or rejects it
+ * in the wallet. Resolves with the signed BOC and hash, or the rejection reason.
+ */
+export async function SendTransactionToDevWallet(
+  ws: WebSocket,
+  data: { payload: unknown; publicKey: string },
+  resultTimeout: number = 5 * 60 * 1000
+): Promise<DevWalletTransactionOutcome> {
+  const ack = await SendToDevWallet(ws, {
+    type: 'proxy_transaction',
+    data,
+    await_result: true,
+  })
+
+  return new Promise<DevWalletTransactionOutcome>((resolve, reject) => {
+    const cleanup = () => {
+      clearTimeout(timeout)
+      ws.removeEventListener('message', onMessage)
+      ws.removeEventListener('close', onClose)
+    }
+
+    const timeout = setTimeout(() => {
+      cleanup()
+      reject(new Error('No transaction result from the dev wallet'))
+    }, resultTimeout)
+
+    const onMessage = (event: MessageEvent) => {
+      let response: DevWalletTransactionResult
+      try {
+        response = JSON.parse(event.data)
+      } catch {
+        return
+      }
+      if (response.type !== 'transaction_result' || response.id !== ack.id) {
+        return
+      }
+
+      cleanup()
+      resolve(response.result)
+    }
+
+    const onClose = () => {
+      cleanup()
+      reject(new Error('Dev wallet closed the connection before answering'))
+    }
+
+    ws.addEventListener('message', onMessage)
+    ws.addEventListener('close', onClose)
+  })
+}
+
+/**
+ * Follow wallet state. `onUpdate` gets the current value of each state topic
+ * right away and every change after that. Returns a function that unsubscribes.
+ */
+export async function SubscribeToDevWallet(
+  ws: WebSocket,
+  topics: DevWalletTopic[],
+  onUpdate: (update: DevWalletUpdate) => void
+): Promise<() => Promise<void>> {
+  const onMessage = (event: MessageEvent) => {
+    let update: DevWalletUpdate
+    try {
+      update = JSON.parse(event.data)
+    } catch {
+      return
+    }
+    if (update.type === 'update' && topics.includes(update.topic)) {
+      onUpdate(update)
+    }
+  }
+
+  // Listen first, the current state can arrive before the ack
+  ws.addEventListener('message', onMessage)
+  try {
+    await SendToDevWallet(ws, { type: 'subscribe', topics })
+  } catch (error) {
+    ws.removeEventListener('message', onMessage)
+    throw error
+  }
+
+  return async () => {
+    ws.removeEventListener('message', onMessage)
+    await SendToDevWallet(ws, { type: 'unsubscribe', topics })
+  }
+}
+
+/**
+ * Add the addresses to the wallet's address book on every network with
+ * `chainId` (-239 for mainnet, -3 for testnet). Entries for addresses already
+ * in the book are renamed. Rejects when the chain is unknown or any address
+ * is invalid; nothing is written then.
+ */
+export async function LabelContractsInDevWallet(
+  ws: WebSocket,
+  chainId: number,
+  contracts: DevWalletAddressLabel[]
+): Promise<void> {
+  await SendToDevWallet(ws, {
+    type: 'label_contracts',
+    data: { chain_id: chainId, contracts },
+  })
+}
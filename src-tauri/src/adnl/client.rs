@@ -0,0 +1,269 @@
+use super::crypto::{self, Aes256Ctr};
+use super::tl::{
+    TlReader, TlWriter, ADNL_MESSAGE_ANSWER, ADNL_MESSAGE_QUERY, LITE_SERVER_ERROR,
+    LITE_SERVER_QUERY, TCP_PING, TCP_PONG,
+};
+use super::{AdnlError, AdnlResult};
+use ctr::cipher::StreamCipher;
+use log::trace;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+/// Largest packet accepted from a liteserver
+const MAX_PACKET_LEN: usize = 16 * 1024 * 1024;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+type PendingQueries = Arc<Mutex<HashMap<[u8; 32], oneshot::Sender<Vec<u8>>>>>;
+type PendingPings = Arc<Mutex<HashMap<i64, oneshot::Sender<()>>>>;
+
+struct Writer {
+    half: OwnedWriteHalf,
+    cipher: Aes256Ctr,
+}
+
+/// An established ADNL TCP session with a single liteserver.
+///
+/// Queries can be issued concurrently; answers are matched by query id.
+pub struct LiteConnection {
+    addr: SocketAddr,
+    writer: tokio::sync::Mutex<Writer>,
+    pending: PendingQueries,
+    pending_pings: PendingPings,
+    closed: Arc<AtomicBool>,
+    reader_task: JoinHandle<()>,
+}
+
+impl LiteConnection {
+    /// Connect to a liteserver and perform the ADNL handshake
+    pub async fn connect(addr: SocketAddr, server_key: &[u8; 32]) -> AdnlResult<Self> {
+        let stream = timeout(HANDSHAKE_TIMEOUT, TcpStream::connect(addr))
+            .await
+            .map_err(|_| AdnlError::Timeout)??;
+        Self::handshake(stream, server_key).await
+    }
+
+    /// Perform the ADNL handshake over an already connected stream
+    pub async fn handshake(stream: TcpStream, server_key: &[u8; 32]) -> AdnlResult<Self> {
+        let addr = stream.peer_addr()?;
+        let _ = stream.set_nodelay(true);
+        let (mut read_half, mut write_half) = stream.into_split();
+
+        let (packet, ciphers) = crypto::client_handshake(server_key)?;
+        let mut rx = ciphers.rx;
+        write_half.write_all(&packet).await?;
+
+        // The server confirms the handshake with an empty packet
+        let confirmation = timeout(HANDSHAKE_TIMEOUT, read_packet(&mut read_half, &mut rx))
+            .await
+            .map_err(|_| AdnlError::Handshake("No confirmation from server".to_string()))?
+            .map_err(|e| AdnlError::Handshake(e.to_string()))?;
+        if !confirmation.is_empty() {
+            return Err(AdnlError::Handshake("Unexpected confirmation packet".to_string()));
+        }
+        trace!("ADNL handshake with {} complete", addr);
+
+        let pending: PendingQueries = Arc::new(Mutex::new(HashMap::new()));
+        let pending_pings: PendingPings = Arc::new(Mutex::new(HashMap::new()));
+        let closed = Arc::new(AtomicBool::new(false));
+
+        let reader_task = tokio::spawn(read_loop(
+            read_half,
+            rx,
+            pending.clone(),
+            pending_pings.clone(),
+            closed.clone(),
+        ));
+
+        Ok(Self {
+            addr,
+            writer: tokio::sync::Mutex::new(Writer {
+                half: write_half,
+                cipher: ciphers.tx,
+            }),
+            pending,
+            pending_pings,
+            closed,
+            reader_task,
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    /// Send a serialized liteServer function and return the raw TL answer.
+    /// A `liteServer.error` answer is turned into [`AdnlError::LiteServer`].
+    pub async fn query(&self, request: &[u8]) -> AdnlResult<Vec<u8>> {
+        let lite_query = TlWriter::new().u32(LITE_SERVER_QUERY).bytes(request).finish();
+        let answer = self.query_raw(&lite_query).await?;
+
+        let mut reader = TlReader::new(&answer);
+        if reader.u32()? == LITE_SERVER_ERROR {
+            return Err(AdnlError::LiteServer {
+                code: reader.i32()?,
+                message: reader.string()?,
+            });
+        }
+
+        Ok(answer)
+    }
+
+    /// Send an already wrapped query (e.g. `liteServer.query`) under a fresh
+    /// query id and return the answer bytes untouched
+    pub async fn query_raw(&self, query: &[u8]) -> AdnlResult<Vec<u8>> {
+        let query_id: [u8; 32] = rand::random();
+        let message = TlWriter::new()
+            .u32(ADNL_MESSAGE_QUERY)
+            .int256(&query_id)
+            .bytes(query)
+            .finish();
+
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(query_id, tx);
+
+        if let Err(e) = self.send(&message).await {
+            self.pending.lock().unwrap().remove(&query_id);
+            return Err(e);
+        }
+
+        match timeout(QUERY_TIMEOUT, rx).await {
+            Ok(Ok(answer)) => Ok(answer),
+            Ok(Err(_)) => Err(AdnlError::Closed),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&query_id);
+                Err(AdnlError::Timeout)
+            }
+        }
+    }
+
+    /// Send a `tcp.ping` and return the round-trip time
+    pub async fn ping(&self) -> AdnlResult<Duration> {
+        let random_id: i64 = rand::random();
+        let message = TlWriter::new().u32(TCP_PING).i64(random_id).finish();
+
+        let (tx, rx) = oneshot::channel();
+        self.pending_pings.lock().unwrap().insert(random_id, tx);
+
+        let started = Instant::now();
+        if let Err(e) = self.send(&message).await {
+            self.pending_pings.lock().unwrap().remove(&random_id);
+            return Err(e);
+        }
+
+        match timeout(QUERY_TIMEOUT, rx).await {
+            Ok(Ok(())) => Ok(started.elapsed()),
+            Ok(Err(_)) => Err(AdnlError::Closed),
+            Err(_) => {
+                self.pending_pings.lock().unwrap().remove(&random_id);
+                Err(AdnlError::Timeout)
+            }
+        }
+    }
+
+    async fn send(&self, payload: &[u8]) -> AdnlResult<()> {
+        if self.is_closed() {
+            return Err(AdnlError::Closed);
+        }
+
+        let mut writer = self.writer.lock().await;
+        let packet = crypto::encode_packet(payload, &mut writer.cipher);
+        writer.half.write_all(&packet).await.map_err(|e| {
+            self.closed.store(true, Ordering::Relaxed);
+            AdnlError::from(e)
+        })
+    }
+}
+
+impl Drop for LiteConnection {
+    fn drop(&mut self) {
+        self.reader_task.abort();
+    }
+}
+
+async fn read_packet(read_half: &mut OwnedReadHalf, cipher: &mut Aes256Ctr) -> AdnlResult<Vec<u8>> {
+    let mut len = [0u8; 4];
+    read_half.read_exact(&mut len).await?;
+    cipher.apply_keystream(&mut len);
+
+    let len = u32::from_le_bytes(len) as usize;
+    if !(64..=MAX_PACKET_LEN).contains(&len) {
+        return Err(AdnlError::Protocol(format!("Invalid packet length {}", len)));
+    }
+
+    let mut body = vec![0u8; len];
+    read_half.read_exact(&mut body).await?;
+    cipher.apply_keystream(&mut body);
+
+    Ok(crypto::decode_packet_body(&body)?.to_vec())
+}
+
+async fn read_loop(
+    mut read_half: OwnedReadHalf,
+    mut cipher: Aes256Ctr,
+    pending: PendingQueries,
+    pending_pings: PendingPings,
+    closed: Arc<AtomicBool>,
+) {
+    loop {
+        let payload = match read_packet(&mut read_half, &mut cipher).await {
+            Ok(payload) => payload,
+            Err(e) => {
+                trace!("ADNL read loop finished: {}", e);
+                break;
+            }
+        };
+
+        if payload.is_empty() {
+            continue;
+        }
+
+        if let Err(e) = dispatch(&payload, &pending, &pending_pings) {
+            trace!("ADNL: dropping message: {}", e);
+        }
+    }
+
+    closed.store(true, Ordering::Relaxed);
+    // Dropping the senders wakes every waiter with a closed error
+    pending.lock().unwrap().clear();
+    pending_pings.lock().unwrap().clear();
+}
+
+fn dispatch(payload: &[u8], pending: &PendingQueries, pending_pings: &PendingPings) -> AdnlResult<()> {
+    let mut reader = TlReader::new(payload);
+    match reader.u32()? {
+        ADNL_MESSAGE_ANSWER => {
+            let query_id = reader.int256()?;
+            let answer = reader.bytes()?;
+            if let Some(tx) = pending.lock().unwrap().remove(&query_id) {
+                let _ = tx.send(answer);
+            }
+        }
+        TCP_PONG => {
+            let random_id = reader.i64()?;
+            if let Some(tx) = pending_pings.lock().unwrap().remove(&random_id) {
+                let _ = tx.send(());
+            }
+        }
+        other => {
+            return Err(AdnlError::Protocol(format!(
+                "Unknown message constructor {:#010x}",
+                other
+            )))
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+//! ADNL TCP handshake and packet ciphers.
+
+use super::tl::{TlWriter, PUB_ED25519};
+use super::{AdnlError, AdnlResult};
+use aes::Aes256;
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+pub type Aes256Ctr = ctr::Ctr128BE<Aes256>;
+
+/// Size of the handshake packet sent by the client
+pub const HANDSHAKE_LEN: usize = 256;
+
+/// Ciphers for one ADNL TCP session, from the client's point of view
+pub struct SessionCiphers {
+    pub rx: Aes256Ctr,
+    pub tx: Aes256Ctr,
+}
+
+/// ADNL key id of an ed25519 public key: sha256 of its TL `pub.ed25519` form
+pub fn key_id(public_key: &[u8; 32]) -> [u8; 32] {
+    let tl = TlWriter::new().u32(PUB_ED25519).int256(public_key).finish();
+    Sha256::digest(&tl).into()
+}
+
+/// Build the client handshake packet for a server with the given ed25519 public key.
+///
+/// Returns the 256-byte packet and the ciphers for the session:
+/// `key_id(server) || client_pub || sha256(params) || aes_ctr(params)`.
+pub fn client_handshake(server_key: &[u8; 32]) -> AdnlResult<([u8; HANDSHAKE_LEN], SessionCiphers)> {
+    let mut rng = rand::thread_rng();
+
+    let mut aes_params = [0u8; 160];
+    rng.fill_bytes(&mut aes_params);
+
+    // Ephemeral key: the same clamped scalar serves as the ed25519 secret we
+    // announce and as the x25519 secret for the key agreement.
+    let mut scalar = [0u8; 32];
+    rng.fill_bytes(&mut scalar);
+    let client_pub = EdwardsPoint::mul_base_clamped(scalar).compress().to_bytes();
+
+    let server_point = CompressedEdwardsY(*server_key)
+        .decompress()
+        .ok_or_else(|| AdnlError::Handshake("Invalid server public key".to_string()))?;
+    let shared = server_point.to_montgomery().mul_clamped(scalar).to_bytes();
+
+    let params_hash: [u8; 32] = Sha256::digest(aes_params).into();
+
+    let mut key = [0u8; 32];
+    key[..16].copy_from_slice(&shared[..16]);
+    key[16..].copy_from_slice(&params_hash[16..]);
+    let mut iv = [0u8; 16];
+    iv[..4].copy_from_slice(&params_hash[..4]);
+    iv[4..].copy_from_slice(&shared[20..]);
+
+    let mut encrypted_params = aes_params;
+    Aes256Ctr::new(&key.into(), &iv.into()).apply_keystream(&mut encrypted_params);
+
+    let mut packet = [0u8; HANDSHAKE_LEN];
+    packet[..32].copy_from_slice(&key_id(server_key));
+    packet[32..64].copy_from_slice(&client_pub);
+    packet[64..96].copy_from_slice(&params_hash);
+    packet[96..].copy_from_slice(&encrypted_params);
+
+    Ok((packet, session_ciphers(&aes_params)))
+}
+
+fn session_ciphers(aes_params: &[u8; 160]) -> SessionCiphers {
+    let rx_key: [u8; 32] = aes_params[0..32].try_into().unwrap();
+    let tx_key: [u8; 32] = aes_params[32..64].try_into().unwrap();
+    let rx_nonce: [u8; 16] = aes_params[64..80].try_into().unwrap();
+    let tx_nonce: [u8; 16] = aes_params[80..96].try_into().unwrap();
+
+    SessionCiphers {
+        rx: Aes256Ctr::new(&rx_key.into(), &rx_nonce.into()),
+        tx: Aes256Ctr::new(&tx_key.into(), &tx_nonce.into()),
+    }
+}
+
+/// Frame and encrypt a payload: `len || nonce || payload || sha256(nonce || payload)`
+pub fn encode_packet(payload: &[u8], cipher: &mut Aes256Ctr) -> Vec<u8> {
+    let mut nonce = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let mut hasher = Sha256::new();
+    hasher.update(nonce);
+    hasher.update(payload);
+    let checksum: [u8; 32] = hasher.finalize().into();
+
+    let len = (nonce.len() + payload.len() + checksum.len()) as u32;
+    let mut packet = Vec::with_capacity(4 + len as usize);
+    packet.extend_from_slice(&len.to_le_bytes());
+    packet.extend_from_slice(&nonce);
+    packet.extend_from_slice(payload);
+    packet.extend_from_slice(&checksum);
+
+    cipher.apply_keystream(&mut packet);
+    packet
+}
+
+/// Verify a decrypted packet body (`nonce || payload || checksum`) and return the payload
+pub fn decode_packet_body(body: &[u8]) -> AdnlResult<&[u8]> {
+    if body.len() < 64 {
+        return Err(AdnlError::Protocol("Packet too short".to_string()));
+    }
+
+    let (nonce_and_payload, checksum) = body.split_at(body.len() - 32);
+    let expected: [u8; 32] = Sha256::digest(nonce_and_payload).into();
+    if expected != checksum {
+        return Err(AdnlError::Protocol("Packet checksum mismatch".to_string()));
+    }
+
+    Ok(&nonce_and_payload[32..])
+}
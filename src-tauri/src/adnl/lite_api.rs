@@ -0,0 +1,159 @@
+//! Typed wrappers for the liteServer functions used by the app.
+
+use super::tl::{TlReader, TlWriter};
+use super::{AdnlResult, LiteConnection};
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Serialize, Serializer};
+
+const GET_MASTERCHAIN_INFO: u32 = 0x89b5e62e;
+const MASTERCHAIN_INFO: u32 = 0x85832881;
+const GET_ACCOUNT_STATE: u32 = 0x6b890e25;
+const ACCOUNT_STATE: u32 = 0x7079c751;
+const SEND_MESSAGE: u32 = 0x690ad482;
+const SEND_MSG_STATUS: u32 = 0x3950e597;
+const GET_TIME: u32 = 0x16ad5a34;
+const CURRENT_TIME: u32 = 0xe953000d;
+
+/// `tonNode.blockIdExt`
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockIdExt {
+    pub workchain: i32,
+    #[serde(serialize_with = "as_string")]
+    pub shard: i64,
+    pub seqno: i32,
+    #[serde(serialize_with = "as_hex")]
+    pub root_hash: [u8; 32],
+    #[serde(serialize_with = "as_hex")]
+    pub file_hash: [u8; 32],
+}
+
+/// `tonNode.zeroStateIdExt`
+#[derive(Debug, Clone, Serialize)]
+pub struct ZeroStateIdExt {
+    pub workchain: i32,
+    #[serde(serialize_with = "as_hex")]
+    pub root_hash: [u8; 32],
+    #[serde(serialize_with = "as_hex")]
+    pub file_hash: [u8; 32],
+}
+
+/// `liteServer.masterchainInfo`
+#[derive(Debug, Clone, Serialize)]
+pub struct MasterchainInfo {
+    pub last: BlockIdExt,
+    #[serde(serialize_with = "as_hex")]
+    pub state_root_hash: [u8; 32],
+    pub init: ZeroStateIdExt,
+}
+
+/// `liteServer.accountState`. Proofs and state are BoCs, serialized as base64.
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountState {
+    pub id: BlockIdExt,
+    pub shardblk: BlockIdExt,
+    #[serde(serialize_with = "as_base64")]
+    pub shard_proof: Vec<u8>,
+    #[serde(serialize_with = "as_base64")]
+    pub proof: Vec<u8>,
+    #[serde(serialize_with = "as_base64")]
+    pub state: Vec<u8>,
+}
+
+impl BlockIdExt {
+    fn read(reader: &mut TlReader) -> AdnlResult<Self> {
+        Ok(Self {
+            workchain: reader.i32()?,
+            shard: reader.i64()?,
+            seqno: reader.i32()?,
+            root_hash: reader.int256()?,
+            file_hash: reader.int256()?,
+        })
+    }
+
+    fn write(&self, writer: &mut TlWriter) {
+        writer
+            .i32(self.workchain)
+            .i64(self.shard)
+            .i32(self.seqno)
+            .int256(&self.root_hash)
+            .int256(&self.file_hash);
+    }
+}
+
+impl ZeroStateIdExt {
+    fn read(reader: &mut TlReader) -> AdnlResult<Self> {
+        Ok(Self {
+            workchain: reader.i32()?,
+            root_hash: reader.int256()?,
+            file_hash: reader.int256()?,
+        })
+    }
+}
+
+pub async fn get_masterchain_info(conn: &LiteConnection) -> AdnlResult<MasterchainInfo> {
+    let request = TlWriter::new().u32(GET_MASTERCHAIN_INFO).finish();
+    let answer = conn.query(&request).await?;
+
+    let mut reader = TlReader::new(&answer);
+    reader.expect(MASTERCHAIN_INFO)?;
+    Ok(MasterchainInfo {
+        last: BlockIdExt::read(&mut reader)?,
+        state_root_hash: reader.int256()?,
+        init: ZeroStateIdExt::read(&mut reader)?,
+    })
+}
+
+pub async fn get_account_state(
+    conn: &LiteConnection,
+    block: &BlockIdExt,
+    workchain: i32,
+    account: &[u8; 32],
+) -> AdnlResult<AccountState> {
+    let mut writer = TlWriter::new();
+    writer.u32(GET_ACCOUNT_STATE);
+    block.write(&mut writer);
+    writer.i32(workchain).int256(account);
+    let answer = conn.query(&writer.finish()).await?;
+
+    let mut reader = TlReader::new(&answer);
+    reader.expect(ACCOUNT_STATE)?;
+    Ok(AccountState {
+        id: BlockIdExt::read(&mut reader)?,
+        shardblk: BlockIdExt::read(&mut reader)?,
+        shard_proof: reader.bytes()?,
+        proof: reader.bytes()?,
+        state: reader.bytes()?,
+    })
+}
+
+/// Send an external message BoC. Returns the liteserver status code.
+pub async fn send_message(conn: &LiteConnection, boc: &[u8]) -> AdnlResult<i32> {
+    let request = TlWriter::new().u32(SEND_MESSAGE).bytes(boc).finish();
+    let answer = conn.query(&request).await?;
+
+    let mut reader = TlReader::new(&answer);
+    reader.expect(SEND_MSG_STATUS)?;
+    reader.i32()
+}
+
+/// Get the liteserver's current unix time
+pub async fn get_time(conn: &LiteConnection) -> AdnlResult<i32> {
+    let request = TlWriter::new().u32(GET_TIME).finish();
+    let answer = conn.query(&request).await?;
+
+    let mut reader = TlReader::new(&answer);
+    reader.expect(CURRENT_TIME)?;
+    reader.i32()
+}
+
+fn as_string<S: Serializer>(v: &i64, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&v.to_string())
+}
+
+fn as_hex<S: Serializer>(v: &[u8; 32], s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&v.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+}
+
+fn as_base64<S: Serializer>(v: &[u8], s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&general_purpose::STANDARD.encode(v))
+}
@@ -0,0 +1,54 @@
+//! Native ADNL-over-TCP client for liteservers.
+//!
+//! Implements the ADNL TCP handshake (x25519 key agreement with the server's
+//! ed25519 key), AES-256-CTR packet framing and the subset of the liteServer
+//! TL schema the app needs, so lite queries can run without the webview.
+
+mod client;
+mod crypto;
+pub mod lite_api;
+#[cfg(test)]
+mod tests;
+pub mod tl;
+
+pub use client::LiteConnection;
+
+use std::error::Error;
+use std::fmt;
+
+/// Result type for ADNL operations
+pub type AdnlResult<T> = Result<T, AdnlError>;
+
+/// Errors that can occur while talking to a liteserver
+#[derive(Debug, Clone)]
+pub enum AdnlError {
+    Io(String),
+    Handshake(String),
+    Protocol(String),
+    LiteServer { code: i32, message: String },
+    Timeout,
+    Closed,
+}
+
+impl fmt::Display for AdnlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdnlError::Io(msg) => write!(f, "IO error: {}", msg),
+            AdnlError::Handshake(msg) => write!(f, "Handshake failed: {}", msg),
+            AdnlError::Protocol(msg) => write!(f, "Protocol error: {}", msg),
+            AdnlError::LiteServer { code, message } => {
+                write!(f, "Liteserver error {}: {}", code, message)
+            }
+            AdnlError::Timeout => write!(f, "Request timed out"),
+            AdnlError::Closed => write!(f, "Connection closed"),
+        }
+    }
+}
+
+impl Error for AdnlError {}
+
+impl From<std::io::Error> for AdnlError {
+    fn from(e: std::io::Error) -> Self {
+        AdnlError::Io(e.to_string())
+    }
+}
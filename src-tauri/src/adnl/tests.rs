@@ -0,0 +1,342 @@
+//! The client against an in-process liteserver speaking ADNL over TCP.
+
+use super::crypto::{self, Aes256Ctr, HANDSHAKE_LEN};
+use super::lite_api::{self, BlockIdExt};
+use super::tl::{
+    TlReader, TlWriter, ADNL_MESSAGE_ANSWER, ADNL_MESSAGE_QUERY, LITE_SERVER_ERROR,
+    LITE_SERVER_QUERY, TCP_PING, TCP_PONG,
+};
+use super::{AdnlError, LiteConnection};
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const GET_MASTERCHAIN_INFO: u32 = 0x89b5e62e;
+const MASTERCHAIN_INFO: u32 = 0x85832881;
+const GET_ACCOUNT_STATE: u32 = 0x6b890e25;
+const ACCOUNT_STATE: u32 = 0x7079c751;
+const SEND_MESSAGE: u32 = 0x690ad482;
+const SEND_MSG_STATUS: u32 = 0x3950e597;
+
+/// Large enough to need the long TL `bytes` prefix and several reads
+const LARGE_STATE_LEN: usize = 300_000;
+
+struct MockLiteserver {
+    addr: SocketAddr,
+    public_key: [u8; 32],
+    /// Unwrapped liteServer requests in the order they arrived
+    requests: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl MockLiteserver {
+    async fn spawn() -> Self {
+        let secret: [u8; 32] = rand::random();
+        let public_key = EdwardsPoint::mul_base_clamped(secret).compress().to_bytes();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, secret, public_key, seen.clone()));
+            }
+        });
+
+        Self {
+            addr,
+            public_key,
+            requests,
+        }
+    }
+
+    async fn connect(&self) -> LiteConnection {
+        LiteConnection::connect(self.addr, &self.public_key)
+            .await
+            .unwrap()
+    }
+}
+
+async fn serve(
+    mut stream: TcpStream,
+    secret: [u8; 32],
+    public_key: [u8; 32],
+    requests: Arc<Mutex<Vec<Vec<u8>>>>,
+) {
+    let mut handshake = [0u8; HANDSHAKE_LEN];
+    if stream.read_exact(&mut handshake).await.is_err() {
+        return;
+    }
+    // A handshake for another key is dropped, as a real liteserver does
+    if handshake[..32] != crypto::key_id(&public_key) {
+        return;
+    }
+
+    let client_pub = CompressedEdwardsY(handshake[32..64].try_into().unwrap())
+        .decompress()
+        .unwrap();
+    let shared = client_pub.to_montgomery().mul_clamped(secret).to_bytes();
+    let params_hash = &handshake[64..96];
+
+    let mut key = [0u8; 32];
+    key[..16].copy_from_slice(&shared[..16]);
+    key[16..].copy_from_slice(&params_hash[16..]);
+    let mut iv = [0u8; 16];
+    iv[..4].copy_from_slice(&params_hash[..4]);
+    iv[4..].copy_from_slice(&shared[20..]);
+    let mut params: [u8; 160] = handshake[96..].try_into().unwrap();
+    Aes256Ctr::new(&key.into(), &iv.into()).apply_keystream(&mut params);
+    assert_eq!(Sha256::digest(params).as_slice(), params_hash);
+
+    // The server's directions are the client's swapped
+    let cipher = |key: &[u8], nonce: &[u8]| {
+        let key: [u8; 32] = key.try_into().unwrap();
+        let nonce: [u8; 16] = nonce.try_into().unwrap();
+        Aes256Ctr::new(&key.into(), &nonce.into())
+    };
+    let mut rx = cipher(&params[32..64], &params[80..96]);
+    let mut tx = cipher(&params[0..32], &params[64..80]);
+
+    let confirmation = crypto::encode_packet(&[], &mut tx);
+    if stream.write_all(&confirmation).await.is_err() {
+        return;
+    }
+
+    loop {
+        let mut len = [0u8; 4];
+        if stream.read_exact(&mut len).await.is_err() {
+            return;
+        }
+        rx.apply_keystream(&mut len);
+        let mut body = vec![0u8; u32::from_le_bytes(len) as usize];
+        if stream.read_exact(&mut body).await.is_err() {
+            return;
+        }
+        rx.apply_keystream(&mut body);
+        let payload = crypto::decode_packet_body(&body).unwrap();
+
+        let mut reader = TlReader::new(payload);
+        let reply = match reader.u32().unwrap() {
+            TCP_PING => TlWriter::new()
+                .u32(TCP_PONG)
+                .i64(reader.i64().unwrap())
+                .finish(),
+            ADNL_MESSAGE_QUERY => {
+                let query_id = reader.int256().unwrap();
+                let query = reader.bytes().unwrap();
+                let mut query = TlReader::new(&query);
+                query.expect(LITE_SERVER_QUERY).unwrap();
+                let request = query.bytes().unwrap();
+                let answer = answer(&request);
+                requests.lock().unwrap().push(request);
+                TlWriter::new()
+                    .u32(ADNL_MESSAGE_ANSWER)
+                    .int256(&query_id)
+                    .bytes(&answer)
+                    .finish()
+            }
+            other => panic!("unexpected message {:#010x}", other),
+        };
+        if stream
+            .write_all(&crypto::encode_packet(&reply, &mut tx))
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+fn block(seqno: i32) -> BlockIdExt {
+    BlockIdExt {
+        workchain: -1,
+        shard: i64::MIN,
+        seqno,
+        root_hash: [seqno as u8; 32],
+        file_hash: [0xee; 32],
+    }
+}
+
+fn write_block(writer: &mut TlWriter, block: &BlockIdExt) {
+    writer
+        .i32(block.workchain)
+        .i64(block.shard)
+        .i32(block.seqno)
+        .int256(&block.root_hash)
+        .int256(&block.file_hash);
+}
+
+fn answer(request: &[u8]) -> Vec<u8> {
+    let mut reader = TlReader::new(request);
+    let mut writer = TlWriter::new();
+    match reader.u32().unwrap() {
+        GET_MASTERCHAIN_INFO => {
+            writer.u32(MASTERCHAIN_INFO);
+            write_block(&mut writer, &block(42));
+            writer
+                .int256(&[0x5a; 32])
+                .i32(-1)
+                .int256(&[1; 32])
+                .int256(&[2; 32]);
+        }
+        GET_ACCOUNT_STATE => {
+            // Echo the account back in the proof, so the test can see it arrived
+            skip_block(&mut reader);
+            let workchain = reader.i32().unwrap();
+            let account = reader.int256().unwrap();
+            let mut proof = workchain.to_le_bytes().to_vec();
+            proof.extend_from_slice(&account);
+
+            writer.u32(ACCOUNT_STATE);
+            write_block(&mut writer, &block(42));
+            write_block(&mut writer, &block(43));
+            writer
+                .bytes(b"shard proof")
+                .bytes(&proof)
+                .bytes(&[0xab; LARGE_STATE_LEN]);
+        }
+        SEND_MESSAGE => {
+            let boc = reader.bytes().unwrap();
+            if boc.is_empty() {
+                writer
+                    .u32(LITE_SERVER_ERROR)
+                    .i32(400)
+                    .bytes(b"cannot deserialize bag-of-cells");
+            } else {
+                writer.u32(SEND_MSG_STATUS).i32(1);
+            }
+        }
+        other => {
+            writer
+                .u32(LITE_SERVER_ERROR)
+                .i32(-400)
+                .bytes(format!("unknown query {:#010x}", other).as_bytes());
+        }
+    }
+    writer.finish()
+}
+
+fn skip_block(reader: &mut TlReader) {
+    reader.i32().unwrap();
+    reader.i64().unwrap();
+    reader.i32().unwrap();
+    reader.int256().unwrap();
+    reader.int256().unwrap();
+}
+
+#[tokio::test]
+async fn handshake_and_ping() {
+    let server = MockLiteserver::spawn().await;
+    let conn = server.connect().await;
+
+    assert_eq!(conn.addr(), server.addr);
+    conn.ping().await.unwrap();
+    assert!(!conn.is_closed());
+}
+
+#[tokio::test]
+async fn handshake_with_wrong_key_fails() {
+    let server = MockLiteserver::spawn().await;
+    let other_key = EdwardsPoint::mul_base_clamped(rand::random())
+        .compress()
+        .to_bytes();
+
+    match LiteConnection::connect(server.addr, &other_key).await {
+        Err(AdnlError::Handshake(_)) => {}
+        Err(e) => panic!("expected a handshake error, got {}", e),
+        Ok(_) => panic!("handshake with the wrong key succeeded"),
+    }
+}
+
+#[tokio::test]
+async fn get_masterchain_info() {
+    let server = MockLiteserver::spawn().await;
+    let conn = server.connect().await;
+
+    let info = lite_api::get_masterchain_info(&conn).await.unwrap();
+    assert_eq!(info.last.workchain, -1);
+    assert_eq!(info.last.shard, i64::MIN);
+    assert_eq!(info.last.seqno, 42);
+    assert_eq!(info.last.root_hash, [42; 32]);
+    assert_eq!(info.state_root_hash, [0x5a; 32]);
+    assert_eq!(info.init.root_hash, [1; 32]);
+    assert_eq!(info.init.file_hash, [2; 32]);
+}
+
+#[tokio::test]
+async fn get_account_state_with_a_large_answer() {
+    let server = MockLiteserver::spawn().await;
+    let conn = server.connect().await;
+
+    let account = [0x11; 32];
+    let state = lite_api::get_account_state(&conn, &block(42), 0, &account)
+        .await
+        .unwrap();
+    assert_eq!(state.id.seqno, 42);
+    assert_eq!(state.shardblk.seqno, 43);
+    assert_eq!(state.shard_proof, b"shard proof");
+    assert_eq!(state.proof[..4], 0i32.to_le_bytes());
+    assert_eq!(state.proof[4..], account);
+    assert_eq!(state.state.len(), LARGE_STATE_LEN);
+    assert!(state.state.iter().all(|&b| b == 0xab));
+
+    // The block the state was asked at went out as given
+    let requests = server.requests.lock().unwrap();
+    let mut reader = TlReader::new(&requests[0]);
+    assert_eq!(reader.u32().unwrap(), GET_ACCOUNT_STATE);
+    assert_eq!(reader.i32().unwrap(), -1);
+    assert_eq!(reader.i64().unwrap(), i64::MIN);
+    assert_eq!(reader.i32().unwrap(), 42);
+}
+
+#[tokio::test]
+async fn send_message() {
+    let server = MockLiteserver::spawn().await;
+    let conn = server.connect().await;
+
+    let boc = vec![0xb5; 1000];
+    assert_eq!(lite_api::send_message(&conn, &boc).await.unwrap(), 1);
+
+    let requests = server.requests.lock().unwrap();
+    let mut reader = TlReader::new(&requests[0]);
+    reader.expect(SEND_MESSAGE).unwrap();
+    assert_eq!(reader.bytes().unwrap(), boc);
+}
+
+#[tokio::test]
+async fn liteserver_errors_are_typed() {
+    let server = MockLiteserver::spawn().await;
+    let conn = server.connect().await;
+
+    match lite_api::send_message(&conn, &[]).await {
+        Err(AdnlError::LiteServer { code, message }) => {
+            assert_eq!(code, 400);
+            assert_eq!(message, "cannot deserialize bag-of-cells");
+        }
+        other => panic!("expected a liteserver error, got {:?}", other),
+    }
+    // The connection stays usable
+    lite_api::get_masterchain_info(&conn).await.unwrap();
+}
+
+#[tokio::test]
+async fn concurrent_queries_get_their_own_answers() {
+    let server = MockLiteserver::spawn().await;
+    let conn = server.connect().await;
+
+    let account = [0x22; 32];
+    let at = block(7);
+    let (info, state, status) = tokio::join!(
+        lite_api::get_masterchain_info(&conn),
+        lite_api::get_account_state(&conn, &at, -1, &account),
+        lite_api::send_message(&conn, b"boc"),
+    );
+    assert_eq!(info.unwrap().last.seqno, 42);
+    assert_eq!(state.unwrap().proof[4..], account);
+    assert_eq!(status.unwrap(), 1);
+    assert_eq!(server.requests.lock().unwrap().len(), 3);
+}
@@ -0,0 +1,132 @@
+//! Minimal TL (Type Language) serialization used by ADNL and the liteServer API.
+//!
+//! Constructor ids are the CRC32 of the schema line, written little-endian.
+
+use super::{AdnlError, AdnlResult};
+
+pub const TCP_PING: u32 = 0x4d082b9a;
+pub const TCP_PONG: u32 = 0xdc69fb03;
+pub const PUB_ED25519: u32 = 0x4813b4c6;
+pub const ADNL_MESSAGE_QUERY: u32 = 0xb48bf97a;
+pub const ADNL_MESSAGE_ANSWER: u32 = 0x0fac8416;
+pub const LITE_SERVER_QUERY: u32 = 0x798c06df;
+pub const LITE_SERVER_ERROR: u32 = 0xbba9e148;
+
+#[derive(Default)]
+pub struct TlWriter {
+    buf: Vec<u8>,
+}
+
+impl TlWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn u32(&mut self, v: u32) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    pub fn i32(&mut self, v: i32) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    pub fn i64(&mut self, v: i64) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    pub fn int256(&mut self, v: &[u8; 32]) -> &mut Self {
+        self.buf.extend_from_slice(v);
+        self
+    }
+
+    /// Write a TL `bytes` value: short or long length prefix, data, padding to 4 bytes
+    pub fn bytes(&mut self, v: &[u8]) -> &mut Self {
+        let header_len = if v.len() < 254 {
+            self.buf.push(v.len() as u8);
+            1
+        } else {
+            self.buf.push(254);
+            self.buf.extend_from_slice(&(v.len() as u32).to_le_bytes()[..3]);
+            4
+        };
+        self.buf.extend_from_slice(v);
+
+        let padding = (4 - (header_len + v.len()) % 4) % 4;
+        self.buf.extend(std::iter::repeat_n(0, padding));
+        self
+    }
+
+    pub fn finish(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buf)
+    }
+}
+
+pub struct TlReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> TlReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> AdnlResult<&'a [u8]> {
+        if self.data.len() - self.pos < n {
+            return Err(AdnlError::Protocol("Unexpected end of TL data".to_string()));
+        }
+        let slice = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(slice)
+    }
+
+    pub fn u32(&mut self) -> AdnlResult<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn i32(&mut self) -> AdnlResult<i32> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn i64(&mut self) -> AdnlResult<i64> {
+        Ok(i64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn int256(&mut self) -> AdnlResult<[u8; 32]> {
+        Ok(self.take(32)?.try_into().unwrap())
+    }
+
+    pub fn bytes(&mut self) -> AdnlResult<Vec<u8>> {
+        let first = self.take(1)?[0];
+        let (len, header_len) = if first < 254 {
+            (first as usize, 1)
+        } else {
+            let len = self.take(3)?;
+            (u32::from_le_bytes([len[0], len[1], len[2], 0]) as usize, 4)
+        };
+        let value = self.take(len)?.to_vec();
+
+        let padding = (4 - (header_len + len) % 4) % 4;
+        self.take(padding)?;
+        Ok(value)
+    }
+
+    pub fn string(&mut self) -> AdnlResult<String> {
+        Ok(String::from_utf8_lossy(&self.bytes()?).into_owned())
+    }
+
+    /// Read a constructor id and fail unless it matches `expected`
+    pub fn expect(&mut self, expected: u32) -> AdnlResult<()> {
+        let id = self.u32()?;
+        if id != expected {
+            return Err(AdnlError::Protocol(format!(
+                "Unexpected TL constructor {:#010x}, expected {:#010x}",
+                id, expected
+            )));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+use rusqlite::types::Value;
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Shared connection to the app database (`databases/data.db`).
+///
+/// The webview keeps using tauri-plugin-sql for its own queries; this handle is
+/// for Rust-side reads and writes. Both sides go through SQLite's file locking,
+/// so a busy timeout is set to ride out short write contention.
+#[derive(Clone)]
+pub struct Database {
+    conn: Arc<Mutex<Connection>>,
+}
+
+/// Mainnet chain ID
+pub const MAINNET_CHAIN_ID: i64 = -239;
+/// Testnet chain ID
+pub const TESTNET_CHAIN_ID: i64 = -3;
+
+/// Row from the `networks` table
+#[derive(Debug, Clone, Serialize)]
+pub struct NetworkRow {
+    pub network_id: i64,
+    pub name: String,
+    pub url: String,
+    pub is_testnet: bool,
+    pub chain_id: Option<i64>,
+}
+
+impl NetworkRow {
+    /// Chain ID of the network: `chain_id` when set, else the default for
+    /// mainnet or testnet, matching `getNetworkChainId` in the webview
+    pub fn effective_chain_id(&self) -> i64 {
+        match self.chain_id {
+            Some(chain_id) => chain_id,
+            None if self.is_testnet => TESTNET_CHAIN_ID,
+            None => MAINNET_CHAIN_ID,
+        }
+    }
+}
+
+impl Database {
+    /// Open the database at the given path. Migrations must already be applied.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let conn =
+            Connection::open(path).map_err(|e| format!("Failed to open database: {}", e))?;
+        conn.busy_timeout(Duration::from_secs(5))
+            .map_err(|e| format!("Failed to set busy timeout: {}", e))?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Run a closure with exclusive access to the connection
+    pub fn with_conn<T>(
+        &self,
+        f: impl FnOnce(&mut Connection) -> rusqlite::Result<T>,
+    ) -> Result<T, String> {
+        let mut conn = self
+            .conn
+            .lock()
+            .map_err(|_| "Database connection lock poisoned".to_string())?;
+        f(&mut conn).map_err(|e| e.to_string())
+    }
+
+    /// Read a value from the `settings` table.
+    /// The webview stores some settings as numbers, so any scalar is returned as text.
+    pub fn get_setting(&self, name: &str) -> Result<Option<String>, String> {
+        let value: Option<Value> = self.with_conn(|conn| {
+            conn.query_row(
+                "SELECT value FROM settings WHERE name = ?1",
+                [name],
+                |row| row.get(0),
+            )
+            .optional()
+        })?;
+
+        Ok(match value {
+            Some(Value::Text(s)) => Some(s),
+            Some(Value::Integer(i)) => Some(i.to_string()),
+            Some(Value::Real(f)) => Some(f.to_string()),
+            Some(Value::Blob(b)) => Some(String::from_utf8_lossy(&b).into_owned()),
+            Some(Value::Null) | None => None,
+        })
+    }
+
+    /// Insert or replace a value in the `settings` table
+    pub fn set_setting(&self, name: &str, value: &str) -> Result<(), String> {
+        self.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO settings (name, value) VALUES (?1, ?2)
+                 ON CONFLICT(name) DO UPDATE SET value = excluded.value",
+                [name, value],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Read a setting that can be overridden per network.
+    /// Looks up `<name>:<network_id>` for the selected network, then `<name>`.
+    pub fn get_network_setting(&self, name: &str) -> Result<Option<String>, String> {
+        if let Some(network) = self.selected_network()? {
+            let scoped = format!("{}:{}", name, network.network_id);
+            if let Some(value) = self.get_setting(&scoped)? {
+                return Ok(Some(value));
+            }
+        }
+        self.get_setting(name)
+    }
+
+    /// Get a network by id
+    pub fn get_network(&self, network_id: i64) -> Result<Option<NetworkRow>, String> {
+        self.with_conn(|conn| {
+            conn.query_row(
+                "SELECT network_id, name, url, is_testnet, chain_id
+                 FROM networks WHERE network_id = ?1",
+                [network_id],
+                network_from_row,
+            )
+            .optional()
+        })
+    }
+
+    /// Get every network on the given chain
+    pub fn networks_by_chain_id(&self, chain_id: i64) -> Result<Vec<NetworkRow>, String> {
+        let networks = self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT network_id, name, url, is_testnet, chain_id
+                 FROM networks ORDER BY network_id",
+            )?;
+            let rows = stmt.query_map([], network_from_row)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })?;
+
+        Ok(networks
+            .into_iter()
+            .filter(|network| network.effective_chain_id() == chain_id)
+            .collect())
+    }
+
+    /// Get the network currently selected in the UI.
+    /// Falls back to the first network, matching what the webview does.
+    pub fn selected_network(&self) -> Result<Option<NetworkRow>, String> {
+        let selected_id = self
+            .get_setting("selected_network")?
+            .and_then(|v| v.parse::<i64>().ok());
+
+        if let Some(id) = selected_id {
+            if let Some(network) = self.get_network(id)? {
+                return Ok(Some(network));
+            }
+        }
+
+        self.with_conn(|conn| {
+            conn.query_row(
+                "SELECT network_id, name, url, is_testnet, chain_id
+                 FROM networks ORDER BY network_id LIMIT 1",
+                [],
+                network_from_row,
+            )
+            .optional()
+        })
+    }
+}
+
+fn network_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<NetworkRow> {
+    Ok(NetworkRow {
+        network_id: row.get(0)?,
+        name: row.get(1)?,
+        url: row.get(2)?,
+        is_testnet: row.get(3)?,
+        chain_id: row.get(4)?,
+    })
+}
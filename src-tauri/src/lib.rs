@@ -3,33 +3,39 @@
     windows_subsystem = "windows"
 )]
 
-use rxing::multi::MultipleBarcodeReader;
-
 #[cfg(target_os = "macos")]
 #[macro_use]
 extern crate objc;
 
-#[cfg(any(target_os = "macos", windows, target_os = "linux"))]
-use screenshots::Screen;
-
+mod adnl;
+mod db;
+mod lite_commands;
 mod migration_commands;
 pub mod migrations;
 mod proxy;
+mod qr;
+mod server_manager;
+mod ton_address;
 mod ton_echo;
+mod tonconnect_link;
+mod util;
 
+use db::Database;
+use lite_commands::LiteClients;
 use migration_commands::run_migrations_on_db;
-use proxy::spawn_proxy;
-use ton_echo::{get_ton_echo_port, start_ton_echo_server};
-
-use image::{self};
-use rxing;
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicU16, Ordering};
+use proxy::{
+    session_secret, CaptureStore, GlobalConfigCache, LiteServerPool, MuxHub, ProxyContext,
+    ProxyPolicy, ProxyStats,
+};
+use server_manager::{ServerKind, ServerManager};
+use ton_echo::{
+    Discovery, EchoClients, EchoContext, EchoEvents, EchoStats, EchoTopics, PendingReplies,
+    TraceDumps,
+};
+
+use std::sync::Arc;
 use sysinfo::{System, SystemExt};
-use tauri::{Emitter, Manager};
-use tokio::net::TcpListener;
-
-static PORT: AtomicU16 = AtomicU16::new(0);
+use tauri::{Emitter, Manager, RunEvent};
 
 #[derive(Clone, serde::Serialize)]
 struct Payload {
@@ -37,6 +43,28 @@ struct Payload {
     cwd: String,
 }
 
+/// A link the app was opened with, sent to the webview as `open_url`
+#[derive(Clone, serde::Serialize)]
+struct OpenUrl {
+    url: String,
+    /// Set when the link is a valid TonConnect connect link
+    connect: Option<tonconnect_link::ConnectRequest>,
+    /// Why a connect link could not be parsed
+    error: Option<String>,
+}
+
+fn emit_open_url(app: &tauri::AppHandle, url: &str) {
+    let url = url.strip_prefix("--url=").unwrap_or(url).to_string();
+    let (connect, error) = match tonconnect_link::parse(&url) {
+        Ok(request) => (Some(request), None),
+        Err(e) if e.is_not_a_connect_link() => (None, None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    if let Err(e) = app.emit("open_url", OpenUrl { url, connect, error }) {
+        log::warn!("Error emitting open_url: {:?}", e);
+    }
+}
+
 pub fn is_win_11() -> bool {
     let sys = System::new_all();
     let version = sys.os_version().unwrap();
@@ -49,73 +77,6 @@ pub fn is_win_11() -> bool {
     version >= 22000
 }
 
-#[tauri::command]
-async fn detect_qr_code() -> Result<Vec<String>, String> {
-    let screens: Vec<Screen> = Screen::all().unwrap();
-
-    let mut images: Vec<String> = Vec::new(); // = vec![String];
-    for screen in screens {
-        let captured = screen.capture().unwrap();
-        let mut png = captured.to_png().unwrap();
-        let i = image::load_from_memory_with_format(&mut png, image::ImageFormat::Png).unwrap();
-        let multi_format_reader = rxing::MultiUseMultiFormatReader::default();
-        let mut scanner = rxing::multi::GenericMultipleBarcodeReader::new(multi_format_reader);
-        let mut hints = HashMap::new();
-
-        hints
-            .entry(rxing::DecodeHintType::TRY_HARDER)
-            .or_insert(rxing::DecodeHintValue::TryHarder(true));
-
-        let results = scanner
-            .decode_multiple_with_hints(
-                &mut rxing::BinaryBitmap::new(rxing::common::HybridBinarizer::new(
-                    rxing::BufferedImageLuminanceSource::new(i),
-                )),
-                &mut hints,
-            )
-            .unwrap_or_default();
-
-        if results.len() > 0 {
-            images.push(results[0].getText().to_string());
-            break;
-        }
-    }
-
-    Ok(images)
-}
-
-use base64::{engine::general_purpose, Engine as _};
-
-#[tauri::command]
-async fn detect_qr_code_from_image(data: String) -> Result<Vec<String>, String> {
-    let mut images: Vec<String> = Vec::new(); // = vec![String];
-
-    let mut image_data = general_purpose::STANDARD.decode(data).unwrap();
-    let i = image::load_from_memory_with_format(&mut image_data, image::ImageFormat::Png).unwrap();
-    let multi_format_reader = rxing::MultiUseMultiFormatReader::default();
-    let mut scanner = rxing::multi::GenericMultipleBarcodeReader::new(multi_format_reader);
-    let mut hints = HashMap::new();
-
-    hints
-        .entry(rxing::DecodeHintType::TRY_HARDER)
-        .or_insert(rxing::DecodeHintValue::TryHarder(true));
-
-    let results = scanner
-        .decode_multiple_with_hints(
-            &mut rxing::BinaryBitmap::new(rxing::common::HybridBinarizer::new(
-                rxing::BufferedImageLuminanceSource::new(i),
-            )),
-            &mut hints,
-        )
-        .unwrap_or_default();
-
-    if results.len() > 0 {
-        images.push(results[0].getText().to_string());
-    }
-
-    Ok(images)
-}
-
 // #[cfg(not(target_os = "windows"))]
 #[tauri::command]
 fn get_os_name() -> Result<String, String> {
@@ -140,13 +101,18 @@ fn get_os_name() -> Result<String, String> {
 }
 
 #[tauri::command]
-fn get_ws_port() -> String {
-    return PORT.load(Ordering::Relaxed).to_string();
+fn get_ws_port(manager: tauri::State<'_, Arc<ServerManager>>) -> String {
+    return manager.port(ServerKind::Proxy).to_string();
 }
 
 #[tauri::command]
-fn get_ton_echo_ws_port() -> String {
-    return get_ton_echo_port().to_string();
+fn get_ws_auth() -> String {
+    session_secret().to_string()
+}
+
+#[tauri::command]
+fn get_ton_echo_ws_port(manager: tauri::State<'_, Arc<ServerManager>>) -> String {
+    return manager.port(ServerKind::TonEcho).to_string();
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -196,52 +162,156 @@ pub fn run() {
             run_migrations_on_db(db_path.to_str().unwrap())
                 .map_err(|e| format!("Failed to run migrations: {}", e))?;
 
+            let db = Database::open(&db_path)?;
+            app.manage(db.clone());
+
+            let configs = Arc::new(GlobalConfigCache::new());
+            let policy = Arc::new(ProxyPolicy::new(db.clone(), configs.clone()));
+            app.manage(policy.clone());
+            let pool = Arc::new(LiteServerPool::new(db.clone(), configs.clone()));
+            app.manage(pool.clone());
+            app.manage(LiteClients::new(db.clone(), configs));
+
+            let captures = Arc::new(CaptureStore::new(
+                db.clone(),
+                app_data_dir.join("proxy_captures"),
+            ));
+            app.manage(captures.clone());
+
+            let stats = Arc::new(ProxyStats::new());
+            app.manage(stats.clone());
+
+            let proxy_ctx = ProxyContext {
+                db: db.clone(),
+                policy,
+                mux: Arc::new(MuxHub::new(pool.clone())),
+                pool,
+                captures,
+                stats,
+            };
+
             #[cfg(any(windows, target_os = "linux"))]
             {
                 use tauri_plugin_deep_link::DeepLinkExt;
                 app.deep_link().register_all()?;
             }
+            {
+                use tauri_plugin_deep_link::DeepLinkExt;
+                let handle = app.handle().clone();
+                app.deep_link().on_open_url(move |event| {
+                    for url in event.urls() {
+                        emit_open_url(&handle, url.as_str());
+                    }
+                });
+            }
 
             #[cfg(desktop)]
             let _ = app.handle()
                 .plugin(tauri_plugin_updater::Builder::new().build());
 
-            tauri::async_runtime::spawn(async move {
-                let mut lst = TcpListener::bind("127.0.0.1:0").await.unwrap();
-                // port = lst.local_addr().unwrap().port();
-                PORT.store(lst.local_addr().unwrap().port(), Ordering::Relaxed);
-
-                match spawn_proxy(&mut lst).await {
-                    Ok(_) => (),
-                    Err(e) => panic!("Listener Error {}", e),
-                };
-            });
+            let echo_pending = Arc::new(PendingReplies::new());
+            app.manage(echo_pending.clone());
+            let echo_clients = Arc::new(EchoClients::new(db.clone()));
+            app.manage(echo_clients.clone());
+            let trace_dumps = Arc::new(TraceDumps::new(db.clone()));
+            app.manage(trace_dumps.clone());
+            let echo_topics = Arc::new(EchoTopics::new());
+            app.manage(echo_topics.clone());
+            let echo_stats = Arc::new(EchoStats::new());
+            app.manage(echo_stats.clone());
+            let echo_socket = ton_echo::local_socket_path(&app_data_dir);
+            let echo_discovery = Arc::new(Discovery::new(
+                app.path().home_dir().ok(),
+                app.package_info().version.to_string(),
+                echo_socket.clone(),
+            ));
+            let echo_ctx = EchoContext {
+                db: db.clone(),
+                events: EchoEvents::spawn(app.handle().clone()),
+                pending: echo_pending,
+                clients: echo_clients,
+                dumps: trace_dumps,
+                topics: echo_topics,
+                stats: echo_stats,
+                discovery: echo_discovery,
+                socket_path: echo_socket,
+            };
 
             let app_handle = app.handle().clone();
-            // Start TON echo server
+            let servers = Arc::new(ServerManager::new(
+                app_handle.clone(),
+                proxy_ctx.clone(),
+                echo_ctx,
+            ));
+            app.manage(servers.clone());
+
+            // Start the proxy and TON echo servers
             tauri::async_runtime::spawn(async move {
-                match start_ton_echo_server(app_handle).await {
-                    Ok(port) => {
-                        println!("TON echo server started on port {}", port);
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to start TON echo server: {}", e);
-                    }
-                };
+                proxy_ctx.pool.spawn_health_checks();
+                proxy_ctx.stats.spawn_event_stream(app_handle);
+                servers.start_all().await;
             });
 
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             get_ws_port,
+            get_ws_auth,
             get_ton_echo_ws_port,
             get_os_name,
-            detect_qr_code,
-            detect_qr_code_from_image,
+            qr::detect_qr_code,
+            qr::detect_qr_code_from_image,
+            qr::detect_qr_code_from_rgba,
+            qr::detect_qr_code_from_file,
+            qr::target::detect_qr_code_in_region,
+            qr::target::detect_qr_code_in_window,
+            qr::target::detect_qr_code_remembered,
+            qr::target::list_capture_windows,
+            qr::target::list_capture_screens,
+            qr::target::list_qr_targets,
+            qr::target::remember_qr_code,
+            qr::target::forget_qr_target,
+            tonconnect_link::parse_connect_link,
+            proxy::trust_proxy_config,
+            proxy::get_liteserver_health,
+            proxy::get_proxy_stats,
+            proxy::list_proxy_captures,
+            proxy::get_proxy_capture_state,
+            proxy::set_proxy_capture,
+            proxy::set_proxy_replay,
+            proxy::delete_proxy_capture,
+            server_manager::restart_proxy,
+            server_manager::restart_ton_echo,
+            ton_echo::resolve_echo_request,
+            ton_echo::publish_echo_state,
+            ton_echo::get_echo_stats,
+            ton_echo::respond_echo_pairing,
+            ton_echo::list_echo_clients,
+            ton_echo::revoke_echo_client,
+            ton_echo::list_trace_dumps,
+            ton_echo::get_trace_dump,
+            ton_echo::delete_trace_dump,
+            lite_commands::lite_get_masterchain_info,
+            lite_commands::lite_get_account_state,
+            lite_commands::lite_send_message,
         ])
         .build(context)
         .expect("error while running tauri application")
-        .run(|_app_handle, event| match event {
+        .run(|app_handle, event| match event {
+            RunEvent::ExitRequested { api, .. } => {
+                // Close the servers and drain their sessions before exiting
+                let servers = app_handle.state::<Arc<ServerManager>>().inner().clone();
+                if servers.is_stopped() {
+                    return;
+                }
+                api.prevent_exit();
+
+                let app_handle = app_handle.clone();
+                tauri::async_runtime::spawn(async move {
+                    servers.stop_all().await;
+                    app_handle.exit(0);
+                });
+            }
             _ => {}
         });
 }
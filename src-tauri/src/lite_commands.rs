@@ -0,0 +1,118 @@
+use crate::adnl::lite_api::{self, AccountState, MasterchainInfo};
+use crate::adnl::LiteConnection;
+use crate::db::Database;
+use crate::proxy::{dial, GlobalConfigCache, Upstream};
+use crate::ton_address::TonAddress;
+use base64::{engine::general_purpose, Engine as _};
+use log::{info, trace};
+use rand::seq::SliceRandom;
+use std::sync::Arc;
+use tauri::State;
+
+struct ActiveConnection {
+    config_url: String,
+    conn: Arc<LiteConnection>,
+}
+
+/// Native liteserver connection for the selected network, shared by the `lite_*` commands.
+/// Reconnects lazily when the network changes or the connection drops.
+pub struct LiteClients {
+    db: Database,
+    configs: Arc<GlobalConfigCache>,
+    active: tokio::sync::Mutex<Option<ActiveConnection>>,
+}
+
+impl LiteClients {
+    pub fn new(db: Database, configs: Arc<GlobalConfigCache>) -> Self {
+        Self {
+            db,
+            configs,
+            active: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    async fn connection(&self) -> Result<Arc<LiteConnection>, String> {
+        let network = self
+            .db
+            .selected_network()?
+            .ok_or_else(|| "No network configured".to_string())?;
+
+        let mut active = self.active.lock().await;
+        if let Some(current) = active.as_ref() {
+            if current.config_url == network.url && !current.conn.is_closed() {
+                return Ok(current.conn.clone());
+            }
+        }
+
+        let mut liteservers = self.configs.liteservers(&network.url).await?.to_vec();
+        liteservers.shuffle(&mut rand::thread_rng());
+
+        // Dialed like the proxy does, through the network's upstream proxy
+        let upstream = Upstream::load(&self.db);
+        for ls in liteservers {
+            let key = ls.public_key()?;
+            let connected = match dial(&upstream, ls.addr()).await {
+                Ok(stream) => LiteConnection::handshake(stream, &key).await,
+                Err(e) => Err(e.into()),
+            };
+            match connected {
+                Ok(conn) => {
+                    info!("Native lite client connected to {}", ls.addr());
+                    let conn = Arc::new(conn);
+                    *active = Some(ActiveConnection {
+                        config_url: network.url.clone(),
+                        conn: conn.clone(),
+                    });
+                    return Ok(conn);
+                }
+                Err(e) => trace!("Native lite client: {} unavailable: {}", ls.addr(), e),
+            }
+        }
+
+        Err(format!("Could not connect to any liteserver of {}", network.name))
+    }
+}
+
+#[tauri::command]
+pub async fn lite_get_masterchain_info(
+    clients: State<'_, LiteClients>,
+) -> Result<MasterchainInfo, String> {
+    let conn = clients.connection().await?;
+    lite_api::get_masterchain_info(&conn)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Get the state of an account at the last masterchain block.
+/// `address` may be raw or user-friendly.
+#[tauri::command]
+pub async fn lite_get_account_state(
+    address: String,
+    clients: State<'_, LiteClients>,
+) -> Result<AccountState, String> {
+    let address = TonAddress::parse(&address)?;
+    let conn = clients.connection().await?;
+
+    let info = lite_api::get_masterchain_info(&conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    lite_api::get_account_state(&conn, &info.last, address.workchain, &address.hash)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Send an external message. `boc` is base64. Returns the liteserver status code.
+#[tauri::command]
+pub async fn lite_send_message(
+    boc: String,
+    clients: State<'_, LiteClients>,
+) -> Result<i32, String> {
+    let boc = general_purpose::STANDARD
+        .decode(boc)
+        .map_err(|e| format!("Invalid BoC base64: {}", e))?;
+    let conn = clients.connection().await?;
+
+    lite_api::send_message(&conn, &boc)
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,28 @@
+use crate::migrations::Migration;
+
+/// M034: create_echo_clients
+pub struct M034CreateEchoClients;
+
+impl M034CreateEchoClients {
+    pub fn new() -> Self { Self }
+}
+
+impl Migration for M034CreateEchoClients {
+    fn name(&self) -> &'static str { "m_34_create_echo_clients" }
+    
+    fn up(&self) -> &'static str {
+        r#"
+        CREATE TABLE echo_clients (
+            echo_client_id integer PRIMARY KEY AUTOINCREMENT,
+            client_id text NOT NULL UNIQUE,
+            secret_hash text NOT NULL,
+            name text,
+            origin text,
+            created_at integer NOT NULL,
+            last_seen_at integer
+        );
+        "#
+    }
+    
+    fn down(&self) -> Option<&'static str> { Some("DROP TABLE echo_clients;") }
+}
@@ -0,0 +1,27 @@
+use crate::migrations::Migration;
+
+/// M035: create_trace_dumps
+pub struct M035CreateTraceDumps;
+
+impl M035CreateTraceDumps {
+    pub fn new() -> Self { Self }
+}
+
+impl Migration for M035CreateTraceDumps {
+    fn name(&self) -> &'static str { "m_35_create_trace_dumps" }
+    
+    fn up(&self) -> &'static str {
+        r#"
+        CREATE TABLE trace_dumps (
+            trace_dump_id integer PRIMARY KEY AUTOINCREMENT,
+            source text,
+            created_at integer NOT NULL,
+            size integer NOT NULL,
+            dump text NOT NULL
+        );
+        CREATE INDEX idx_trace_dumps_created_at ON trace_dumps(created_at);
+        "#
+    }
+    
+    fn down(&self) -> Option<&'static str> { Some("DROP TABLE trace_dumps;") }
+}
@@ -0,0 +1,54 @@
+//! Session secret for the liteserver proxy.
+//!
+//! The secret is generated once per app run and handed to the webview through
+//! `get_ws_auth`. Clients pass it as the `auth` query parameter (browsers cannot
+//! set headers on a WebSocket) or in an `Authorization: Bearer` header.
+
+use rand::Rng;
+use std::sync::OnceLock;
+use tungstenite::handshake::server::Request;
+use url::form_urlencoded;
+
+static SESSION_SECRET: OnceLock<String> = OnceLock::new();
+
+/// Query parameter carrying the session secret
+pub const AUTH_QUERY_PARAM: &str = "auth";
+
+/// Get the session secret, generating it on first use
+pub fn session_secret() -> &'static str {
+    SESSION_SECRET.get_or_init(|| {
+        let bytes: [u8; 32] = rand::thread_rng().gen();
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    })
+}
+
+/// Check whether the handshake request carries the session secret
+pub fn is_authorized(req: &Request) -> bool {
+    let expected = session_secret().as_bytes();
+
+    let from_header = req
+        .headers()
+        .get(tungstenite::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|token| constant_time_eq(token.trim().as_bytes(), expected))
+        .unwrap_or(false);
+    if from_header {
+        return true;
+    }
+
+    req.uri()
+        .query()
+        .map(|query| {
+            form_urlencoded::parse(query.as_bytes())
+                .any(|(k, v)| k == AUTH_QUERY_PARAM && constant_time_eq(v.as_bytes(), expected))
+        })
+        .unwrap_or(false)
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
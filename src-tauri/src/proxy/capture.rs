@@ -0,0 +1,449 @@
+//! Capture and replay of liteserver queries on `/mux` sessions.
+//!
+//! Relayed sessions carry ADNL encrypted with keys negotiated per session, so
+//! their bytes can neither be read nor served again. `/mux` sessions are
+//! decrypted by the proxy, so they are captured as plain TL instead: when
+//! capturing is on, every query a session makes is written with its answer
+//! to a JSON-lines file in `<app data>/proxy_captures`, one header line
+//! followed by one line per query with a millisecond offset and the base64
+//! `liteServer.query` and answer.
+//!
+//! When a capture is chosen for replay, new `/mux` sessions are answered from
+//! that file instead of a liteserver. Queries are matched by their content, as
+//! clients pick fresh query ids, and a query asked more often than recorded
+//! gets the last recorded answer again. A replayed session may only ask for
+//! the server its capture was recorded from, and is let through without
+//! fetching the network config. Relayed sessions are refused while
+//! replaying, so nothing reaches the network.
+
+use crate::db::Database;
+use crate::util::unix_millis;
+use base64::{engine::general_purpose, Engine as _};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+
+pub const CAPTURE_ENABLED_SETTING: &str = "proxy_capture_enabled";
+pub const REPLAY_CAPTURE_SETTING: &str = "proxy_replay_capture";
+
+/// Version 1 captures held encrypted relay bytes and cannot be replayed
+const CAPTURE_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CaptureHeader {
+    version: u32,
+    destination: String,
+    started_at: i64,
+}
+
+/// One query and its answer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CaptureExchange {
+    /// Milliseconds since the session started
+    t: u64,
+    /// Base64 `liteServer.query`, as sent in `adnl.message.query`
+    query: String,
+    /// Base64 answer, as returned in `adnl.message.answer`
+    answer: String,
+}
+
+/// Capture file summary for the UI
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureInfo {
+    pub name: String,
+    pub destination: String,
+    pub started_at: i64,
+    pub version: u32,
+    pub size: u64,
+}
+
+/// Whether sessions are captured or replayed
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureState {
+    pub capturing: bool,
+    /// Name of the capture new `/mux` sessions are answered from
+    pub replaying: Option<String>,
+}
+
+/// Records one session's queries. Cloned into every query task.
+#[derive(Clone)]
+pub struct Recorder {
+    started: Instant,
+    tx: mpsc::UnboundedSender<CaptureExchange>,
+}
+
+impl Recorder {
+    pub fn record(&self, query: &[u8], answer: &[u8]) {
+        let _ = self.tx.send(CaptureExchange {
+            t: self.started.elapsed().as_millis() as u64,
+            query: general_purpose::STANDARD.encode(query),
+            answer: general_purpose::STANDARD.encode(answer),
+        });
+    }
+}
+
+/// Recorded answers of a capture, by query
+pub struct Replay {
+    name: String,
+    destination: SocketAddr,
+    answers: HashMap<Vec<u8>, VecDeque<Vec<u8>>>,
+}
+
+impl Replay {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The server the capture was recorded from
+    pub fn destination(&self) -> SocketAddr {
+        self.destination
+    }
+
+    /// The next recorded answer to `query`. The last one is kept for
+    /// queries repeated more often than recorded, e.g. polling.
+    pub fn answer(&mut self, query: &[u8]) -> Option<Vec<u8>> {
+        let answers = self.answers.get_mut(query)?;
+        if answers.len() > 1 {
+            answers.pop_front()
+        } else {
+            answers.front().cloned()
+        }
+    }
+}
+
+pub struct CaptureStore {
+    db: Database,
+    dir: PathBuf,
+}
+
+impl CaptureStore {
+    pub fn new(db: Database, dir: PathBuf) -> Self {
+        Self { db, dir }
+    }
+
+    fn capturing(&self) -> bool {
+        matches!(
+            self.db
+                .get_setting(CAPTURE_ENABLED_SETTING)
+                .ok()
+                .flatten()
+                .as_deref(),
+            Some("1") | Some("true")
+        )
+    }
+
+    fn replaying(&self) -> Option<String> {
+        self.db
+            .get_setting(REPLAY_CAPTURE_SETTING)
+            .ok()
+            .flatten()
+            .filter(|name| !name.is_empty())
+    }
+
+    pub fn state(&self) -> CaptureState {
+        CaptureState {
+            capturing: self.capturing(),
+            replaying: self.replaying(),
+        }
+    }
+
+    pub fn set_capturing(&self, enabled: bool) -> Result<(), String> {
+        self.db
+            .set_setting(CAPTURE_ENABLED_SETTING, if enabled { "1" } else { "0" })
+    }
+
+    /// Answer new `/mux` sessions from the capture `name`, or from
+    /// liteservers again when `None`
+    pub async fn set_replaying(&self, name: Option<&str>) -> Result<(), String> {
+        match name {
+            Some(name) => {
+                let path = self.path_for(name)?;
+                let header = read_header(&path)
+                    .await
+                    .ok_or_else(|| format!("{} is not a capture", name))?;
+                if header.version != CAPTURE_VERSION {
+                    return Err(format!(
+                        "{} is a version {} capture, only version {} can be replayed",
+                        name, header.version, CAPTURE_VERSION
+                    ));
+                }
+                self.db.set_setting(REPLAY_CAPTURE_SETTING, name)
+            }
+            None => self.db.set_setting(REPLAY_CAPTURE_SETTING, ""),
+        }
+    }
+
+    /// Whether sessions are answered from a capture instead of the network
+    pub fn is_replaying(&self) -> bool {
+        self.replaying().is_some()
+    }
+
+    /// Start recording a `/mux` session if capturing is enabled
+    pub async fn start_recording(&self, destination: SocketAddr) -> Option<Recorder> {
+        if !self.capturing() {
+            return None;
+        }
+
+        if let Err(e) = tokio::fs::create_dir_all(&self.dir).await {
+            warn!("Capture: failed to create {}: {}", self.dir.display(), e);
+            return None;
+        }
+
+        let started_at = unix_millis();
+        let name = format!(
+            "{}_{}_{}.jsonl",
+            started_at,
+            destination.ip(),
+            destination.port()
+        );
+        let path = self.dir.join(&name);
+        let file = match tokio::fs::File::create(&path).await {
+            Ok(file) => file,
+            Err(e) => {
+                warn!("Capture: failed to create {}: {}", path.display(), e);
+                return None;
+            }
+        };
+
+        let header = CaptureHeader {
+            version: CAPTURE_VERSION,
+            destination: destination.to_string(),
+            started_at,
+        };
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(write_capture(file, header, rx));
+
+        info!("Capture: recording session to {}", name);
+        Some(Recorder {
+            started: Instant::now(),
+            tx,
+        })
+    }
+
+    /// The capture to answer a new session from, if replay mode is on
+    pub async fn load_replay(&self) -> Option<Result<Replay, String>> {
+        let name = self.replaying()?;
+        Some(self.read_replay(name).await)
+    }
+
+    async fn read_replay(&self, name: String) -> Result<Replay, String> {
+        let path = self.path_for(&name)?;
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|e| format!("Failed to read capture {}: {}", name, e))?;
+        let mut lines = content.lines();
+        let header: CaptureHeader = serde_json::from_str(lines.next().unwrap_or_default())
+            .map_err(|e| format!("Invalid capture {}: {}", name, e))?;
+        if header.version != CAPTURE_VERSION {
+            return Err(format!(
+                "Capture {} has version {}, expected {}",
+                name, header.version, CAPTURE_VERSION
+            ));
+        }
+        let destination = header
+            .destination
+            .parse()
+            .map_err(|e| format!("Invalid capture {}: {}", name, e))?;
+
+        let decode = |data: &str| {
+            general_purpose::STANDARD
+                .decode(data)
+                .map_err(|e| format!("Invalid capture {}: {}", name, e))
+        };
+        let mut answers: HashMap<Vec<u8>, VecDeque<Vec<u8>>> = HashMap::new();
+        for line in lines.filter(|line| !line.is_empty()) {
+            let exchange: CaptureExchange = serde_json::from_str(line)
+                .map_err(|e| format!("Invalid capture {}: {}", name, e))?;
+            answers
+                .entry(decode(&exchange.query)?)
+                .or_default()
+                .push_back(decode(&exchange.answer)?);
+        }
+
+        info!(
+            "Capture: replaying {} (recorded from {})",
+            name, destination
+        );
+        Ok(Replay {
+            name,
+            destination,
+            answers,
+        })
+    }
+
+    pub async fn list(&self) -> Result<Vec<CaptureInfo>, String> {
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("Failed to read captures: {}", e)),
+        };
+
+        let mut captures = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.ends_with(".jsonl") {
+                continue;
+            }
+            let size = entry.metadata().await.map(|m| m.len()).unwrap_or(0);
+            let header = read_header(&entry.path()).await;
+            captures.push(CaptureInfo {
+                name,
+                destination: header
+                    .as_ref()
+                    .map(|h| h.destination.clone())
+                    .unwrap_or_default(),
+                started_at: header.as_ref().map(|h| h.started_at).unwrap_or(0),
+                version: header.map(|h| h.version).unwrap_or(0),
+                size,
+            });
+        }
+
+        captures.sort_by_key(|c| Reverse(c.started_at));
+        Ok(captures)
+    }
+
+    pub async fn delete(&self, name: &str) -> Result<(), String> {
+        let path = self.path_for(name)?;
+        if self.replaying().as_deref() == Some(name) {
+            self.set_replaying(None).await?;
+        }
+        tokio::fs::remove_file(&path)
+            .await
+            .map_err(|e| format!("Failed to delete capture: {}", e))
+    }
+
+    /// Resolve a capture name to a path, rejecting anything that is not a plain file name
+    fn path_for(&self, name: &str) -> Result<PathBuf, String> {
+        let is_plain = !name.is_empty()
+            && !name.contains(['/', '\\'])
+            && name != "."
+            && name != ".."
+            && name.ends_with(".jsonl");
+        if !is_plain {
+            return Err(format!("Invalid capture name {:?}", name));
+        }
+        Ok(self.dir.join(name))
+    }
+}
+
+async fn write_capture(
+    file: tokio::fs::File,
+    header: CaptureHeader,
+    mut rx: mpsc::UnboundedReceiver<CaptureExchange>,
+) {
+    let mut writer = tokio::io::BufWriter::new(file);
+
+    if writer.write_all(&json_line(&header)).await.is_err() {
+        return;
+    }
+
+    // Flushed per query, so a capture can be replayed while still recording
+    while let Some(exchange) = rx.recv().await {
+        let written = match writer.write_all(&json_line(&exchange)).await {
+            Ok(()) => writer.flush().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            warn!("Capture: write failed: {}", e);
+            return;
+        }
+    }
+}
+
+fn json_line<T: Serialize>(value: &T) -> Vec<u8> {
+    let mut line = serde_json::to_vec(value).unwrap_or_default();
+    line.push(b'\n');
+    line
+}
+
+async fn read_header(path: &Path) -> Option<CaptureHeader> {
+    let file = tokio::fs::File::open(path).await.ok()?;
+    let mut line = String::new();
+    tokio::io::BufReader::new(file)
+        .read_line(&mut line)
+        .await
+        .ok()?;
+    serde_json::from_str(&line).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migration_commands::run_migrations_on_db;
+
+    fn store(name: &str) -> CaptureStore {
+        let dir = std::env::temp_dir().join(format!("captures-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.db");
+        run_migrations_on_db(path.to_str().unwrap()).unwrap();
+        CaptureStore::new(Database::open(&path).unwrap(), dir.join("proxy_captures"))
+    }
+
+    async fn wait_for_lines(store: &CaptureStore, name: &str, lines: usize) {
+        let path = store.path_for(name).unwrap();
+        for _ in 0..100 {
+            let content = tokio::fs::read_to_string(&path).await.unwrap_or_default();
+            if content.lines().count() >= lines {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("capture {} was not written", name);
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_answers_by_query() {
+        let store = store("replay");
+        store.set_capturing(true).unwrap();
+        let recorder = store
+            .start_recording("127.0.0.1:4924".parse().unwrap())
+            .await
+            .unwrap();
+        recorder.record(b"time", b"first");
+        recorder.record(b"info", b"master");
+        recorder.record(b"time", b"second");
+        drop(recorder);
+
+        let name = store.list().await.unwrap().remove(0).name;
+        wait_for_lines(&store, &name, 4).await;
+        assert!(store.load_replay().await.is_none());
+
+        store.set_replaying(Some(&name)).await.unwrap();
+        let mut replay = store.load_replay().await.unwrap().unwrap();
+        assert_eq!(replay.answer(b"time").as_deref(), Some(&b"first"[..]));
+        assert_eq!(replay.answer(b"time").as_deref(), Some(&b"second"[..]));
+        // Polled more often than recorded
+        assert_eq!(replay.answer(b"time").as_deref(), Some(&b"second"[..]));
+        assert_eq!(replay.answer(b"info").as_deref(), Some(&b"master"[..]));
+        assert_eq!(replay.answer(b"other"), None);
+
+        // Deleting the replayed capture turns replay off
+        store.delete(&name).await.unwrap();
+        assert_eq!(store.state().replaying, None);
+    }
+
+    #[tokio::test]
+    async fn refuses_to_replay_relay_captures() {
+        let store = store("version");
+        tokio::fs::create_dir_all(&store.dir).await.unwrap();
+        let header = CaptureHeader {
+            version: 1,
+            destination: "127.0.0.1:4924".into(),
+            started_at: 0,
+        };
+        tokio::fs::write(store.dir.join("old.jsonl"), json_line(&header))
+            .await
+            .unwrap();
+
+        assert!(store.set_replaying(Some("old.jsonl")).await.is_err());
+        assert!(store.set_replaying(Some("../data.db")).await.is_err());
+        assert_eq!(store.state().replaying, None);
+    }
+}
@@ -0,0 +1,184 @@
+use base64::{engine::general_purpose, Engine as _};
+use log::{trace, warn};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri_plugin_http::reqwest;
+
+/// How long a fetched global config is reused before it is fetched again
+const CONFIG_TTL: Duration = Duration::from_secs(300);
+/// How long a failed fetch is reported without trying again, so being
+/// offline does not stall every caller for the fetch timeout
+const FAILURE_TTL: Duration = Duration::from_secs(30);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize)]
+struct GlobalConfig {
+    #[serde(default)]
+    liteservers: Vec<LiteServerEntry>,
+}
+
+/// Liteserver entry from a TON global config (`liteservers[]`)
+#[derive(Debug, Clone, Deserialize)]
+pub struct LiteServerEntry {
+    pub ip: i32,
+    pub port: u16,
+    pub id: LiteServerId,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LiteServerId {
+    pub key: String,
+}
+
+impl LiteServerEntry {
+    /// Socket address of the liteserver. Global configs store the IPv4 address as a signed int.
+    pub fn addr(&self) -> SocketAddr {
+        SocketAddr::from((Ipv4Addr::from(self.ip as u32), self.port))
+    }
+
+    /// The liteserver's ed25519 public key
+    pub fn public_key(&self) -> Result<[u8; 32], String> {
+        general_purpose::STANDARD
+            .decode(&self.id.key)
+            .ok()
+            .and_then(|key| key.try_into().ok())
+            .ok_or_else(|| format!("Invalid liteserver key {}", self.id.key))
+    }
+}
+
+#[derive(Default)]
+struct CachedConfig {
+    fetched: Option<(Instant, Arc<Vec<LiteServerEntry>>)>,
+    failed: Option<(Instant, String)>,
+}
+
+impl CachedConfig {
+    /// The last fetched copy, however old, or why fetching failed
+    fn stale(&self, error: String) -> Result<Arc<Vec<LiteServerEntry>>, String> {
+        match &self.fetched {
+            Some((_, liteservers)) => Ok(liteservers.clone()),
+            None => Err(error),
+        }
+    }
+}
+
+/// Config of one URL, locked while it is being fetched
+type Slot = Arc<tokio::sync::Mutex<CachedConfig>>;
+
+/// Fetches global config files and caches their liteserver lists by URL
+pub struct GlobalConfigCache {
+    http: reqwest::Client,
+    entries: Mutex<HashMap<String, Slot>>,
+}
+
+impl GlobalConfigCache {
+    pub fn new() -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get the liteservers listed in the config at `url`.
+    /// A stale cached copy is returned if refreshing fails.
+    pub async fn liteservers(&self, url: &str) -> Result<Arc<Vec<LiteServerEntry>>, String> {
+        let slot = self
+            .entries
+            .lock()
+            .unwrap()
+            .entry(url.to_string())
+            .or_default()
+            .clone();
+        // Callers of the same URL wait for one fetch; other URLs are not held up
+        let mut cached = slot.lock().await;
+
+        if let Some((fetched_at, liteservers)) = &cached.fetched {
+            if fetched_at.elapsed() < CONFIG_TTL {
+                return Ok(liteservers.clone());
+            }
+        }
+        if let Some((failed_at, e)) = &cached.failed {
+            if failed_at.elapsed() < FAILURE_TTL {
+                return cached.stale(e.clone());
+            }
+        }
+
+        match self.fetch(url).await {
+            Ok(liteservers) => {
+                trace!("Fetched global config {} ({} liteservers)", url, liteservers.len());
+                let liteservers = Arc::new(liteservers);
+                cached.fetched = Some((Instant::now(), liteservers.clone()));
+                cached.failed = None;
+                Ok(liteservers)
+            }
+            Err(e) => {
+                if cached.fetched.is_some() {
+                    warn!("Failed to refresh global config {}, using cached copy: {}", url, e);
+                }
+                cached.failed = Some((Instant::now(), e.clone()));
+                cached.stale(e)
+            }
+        }
+    }
+
+    async fn fetch(&self, url: &str) -> Result<Vec<LiteServerEntry>, String> {
+        let body = self
+            .http
+            .get(url)
+            .timeout(FETCH_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to fetch global config: {}", e))?
+            .error_for_status()
+            .map_err(|e| format!("Failed to fetch global config: {}", e))?
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read global config: {}", e))?;
+
+        let config: GlobalConfig = serde_json::from_str(&body)
+            .map_err(|e| format!("Invalid global config: {}", e))?;
+
+        Ok(config.liteservers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// A config host that fails every request, counting them
+    async fn failing_host() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/global.config.json", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counted = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                counted.fetch_add(1, Ordering::SeqCst);
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf).await;
+                let _ = stream
+                    .write_all(b"HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\n\r\n")
+                    .await;
+            }
+        });
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn failed_fetches_are_cached() {
+        let (url, requests) = failing_host().await;
+        let cache = GlobalConfigCache::new();
+
+        let first = cache.liteservers(&url).await.unwrap_err();
+        let second = cache.liteservers(&url).await.unwrap_err();
+        assert_eq!(first, second);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,747 @@
+mod auth;
+mod capture;
+mod global_config;
+mod mux;
+mod policy;
+mod pool;
+mod session_config;
+mod stats;
+mod upstream;
+
+pub use auth::session_secret;
+pub(crate) use auth::constant_time_eq;
+pub use capture::CaptureStore;
+pub use global_config::GlobalConfigCache;
+pub use mux::MuxHub;
+use mux::MuxBackend;
+pub use policy::ProxyPolicy;
+pub use pool::{dial, LiteServerPool};
+pub use session_config::SessionConfig;
+pub use stats::ProxyStats;
+pub use upstream::Upstream;
+
+use capture::Recorder;
+use policy::Decision;
+use crate::db::Database;
+use crate::server_manager::{SessionGuard, Shutdown};
+use stats::ConnectionHandle;
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use log::{info, trace, warn};
+use serde::{Deserialize, Serialize};
+use std::error::Error as stdError;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::sleep_until;
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::{
+    accept_hdr_async,
+    tungstenite::handshake::server::{ErrorResponse, Request, Response},
+};
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::protocol::CloseFrame;
+use tungstenite::{http, Message};
+use url::form_urlencoded;
+
+/// Shared state for all proxy sessions
+#[derive(Clone)]
+pub struct ProxyContext {
+    pub db: Database,
+    pub policy: Arc<ProxyPolicy>,
+    pub pool: Arc<LiteServerPool>,
+    pub captures: Arc<CaptureStore>,
+    pub stats: Arc<ProxyStats>,
+    pub mux: Arc<MuxHub>,
+}
+
+/// Direction of proxied traffic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// Webview to liteserver
+    ClientToServer,
+    /// Liteserver to webview
+    ServerToClient,
+}
+
+/// Observers attached to one proxied session
+#[derive(Clone)]
+struct SessionTap {
+    stats: ConnectionHandle,
+    /// Set on `/mux` sessions while capturing
+    recorder: Option<Recorder>,
+    /// Keeps the session counted until both relay directions finish
+    _session: Arc<SessionGuard>,
+}
+
+impl SessionTap {
+    fn observe(&self, dir: Direction, data: &[u8]) {
+        self.stats.record(dir, data.len());
+    }
+
+    /// Capture a query answered by a liteserver
+    fn record(&self, query: &[u8], answer: &[u8]) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record(query, answer);
+        }
+    }
+}
+
+/// Messages queued for the WebSocket writer before the relay waits
+const WS_OUTBOX_SIZE: usize = 32;
+/// Pause after a failed accept, so a persistent error does not spin
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+#[derive(Serialize)]
+struct ProxyErrorBody {
+    error: &'static str,
+    message: String,
+}
+
+/// Accept proxy connections until the listener fails or a shutdown is requested
+pub async fn spawn_proxy(
+    listener: &mut TcpListener,
+    ctx: ProxyContext,
+    mut shutdown: Shutdown,
+) -> Result<(), Box<dyn stdError + Send + Sync>> {
+    let _ = env_logger::try_init();
+
+    loop {
+        let (stream, _) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    // Usually out of file descriptors; the listener itself is fine
+                    warn!("Proxy failed to accept a connection: {}", e);
+                    tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                    continue;
+                }
+            },
+            _ = shutdown.wait() => return Ok(()),
+        };
+
+        let ctx = ctx.clone();
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            if let Err(e) = accept_connection(stream, ctx, shutdown).await {
+                trace!("Connection error: {:?}", e);
+            }
+        });
+    }
+}
+
+/// Allow the proxy to reach liteservers from the given global config URL.
+/// Used when testing a network that is not selected (or not saved) yet.
+#[tauri::command]
+pub fn trust_proxy_config(url: String, policy: tauri::State<'_, Arc<ProxyPolicy>>) {
+    info!("Proxy policy: trusting config {}", url);
+    policy.trust_config(url);
+}
+
+/// Health and latency of the selected network's liteservers
+#[tauri::command]
+pub fn get_liteserver_health(
+    pool: tauri::State<'_, Arc<LiteServerPool>>,
+) -> Vec<pool::LiteServerHealth> {
+    pool.health()
+}
+
+/// Per-connection and per-destination traffic counters
+#[tauri::command]
+pub fn get_proxy_stats(stats: tauri::State<'_, Arc<ProxyStats>>) -> stats::ProxyStatsSnapshot {
+    stats.snapshot()
+}
+
+#[tauri::command]
+pub async fn list_proxy_captures(
+    captures: tauri::State<'_, Arc<CaptureStore>>,
+) -> Result<Vec<capture::CaptureInfo>, String> {
+    captures.list().await
+}
+
+#[tauri::command]
+pub fn get_proxy_capture_state(
+    captures: tauri::State<'_, Arc<CaptureStore>>,
+) -> capture::CaptureState {
+    captures.state()
+}
+
+/// Record the queries of new `/mux` sessions
+#[tauri::command]
+pub fn set_proxy_capture(
+    enabled: bool,
+    captures: tauri::State<'_, Arc<CaptureStore>>,
+) -> Result<(), String> {
+    captures.set_capturing(enabled)
+}
+
+/// Answer new `/mux` sessions from the capture `name`, or from liteservers
+/// again when `name` is null
+#[tauri::command]
+pub async fn set_proxy_replay(
+    name: Option<String>,
+    captures: tauri::State<'_, Arc<CaptureStore>>,
+) -> Result<(), String> {
+    captures.set_replaying(name.as_deref()).await
+}
+
+#[tauri::command]
+pub async fn delete_proxy_capture(
+    name: String,
+    captures: tauri::State<'_, Arc<CaptureStore>>,
+) -> Result<(), String> {
+    captures.delete(&name).await
+}
+
+fn int_to_ip(int: i32) -> String {
+    Ipv4Addr::from(int as u32).to_string()
+}
+
+fn error_response(
+    status: http::StatusCode,
+    error: &'static str,
+    message: &str,
+) -> ErrorResponse {
+    let body = ProxyErrorBody {
+        error,
+        message: message.to_string(),
+    };
+
+    http::Response::builder()
+        .status(status)
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(Some(serde_json::to_string(&body).unwrap_or_default()))
+        .unwrap()
+}
+
+async fn accept_connection(
+    stream: TcpStream,
+    ctx: ProxyContext,
+    shutdown: Shutdown,
+) -> Result<(), Box<dyn stdError + Send + Sync>> {
+    let peer_addr = stream.peer_addr()?;
+    trace!("Peer address: {}", peer_addr);
+
+    let mut target_addr: Option<SocketAddr> = None;
+    let mut target_key: Option<String> = None;
+    let mut mux_key: Option<[u8; 32]> = None;
+
+    // Only authorized clients get their destination checked, as the check
+    // may fetch the network config. It runs after the handshake, since the
+    // callback cannot wait for it.
+    // The error type is fixed by tungstenite
+    #[allow(clippy::result_large_err)]
+    let auth_callback = |req: &Request, res: Response| {
+        trace!("Request path: {}", req.uri().path());
+
+        if !auth::is_authorized(req) {
+            warn!("Proxy auth: rejected {} (missing or invalid session secret)", peer_addr);
+            return Err(error_response(
+                http::StatusCode::UNAUTHORIZED,
+                "unauthorized",
+                "Missing or invalid proxy session secret."
+            ));
+        }
+
+        let mut ip = None;
+        let mut port = None;
+
+        if let Some(query) = req.uri().query() {
+            for (k, v) in form_urlencoded::parse(query.as_bytes()) {
+                match k.as_ref() {
+                    "ip" => {
+                        if let Ok(ip_num) = v.parse::<i32>() {
+                            ip = Some(int_to_ip(ip_num));
+                        }
+                    }
+                    "port" => {
+                        if let Ok(port_num) = v.parse::<u16>() {
+                            port = Some(port_num);
+                        }
+                    }
+                    "pubkey" => {
+                        target_key = Some(v.into_owned());
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if req.uri().path() == mux::MUX_PATH {
+            mux_key = target_key.as_deref().and_then(mux::parse_public_key);
+            if mux_key.is_none() {
+                warn!("Proxy: rejected {} (mux without a valid pubkey)", peer_addr);
+                return Err(error_response(
+                    http::StatusCode::BAD_REQUEST,
+                    "invalid_public_key",
+                    "The mux endpoint requires a base64 'pubkey' query parameter."
+                ));
+            }
+        }
+
+        if let (Some(ip_str), Some(port_val)) = (ip, port) {
+            if let Ok(addr) = format!("{}:{}", ip_str, port_val).parse::<SocketAddr>() {
+                target_addr = Some(addr);
+                return Ok(res);
+            }
+        }
+
+        warn!("Proxy policy: deny {} (invalid destination)", peer_addr);
+        Err(error_response(
+            http::StatusCode::BAD_REQUEST,
+            "invalid_destination",
+            "Missing or invalid 'ip' or 'port' query parameters."
+        ))
+    };
+
+    let ws_stream = accept_hdr_async(stream, auth_callback).await?;
+
+    trace!("New WebSocket connection: {}", peer_addr);
+
+    let addr = target_addr.unwrap();
+    trace!("Target address {}", addr);
+
+    let (mut wo, ro) = ws_stream.split();
+
+    // A replayed session is answered from its capture alone, so it skips the
+    // config fetch and may only ask for the server the capture was made with
+    let replay = match mux_key {
+        Some(_) => ctx.captures.load_replay().await,
+        None => None,
+    };
+    let replay = match replay {
+        Some(Ok(replay)) if replay.destination() == addr => Some(replay),
+        Some(Ok(replay)) => {
+            warn!(
+                "Capture: deny {} -> {} (replaying {}, recorded from {})",
+                peer_addr,
+                addr,
+                replay.name(),
+                replay.destination()
+            );
+            let _ = wo
+                .send(CloseReason::DestinationNotAllowed(addr).close_message())
+                .await;
+            return Ok(());
+        }
+        Some(Err(e)) => {
+            warn!("Capture: {}", e);
+            let _ = wo.send(CloseReason::ServerError(e).close_message()).await;
+            return Ok(());
+        }
+        None => None,
+    };
+
+    if replay.is_none() {
+        // Nothing but recorded answers is served while replaying
+        if ctx.captures.is_replaying() {
+            warn!("Capture: refused relay session from {} while replaying", peer_addr);
+            let reason = CloseReason::ServerError("only /mux sessions are replayed".into());
+            let _ = wo.send(reason.close_message()).await;
+            return Ok(());
+        }
+
+        match ctx.policy.check(&addr).await {
+            Decision::Allow(source) => {
+                info!("Proxy policy: allow {} -> {} ({})", peer_addr, addr, source);
+            }
+            Decision::Deny => {
+                warn!("Proxy policy: deny {} -> {}", peer_addr, addr);
+                let _ = wo
+                    .send(CloseReason::DestinationNotAllowed(addr).close_message())
+                    .await;
+                return Ok(());
+            }
+        }
+    }
+
+    let config = SessionConfig::load(&ctx.db);
+    trace!("Session config: {:?}", config);
+
+    if let Some(key) = mux_key {
+        let backend = match replay {
+            Some(replay) => MuxBackend::Replay(replay),
+            None => match ctx.mux.session(addr, key).await {
+                Ok(session) => MuxBackend::Live(session),
+                Err(e) => {
+                    warn!("Mux: failed to connect to {}: {}", addr, e);
+                    let _ = wo
+                        .send(CloseReason::ServerError(e.to_string()).close_message())
+                        .await;
+                    return Ok(());
+                }
+            },
+        };
+        let recorder = match backend {
+            MuxBackend::Live(_) => ctx.captures.start_recording(addr).await,
+            MuxBackend::Replay(_) => None,
+        };
+
+        let tap = SessionTap {
+            stats: ctx.stats.open(peer_addr, addr),
+            recorder,
+            _session: Arc::new(shutdown.track()),
+        };
+        let (ws_tx, ws_rx) = mpsc::channel(WS_OUTBOX_SIZE);
+        tokio::spawn(ws_writer(wo, ws_rx));
+        tokio::spawn(mux::serve(ro, ws_tx, backend, config, tap, shutdown));
+        return Ok(());
+    }
+
+    // The client encrypts the handshake, so it can only be rerouted to a
+    // server with the key it pinned
+    let connected = ctx.pool.connect(addr, target_key.as_deref()).await?;
+    let (target_stream, connected_addr) = (connected.stream, connected.addr);
+    if connected_addr != addr {
+        info!("Proxy: {} served by {} instead of {}", peer_addr, connected_addr, addr);
+    }
+
+    if let Some(time) = config.tcp_keepalive() {
+        let keepalive = socket2::TcpKeepalive::new().with_time(time);
+        if let Err(e) = socket2::SockRef::from(&target_stream).set_tcp_keepalive(&keepalive) {
+            warn!("Proxy: failed to enable TCP keepalive for {}: {}", connected_addr, e);
+        }
+    }
+
+    let tap = SessionTap {
+        stats: ctx.stats.open(peer_addr, connected_addr),
+        // Relayed bytes are encrypted with per-session keys and not worth capturing
+        recorder: None,
+        _session: Arc::new(shutdown.track()),
+    };
+
+    let (ri, wi) = target_stream.into_split();
+    let (ws_tx, ws_rx) = mpsc::channel(WS_OUTBOX_SIZE);
+
+    tokio::spawn(ws_writer(wo, ws_rx));
+    tokio::spawn(ws_to_tcp(
+        ro,
+        wi,
+        ws_tx.clone(),
+        config.clone(),
+        tap.clone(),
+        shutdown.clone(),
+    ));
+    tokio::spawn(tcp_to_ws(ri, ws_tx, config, tap, shutdown));
+
+    Ok(())
+}
+
+/// Why a session ended. Sent to the webview in the WebSocket close frame.
+#[derive(Debug)]
+enum CloseReason {
+    ClientClosed,
+    IdleTimeout,
+    KeepaliveTimeout,
+    ClientError(String),
+    ServerClosed,
+    ServerError(String),
+    /// Not a liteserver of the selected network nor in the allow-list
+    DestinationNotAllowed(SocketAddr),
+    Shutdown,
+}
+
+impl CloseReason {
+    fn code(&self) -> CloseCode {
+        match self {
+            CloseReason::ClientClosed | CloseReason::ServerClosed => CloseCode::Normal,
+            CloseReason::IdleTimeout | CloseReason::KeepaliveTimeout | CloseReason::Shutdown => {
+                CloseCode::Away
+            }
+            CloseReason::ClientError(_) => CloseCode::Protocol,
+            CloseReason::ServerError(_) => CloseCode::Error,
+            CloseReason::DestinationNotAllowed(_) => CloseCode::Policy,
+        }
+    }
+
+    fn close_message(&self) -> Message {
+        Message::Close(Some(CloseFrame {
+            code: self.code(),
+            reason: self.to_string().into(),
+        }))
+    }
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CloseReason::ClientClosed => write!(f, "client closed"),
+            CloseReason::IdleTimeout => write!(f, "idle timeout"),
+            CloseReason::KeepaliveTimeout => write!(f, "keepalive timeout"),
+            CloseReason::ClientError(e) => write!(f, "websocket error: {}", e),
+            CloseReason::ServerClosed => write!(f, "liteserver closed"),
+            CloseReason::ServerError(e) => write!(f, "liteserver error: {}", e),
+            CloseReason::DestinationNotAllowed(addr) => {
+                write!(f, "destination {} is not allowed", addr)
+            }
+            CloseReason::Shutdown => write!(f, "server shutting down"),
+        }
+    }
+}
+
+/// Owns the WebSocket sink so data, pings and the close frame share one writer.
+/// Stops after the first close frame.
+async fn ws_writer(
+    mut wo: SplitSink<WebSocketStream<TcpStream>, Message>,
+    mut rx: mpsc::Receiver<Message>,
+) {
+    while let Some(msg) = rx.recv().await {
+        let is_close = matches!(msg, Message::Close(_));
+        if let Err(e) = wo.send(msg).await {
+            trace!("ws_writer: WebSocket send error: {}", e);
+            break;
+        }
+        if is_close {
+            break;
+        }
+    }
+    let _ = wo.close().await;
+}
+
+/// Resolves on the next ping tick, or never when pings are disabled
+async fn next_ping(ping: &mut Option<tokio::time::Interval>) {
+    match ping {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+async fn ws_to_tcp(
+    mut ro: SplitStream<WebSocketStream<TcpStream>>,
+    mut wi: OwnedWriteHalf,
+    ws_tx: mpsc::Sender<Message>,
+    config: SessionConfig,
+    tap: SessionTap,
+    mut shutdown: Shutdown,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    trace!("ws_to_tcp start");
+
+    let idle_timeout = config.idle_timeout();
+    let ping_interval = config.ws_ping_interval();
+    let mut ping = ping_interval.map(|period| {
+        tokio::time::interval_at(tokio::time::Instant::now() + period, period)
+    });
+    let mut last_data = Instant::now();
+    let mut last_pong = Instant::now();
+
+    let reason = loop {
+        let idle = sleep_until((last_data + idle_timeout).into());
+
+        let msg = tokio::select! {
+            msg = ro.next() => msg,
+            _ = idle => {
+                trace!("ws_to_tcp: Timeout after {:?} of inactivity", idle_timeout);
+                break CloseReason::IdleTimeout;
+            }
+            _ = shutdown.wait() => break CloseReason::Shutdown,
+            _ = next_ping(&mut ping) => {
+                // A peer that misses two pings in a row is gone
+                if let Some(period) = ping_interval {
+                    if last_pong.elapsed() > period * 2 {
+                        trace!("ws_to_tcp: no pong for {:?}", last_pong.elapsed());
+                        break CloseReason::KeepaliveTimeout;
+                    }
+                }
+                if ws_tx.send(Message::Ping(Vec::new())).await.is_err() {
+                    break CloseReason::ClientClosed;
+                }
+                continue;
+            }
+        };
+
+        let msg = match msg {
+            None => {
+                trace!("ws_to_tcp: WebSocket stream closed");
+                break CloseReason::ClientClosed;
+            }
+            Some(Err(e)) => {
+                trace!("ws_to_tcp: WebSocket read error: {}", e);
+                break CloseReason::ClientError(e.to_string());
+            }
+            Some(Ok(msg)) => msg,
+        };
+
+        match msg {
+            Message::Binary(data) => {
+                trace!("ws_to_tcp: received {} bytes", data.len());
+                last_data = Instant::now();
+                tap.observe(Direction::ClientToServer, &data);
+                if let Err(e) = wi.write_all(&data).await {
+                    trace!("ws_to_tcp: TCP write error, connection likely closed.");
+                    break CloseReason::ServerError(e.to_string());
+                }
+                trace!("ws_to_tcp: written to tcp {}", data.len());
+            }
+            Message::Pong(_) => {
+                last_pong = Instant::now();
+            }
+            Message::Close(_) => {
+                trace!("ws_to_tcp: client sent close frame");
+                break CloseReason::ClientClosed;
+            }
+            _ => {}
+        }
+    };
+
+    tap.stats.close(&reason.to_string());
+    let _ = ws_tx.send(reason.close_message()).await;
+    let _ = wi.shutdown().await;
+    Ok(())
+}
+
+async fn tcp_to_ws(
+    ri: OwnedReadHalf,
+    ws_tx: mpsc::Sender<Message>,
+    config: SessionConfig,
+    tap: SessionTap,
+    mut shutdown: Shutdown,
+) -> Result<(), Box<dyn stdError + Send + Sync>> {
+    trace!("tcp_to_ws start");
+
+    let mut stream = BufReader::new(ri);
+    let mut buffer = vec![0_u8; config.read_buffer_bytes];
+
+    let reason = loop {
+        let read = tokio::select! {
+            read = stream.read(&mut buffer) => read,
+            _ = shutdown.wait() => break CloseReason::Shutdown,
+        };
+        let n = match read {
+            Ok(0) => {
+                trace!("tcp_to_ws: TCP stream ended");
+                break CloseReason::ServerClosed;
+            }
+            Ok(n) => n,
+            Err(e) => {
+                trace!("tcp_to_ws: TCP read error: {}", e);
+                break CloseReason::ServerError(e.to_string());
+            }
+        };
+
+        trace!("tcp_to_ws: read {} bytes from tcp", n);
+        tap.observe(Direction::ServerToClient, &buffer[..n]);
+        let msg = Message::Binary(buffer[..n].to_vec());
+        if ws_tx.send(msg).await.is_err() {
+            trace!("tcp_to_ws: WebSocket writer gone, connection likely closed.");
+            break CloseReason::ClientClosed;
+        }
+    };
+
+    tap.stats.close(&reason.to_string());
+    let _ = ws_tx.send(reason.close_message()).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adnl::tl::{TlReader, TlWriter, ADNL_MESSAGE_ANSWER, ADNL_MESSAGE_QUERY};
+    use crate::migration_commands::run_migrations_on_db;
+    use base64::{engine::general_purpose, Engine as _};
+
+    const RECORDED: &str = "10.0.0.1:4924";
+
+    /// A proxy with no networks configured, replaying a capture of `RECORDED`
+    async fn replaying_proxy() -> (SocketAddr, tokio::sync::watch::Sender<bool>) {
+        let dir = std::env::temp_dir().join(format!("proxy-replay-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.db");
+        run_migrations_on_db(path.to_str().unwrap()).unwrap();
+        let db = Database::open(&path).unwrap();
+
+        let captures = Arc::new(CaptureStore::new(db.clone(), dir.join("proxy_captures")));
+        captures.set_capturing(true).unwrap();
+        let recorder = captures
+            .start_recording(RECORDED.parse().unwrap())
+            .await
+            .unwrap();
+        recorder.record(b"time", b"recorded");
+        drop(recorder);
+        let name = captures.list().await.unwrap().remove(0).name;
+        for _ in 0..100 {
+            if captures.set_replaying(Some(&name)).await.is_ok() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(captures.is_replaying());
+
+        let configs = Arc::new(GlobalConfigCache::new());
+        let pool = Arc::new(LiteServerPool::new(db.clone(), configs.clone()));
+        let ctx = ProxyContext {
+            policy: Arc::new(ProxyPolicy::new(db.clone(), configs)),
+            mux: Arc::new(MuxHub::new(pool.clone())),
+            pool,
+            captures,
+            stats: Arc::new(ProxyStats::new()),
+            db,
+        };
+
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, shutdown) = Shutdown::new();
+        tokio::spawn(async move { spawn_proxy(&mut listener, ctx, shutdown).await });
+        (addr, stop)
+    }
+
+    async fn connect_mux(proxy: SocketAddr, destination: &str) -> WebSocketStream<TcpStream> {
+        let destination: SocketAddr = destination.parse().unwrap();
+        let ip = match destination.ip() {
+            std::net::IpAddr::V4(ip) => u32::from(ip) as i32,
+            std::net::IpAddr::V6(_) => unreachable!(),
+        };
+        let url = format!(
+            "ws://{}{}?ip={}&port={}&pubkey={}&auth={}",
+            proxy,
+            mux::MUX_PATH,
+            ip,
+            destination.port(),
+            form_urlencoded::byte_serialize(general_purpose::STANDARD.encode([7u8; 32]).as_bytes())
+                .collect::<String>(),
+            session_secret()
+        );
+        let stream = TcpStream::connect(proxy).await.unwrap();
+        let (ws, _) = tokio_tungstenite::client_async(url, stream).await.unwrap();
+        ws
+    }
+
+    #[tokio::test]
+    async fn replays_without_network_config() {
+        let (proxy, _stop) = replaying_proxy().await;
+
+        let mut ws = connect_mux(proxy, RECORDED).await;
+        let query_id = [1u8; 32];
+        let frame = TlWriter::new()
+            .u32(ADNL_MESSAGE_QUERY)
+            .int256(&query_id)
+            .bytes(b"time")
+            .finish();
+        ws.send(Message::Binary(frame)).await.unwrap();
+
+        let answer = match ws.next().await {
+            Some(Ok(Message::Binary(data))) => data,
+            other => panic!("expected an answer, got {:?}", other),
+        };
+        let mut reader = TlReader::new(&answer);
+        assert_eq!(reader.u32().unwrap(), ADNL_MESSAGE_ANSWER);
+        assert_eq!(reader.int256().unwrap(), query_id);
+        assert_eq!(reader.bytes().unwrap(), b"recorded");
+
+        // Any other server is refused without asking the network config
+        let mut ws = connect_mux(proxy, "10.0.0.2:4924").await;
+        match ws.next().await {
+            Some(Ok(Message::Close(Some(frame)))) => assert_eq!(frame.code, CloseCode::Policy),
+            other => panic!("expected a policy close, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,319 @@
+//! Liteserver multiplexer behind the `/mux` endpoint.
+//!
+//! Regular proxy sessions relay encrypted ADNL bytes, so every WebSocket needs
+//! its own TCP connection. On `/mux` the proxy terminates ADNL instead: clients
+//! send plaintext ADNL messages as binary frames and all clients of the same
+//! liteserver share one encrypted [`LiteConnection`].
+//!
+//! Frames from the client:
+//! - `adnl.message.query query_id:int256 query:bytes` is forwarded under a
+//!   fresh query id and answered with `adnl.message.answer` carrying the
+//!   client's original id, so ids from different clients never collide
+//! - `tcp.ping random_id:long` is answered locally with `tcp.pong`
+//!
+//! A query the shared connection cannot serve is answered with a
+//! `liteServer.error` so the client does not wait for its own timeout. A
+//! session whose shared connection closed gets a fresh one from the hub on
+//! its next query.
+//!
+//! Queries and answers are plain TL here, so this is where sessions are
+//! captured and where a capture is replayed instead of a liteserver.
+
+use super::capture::Replay;
+use super::pool::LiteServerPool;
+use super::{next_ping, CloseReason, Direction, SessionConfig, SessionTap};
+use crate::adnl::tl::{
+    TlReader, TlWriter, ADNL_MESSAGE_ANSWER, ADNL_MESSAGE_QUERY, LITE_SERVER_ERROR, TCP_PING,
+    TCP_PONG,
+};
+use crate::adnl::{AdnlError, AdnlResult, LiteConnection};
+use crate::server_manager::Shutdown;
+use base64::{engine::general_purpose, Engine as _};
+use futures_util::stream::SplitStream;
+use futures_util::StreamExt;
+use log::{info, trace};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::time::sleep_until;
+use tokio_tungstenite::WebSocketStream;
+use tungstenite::Message;
+
+pub const MUX_PATH: &str = "/mux";
+
+/// Code of the `liteServer.error` sent when the shared connection fails a query
+const MUX_ERROR_CODE: i32 = 502;
+/// Queries one session may have waiting for an answer. Further frames are
+/// not read until one is answered.
+const MAX_IN_FLIGHT: usize = 64;
+
+/// Requested server address and key
+type ServerKey = (SocketAddr, [u8; 32]);
+/// Connection to one server, locked while it is being opened
+type Slot = Arc<tokio::sync::Mutex<Option<Arc<LiteConnection>>>>;
+
+/// Shared liteserver connections, one per requested server address and key
+pub struct MuxHub {
+    pool: Arc<LiteServerPool>,
+    connections: Mutex<HashMap<ServerKey, Slot>>,
+}
+
+impl MuxHub {
+    pub fn new(pool: Arc<LiteServerPool>) -> Self {
+        Self {
+            pool,
+            connections: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Shared connection to `addr`, opened on first use and replaced once closed.
+    /// The hub does the handshake, so when `addr` is down the pool may reroute
+    /// to any healthy server and the hub handshakes with that server's key.
+    pub async fn connection(
+        &self,
+        addr: SocketAddr,
+        public_key: [u8; 32],
+    ) -> AdnlResult<Arc<LiteConnection>> {
+        let slot = self
+            .connections
+            .lock()
+            .unwrap()
+            .entry((addr, public_key))
+            .or_default()
+            .clone();
+        // Held while connecting so concurrent clients of the same server wait
+        // for one connection, while other servers connect in parallel
+        let mut slot = slot.lock().await;
+        if let Some(conn) = slot.as_ref() {
+            if !conn.is_closed() {
+                return Ok(conn.clone());
+            }
+        }
+
+        let connected = self.pool.connect(addr, None).await?;
+        let key = if connected.addr == addr {
+            public_key
+        } else {
+            connected
+                .public_key
+                .as_deref()
+                .and_then(parse_public_key)
+                .ok_or_else(|| {
+                    AdnlError::Handshake(format!("no valid public key for {}", connected.addr))
+                })?
+        };
+        let conn = Arc::new(LiteConnection::handshake(connected.stream, &key).await?);
+        info!("Mux: opened shared connection to {}", connected.addr);
+
+        *slot = Some(conn.clone());
+        Ok(conn)
+    }
+
+    /// A session's handle on the shared connection to `addr`
+    pub async fn session(
+        self: &Arc<Self>,
+        addr: SocketAddr,
+        public_key: [u8; 32],
+    ) -> AdnlResult<MuxSession> {
+        Ok(MuxSession {
+            conn: self.connection(addr, public_key).await?,
+            hub: self.clone(),
+            key: (addr, public_key),
+        })
+    }
+}
+
+/// The shared connection one `/mux` client uses, reopened through the hub
+/// once it closes
+pub struct MuxSession {
+    hub: Arc<MuxHub>,
+    key: ServerKey,
+    conn: Arc<LiteConnection>,
+}
+
+/// Where a `/mux` session's queries are answered
+pub enum MuxBackend {
+    Live(MuxSession),
+    Replay(Replay),
+}
+
+impl MuxSession {
+    async fn connection(&mut self) -> AdnlResult<Arc<LiteConnection>> {
+        if self.conn.is_closed() {
+            self.conn = self.hub.connection(self.key.0, self.key.1).await?;
+        }
+        Ok(self.conn.clone())
+    }
+}
+
+/// Decode the base64 `pubkey` query parameter
+pub fn parse_public_key(key: &str) -> Option<[u8; 32]> {
+    general_purpose::STANDARD
+        .decode(key)
+        .ok()
+        .and_then(|key| key.try_into().ok())
+}
+
+enum ClientFrame {
+    Query { query_id: [u8; 32], query: Vec<u8> },
+    Ping { random_id: i64 },
+}
+
+fn parse_frame(data: &[u8]) -> AdnlResult<ClientFrame> {
+    let mut reader = TlReader::new(data);
+    match reader.u32()? {
+        ADNL_MESSAGE_QUERY => Ok(ClientFrame::Query {
+            query_id: reader.int256()?,
+            query: reader.bytes()?,
+        }),
+        TCP_PING => Ok(ClientFrame::Ping {
+            random_id: reader.i64()?,
+        }),
+        other => Err(AdnlError::Protocol(format!(
+            "Unsupported message constructor {:#010x}",
+            other
+        ))),
+    }
+}
+
+fn answer_frame(query_id: &[u8; 32], answer: &[u8]) -> Vec<u8> {
+    TlWriter::new()
+        .u32(ADNL_MESSAGE_ANSWER)
+        .int256(query_id)
+        .bytes(answer)
+        .finish()
+}
+
+fn error_answer(error: &impl fmt::Display) -> Vec<u8> {
+    TlWriter::new()
+        .u32(LITE_SERVER_ERROR)
+        .i32(MUX_ERROR_CODE)
+        .bytes(error.to_string().as_bytes())
+        .finish()
+}
+
+/// Serve one `/mux` client until it disconnects, idles out or the server stops
+pub(super) async fn serve(
+    mut ro: SplitStream<WebSocketStream<TcpStream>>,
+    ws_tx: mpsc::Sender<Message>,
+    mut backend: MuxBackend,
+    config: SessionConfig,
+    tap: SessionTap,
+    mut shutdown: Shutdown,
+) {
+    trace!("mux session start");
+
+    let idle_timeout = config.idle_timeout();
+    let ping_interval = config.ws_ping_interval();
+    let mut ping = ping_interval
+        .map(|period| tokio::time::interval_at(tokio::time::Instant::now() + period, period));
+    let mut last_data = Instant::now();
+    let mut last_pong = Instant::now();
+    let in_flight = Arc::new(Semaphore::new(MAX_IN_FLIGHT));
+
+    let reason = loop {
+        let idle = sleep_until((last_data + idle_timeout).into());
+
+        let msg = tokio::select! {
+            msg = ro.next() => msg,
+            _ = idle => break CloseReason::IdleTimeout,
+            _ = shutdown.wait() => break CloseReason::Shutdown,
+            _ = next_ping(&mut ping) => {
+                if let Some(period) = ping_interval {
+                    if last_pong.elapsed() > period * 2 {
+                        break CloseReason::KeepaliveTimeout;
+                    }
+                }
+                if ws_tx.send(Message::Ping(Vec::new())).await.is_err() {
+                    break CloseReason::ClientClosed;
+                }
+                continue;
+            }
+        };
+
+        let data = match msg {
+            None | Some(Ok(Message::Close(_))) => break CloseReason::ClientClosed,
+            Some(Err(e)) => break CloseReason::ClientError(e.to_string()),
+            Some(Ok(Message::Binary(data))) => data,
+            Some(Ok(Message::Pong(_))) => {
+                last_pong = Instant::now();
+                continue;
+            }
+            Some(Ok(_)) => continue,
+        };
+
+        last_data = Instant::now();
+        tap.observe(Direction::ClientToServer, &data);
+
+        match parse_frame(&data) {
+            Ok(ClientFrame::Query { query_id, query }) => {
+                let session = match &mut backend {
+                    MuxBackend::Live(session) => session,
+                    MuxBackend::Replay(replay) => {
+                        let answer = replay.answer(&query).unwrap_or_else(|| {
+                            trace!("Mux: query not in capture {}", replay.name());
+                            error_answer(&"query is not in the replayed capture")
+                        });
+                        let frame = answer_frame(&query_id, &answer);
+                        tap.observe(Direction::ServerToClient, &frame);
+                        if ws_tx.send(Message::Binary(frame)).await.is_err() {
+                            break CloseReason::ClientClosed;
+                        }
+                        continue;
+                    }
+                };
+                let permit = tokio::select! {
+                    permit = in_flight.clone().acquire_owned() => {
+                        permit.expect("the semaphore is never closed")
+                    }
+                    _ = shutdown.wait() => break CloseReason::Shutdown,
+                };
+                let conn = match session.connection().await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        trace!("Mux: could not reconnect to {}: {}", session.key.0, e);
+                        let frame = answer_frame(&query_id, &error_answer(&e));
+                        tap.observe(Direction::ServerToClient, &frame);
+                        if ws_tx.send(Message::Binary(frame)).await.is_err() {
+                            break CloseReason::ClientClosed;
+                        }
+                        continue;
+                    }
+                };
+                let ws_tx = ws_tx.clone();
+                let tap = tap.clone();
+                tokio::spawn(async move {
+                    let _permit = permit;
+                    let answer = match conn.query_raw(&query).await {
+                        Ok(answer) => {
+                            tap.record(&query, &answer);
+                            answer
+                        }
+                        Err(e) => {
+                            trace!("Mux: query to {} failed: {}", conn.addr(), e);
+                            error_answer(&e)
+                        }
+                    };
+                    let frame = answer_frame(&query_id, &answer);
+                    tap.observe(Direction::ServerToClient, &frame);
+                    let _ = ws_tx.send(Message::Binary(frame)).await;
+                });
+            }
+            Ok(ClientFrame::Ping { random_id }) => {
+                let frame = TlWriter::new().u32(TCP_PONG).i64(random_id).finish();
+                tap.observe(Direction::ServerToClient, &frame);
+                if ws_tx.send(Message::Binary(frame)).await.is_err() {
+                    break CloseReason::ClientClosed;
+                }
+            }
+            Err(e) => break CloseReason::ClientError(e.to_string()),
+        }
+    };
+
+    tap.stats.close(&reason.to_string());
+    let _ = ws_tx.send(reason.close_message()).await;
+}
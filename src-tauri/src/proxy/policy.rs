@@ -0,0 +1,124 @@
+//! Destination policy for the liteserver proxy.
+//!
+//! A WebSocket client may only be relayed to a liteserver that is listed in the
+//! selected network's global config, in a config the app trusted at runtime
+//! (e.g. while testing a network that is not saved yet), or in the user-managed
+//! allow-list stored in the `settings` table.
+
+use super::global_config::GlobalConfigCache;
+use crate::db::Database;
+use log::warn;
+use std::collections::HashSet;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+/// Setting holding a JSON array of extra allowed destinations, e.g. `["1.2.3.4:5678"]`
+pub const ALLOW_LIST_SETTING: &str = "proxy_allowed_destinations";
+
+pub struct ProxyPolicy {
+    db: Database,
+    configs: Arc<GlobalConfigCache>,
+    trusted_configs: Mutex<HashSet<String>>,
+}
+
+/// Why a destination was allowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllowSource {
+    NetworkConfig,
+    AllowList,
+}
+
+/// Outcome of checking a destination against the policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Allow(AllowSource),
+    Deny,
+}
+
+impl fmt::Display for AllowSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AllowSource::NetworkConfig => write!(f, "network config"),
+            AllowSource::AllowList => write!(f, "allow-list"),
+        }
+    }
+}
+
+impl ProxyPolicy {
+    pub fn new(db: Database, configs: Arc<GlobalConfigCache>) -> Self {
+        Self {
+            db,
+            configs,
+            trusted_configs: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Allow liteservers from the given global config URL until the app restarts
+    pub fn trust_config(&self, url: String) {
+        if let Ok(mut trusted) = self.trusted_configs.lock() {
+            trusted.insert(url);
+        }
+    }
+
+    /// Check a destination of an authorized client. The allow-list is
+    /// checked first, so allow-listed servers never wait for a config fetch.
+    pub async fn check(&self, addr: &SocketAddr) -> Decision {
+        if self.allow_list().contains(addr) {
+            return Decision::Allow(AllowSource::AllowList);
+        }
+
+        let mut config_urls: Vec<String> = self
+            .trusted_configs
+            .lock()
+            .map(|trusted| trusted.iter().cloned().collect())
+            .unwrap_or_default();
+
+        match self.db.selected_network() {
+            Ok(Some(network)) => config_urls.push(network.url),
+            Ok(None) => warn!("Proxy policy: no network configured"),
+            Err(e) => warn!("Proxy policy: failed to read selected network: {}", e),
+        }
+
+        for url in config_urls {
+            match self.configs.liteservers(&url).await {
+                Ok(liteservers) if liteservers.iter().any(|ls| ls.addr() == *addr) => {
+                    return Decision::Allow(AllowSource::NetworkConfig);
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Proxy policy: {} ({})", e, url),
+            }
+        }
+        Decision::Deny
+    }
+
+    fn allow_list(&self) -> HashSet<SocketAddr> {
+        let raw = match self.db.get_setting(ALLOW_LIST_SETTING) {
+            Ok(Some(raw)) => raw,
+            Ok(None) => return HashSet::new(),
+            Err(e) => {
+                warn!("Proxy policy: failed to read allow-list: {}", e);
+                return HashSet::new();
+            }
+        };
+
+        let entries: Vec<String> = match serde_json::from_str(&raw) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Proxy policy: invalid {} setting: {}", ALLOW_LIST_SETTING, e);
+                return HashSet::new();
+            }
+        };
+
+        entries
+            .iter()
+            .filter_map(|entry| match entry.trim().parse::<SocketAddr>() {
+                Ok(addr) => Some(addr),
+                Err(_) => {
+                    warn!("Proxy policy: ignoring invalid allow-list entry {:?}", entry);
+                    None
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,308 @@
+//! Liteserver pool for the proxy.
+//!
+//! Tracks every liteserver of the selected network, pings them over ADNL on a
+//! schedule, keeps one warm TCP connection per healthy server and picks a
+//! healthy replacement when the requested server refuses a connection.
+//!
+//! ADNL sessions are bound to the server's public key. A caller that pinned a
+//! key, like a relay whose client encrypts the handshake itself, can only be
+//! rerouted to servers sharing that key. Unpinned callers, like the mux which
+//! does the handshake itself, can be rerouted to any healthy server and are
+//! told its key.
+//!
+//! All dials go through the network's upstream proxy, if one is configured.
+
+use super::global_config::GlobalConfigCache;
+use super::upstream::Upstream;
+use crate::adnl::LiteConnection;
+use crate::db::Database;
+use crate::util::unix_millis;
+use futures_util::future::join_all;
+use log::{info, trace, warn};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Warm connections older than this are discarded instead of handed out. Longer
+/// than the check interval, so the next check replaces a connection first.
+const WARM_MAX_AGE: Duration = Duration::from_secs(45);
+
+/// Health of a single liteserver as reported to the UI
+#[derive(Debug, Clone, Serialize)]
+pub struct LiteServerHealth {
+    pub address: String,
+    pub public_key: String,
+    pub healthy: bool,
+    pub latency_ms: Option<u64>,
+    pub last_checked: Option<i64>,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+}
+
+/// A connection handed out by the pool
+pub struct PoolConnection {
+    pub stream: TcpStream,
+    /// The server connected to, another one than requested after a failover
+    pub addr: SocketAddr,
+    /// Base64 public key of that server, if it is in the selected network
+    pub public_key: Option<String>,
+}
+
+struct ServerState {
+    health: LiteServerHealth,
+    warm: Option<(TcpStream, Instant)>,
+}
+
+pub struct LiteServerPool {
+    db: Database,
+    configs: Arc<GlobalConfigCache>,
+    servers: Mutex<HashMap<SocketAddr, ServerState>>,
+}
+
+impl LiteServerPool {
+    pub fn new(db: Database, configs: Arc<GlobalConfigCache>) -> Self {
+        Self {
+            db,
+            configs,
+            servers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Start the periodic health check loop
+    pub fn spawn_health_checks(self: &Arc<Self>) {
+        let pool = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                pool.check_all().await;
+            }
+        });
+    }
+
+    /// Current health of all known liteservers, fastest first
+    pub fn health(&self) -> Vec<LiteServerHealth> {
+        let mut health: Vec<LiteServerHealth> = self
+            .servers
+            .lock()
+            .unwrap()
+            .values()
+            .map(|s| s.health.clone())
+            .collect();
+        health.sort_by_key(|h| (!h.healthy, h.latency_ms.unwrap_or(u64::MAX)));
+        health
+    }
+
+    /// Connect to `addr`, preferring a warm connection. If the server refuses,
+    /// reroute to the healthiest server that shares the pinned `public_key`,
+    /// or to the healthiest server at all when no key is pinned.
+    pub async fn connect(
+        self: &Arc<Self>,
+        addr: SocketAddr,
+        public_key: Option<&str>,
+    ) -> Result<PoolConnection, std::io::Error> {
+        if let Some(stream) = self.take_warm(&addr) {
+            trace!("Pool: using warm connection to {}", addr);
+            return Ok(self.connected(stream, addr));
+        }
+
+        let upstream = Upstream::load(&self.db);
+        let error = match dial(&upstream, addr).await {
+            Ok(stream) => return Ok(self.connected(stream, addr)),
+            Err(e) => e,
+        };
+
+        warn!("Pool: {} refused connection: {}", addr, error);
+        self.record_failure(&addr, &error.to_string());
+
+        for candidate in self.failover_candidates(&addr, public_key) {
+            let stream = match self.take_warm(&candidate) {
+                Some(stream) => stream,
+                None => match dial(&upstream, candidate).await {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        self.record_failure(&candidate, &e.to_string());
+                        continue;
+                    }
+                },
+            };
+            info!("Pool: rerouted {} -> {}", addr, candidate);
+            return Ok(self.connected(stream, candidate));
+        }
+
+        Err(error)
+    }
+
+    fn connected(&self, stream: TcpStream, addr: SocketAddr) -> PoolConnection {
+        let public_key = self
+            .servers
+            .lock()
+            .unwrap()
+            .get(&addr)
+            .map(|state| state.health.public_key.clone());
+        PoolConnection {
+            stream,
+            addr,
+            public_key,
+        }
+    }
+
+    /// Take the warm connection to `addr` and open its replacement
+    fn take_warm(self: &Arc<Self>, addr: &SocketAddr) -> Option<TcpStream> {
+        let (stream, opened_at) = self.servers.lock().unwrap().get_mut(addr)?.warm.take()?;
+        if opened_at.elapsed() >= WARM_MAX_AGE {
+            return None;
+        }
+
+        let pool = self.clone();
+        let addr = *addr;
+        tokio::spawn(async move {
+            let upstream = Upstream::load(&pool.db);
+            let Ok(stream) = dial(&upstream, addr).await else {
+                return;
+            };
+            if let Some(state) = pool.servers.lock().unwrap().get_mut(&addr) {
+                if state.health.healthy && state.warm.is_none() {
+                    state.warm = Some((stream, Instant::now()));
+                }
+            }
+        });
+        Some(stream)
+    }
+
+    fn failover_candidates(
+        &self,
+        failed: &SocketAddr,
+        public_key: Option<&str>,
+    ) -> Vec<SocketAddr> {
+        let servers = self.servers.lock().unwrap();
+        let mut candidates: Vec<(&SocketAddr, &ServerState)> = servers
+            .iter()
+            .filter(|(addr, state)| {
+                *addr != failed
+                    && state.health.healthy
+                    && public_key.is_none_or(|key| key == state.health.public_key)
+            })
+            .collect();
+        candidates.sort_by_key(|(_, state)| state.health.latency_ms.unwrap_or(u64::MAX));
+        candidates.into_iter().map(|(addr, _)| *addr).collect()
+    }
+
+    fn record_failure(&self, addr: &SocketAddr, error: &str) {
+        if let Some(state) = self.servers.lock().unwrap().get_mut(addr) {
+            state.health.healthy = false;
+            state.health.consecutive_failures += 1;
+            state.health.last_error = Some(error.to_string());
+            state.warm = None;
+        }
+    }
+
+    async fn check_all(&self) {
+        let network = match self.db.selected_network() {
+            Ok(Some(network)) => network,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Pool: failed to read selected network: {}", e);
+                return;
+            }
+        };
+
+        let liteservers = match self.configs.liteservers(&network.url).await {
+            Ok(liteservers) => liteservers,
+            Err(e) => {
+                warn!("Pool: {}", e);
+                return;
+            }
+        };
+
+        {
+            let mut servers = self.servers.lock().unwrap();
+            servers.retain(|addr, _| liteservers.iter().any(|ls| ls.addr() == *addr));
+            for ls in liteservers.iter() {
+                servers.entry(ls.addr()).or_insert_with(|| ServerState {
+                    health: LiteServerHealth {
+                        address: ls.addr().to_string(),
+                        public_key: ls.id.key.clone(),
+                        healthy: false,
+                        latency_ms: None,
+                        last_checked: None,
+                        consecutive_failures: 0,
+                        last_error: None,
+                    },
+                    warm: None,
+                });
+            }
+        }
+
+        let upstream = Upstream::load(&self.db);
+        let upstream = &upstream;
+        let checks = liteservers.iter().map(|ls| async move {
+            let result = match ls.public_key() {
+                Ok(key) => check_server(upstream, ls.addr(), &key).await,
+                Err(e) => Err(e),
+            };
+            let warm = match result {
+                Ok(_) => dial(upstream, ls.addr())
+                    .await
+                    .ok()
+                    .map(|stream| (stream, Instant::now())),
+                Err(_) => None,
+            };
+            (ls.addr(), result, warm)
+        });
+
+        for (addr, result, warm) in join_all(checks).await {
+            let mut servers = self.servers.lock().unwrap();
+            let Some(state) = servers.get_mut(&addr) else {
+                continue;
+            };
+            state.health.last_checked = Some(unix_millis());
+            match result {
+                Ok(latency) => {
+                    state.health.healthy = true;
+                    state.health.latency_ms = Some(latency.as_millis() as u64);
+                    state.health.consecutive_failures = 0;
+                    state.health.last_error = None;
+                    state.warm = warm;
+                }
+                Err(e) => {
+                    trace!("Pool: health check for {} failed: {}", addr, e);
+                    state.health.healthy = false;
+                    state.health.latency_ms = None;
+                    state.health.consecutive_failures += 1;
+                    state.health.last_error = Some(e);
+                    state.warm = None;
+                }
+            }
+        }
+    }
+}
+
+/// Open a TCP stream to `addr` through `upstream`, giving up after a timeout
+pub async fn dial(
+    upstream: &Upstream,
+    addr: SocketAddr,
+) -> Result<TcpStream, std::io::Error> {
+    timeout(CONNECT_TIMEOUT, upstream.connect(addr))
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "connect timed out"))?
+}
+
+/// Open an ADNL session and measure a `tcp.ping` round trip
+async fn check_server(
+    upstream: &Upstream,
+    addr: SocketAddr,
+    public_key: &[u8; 32],
+) -> Result<Duration, String> {
+    let stream = dial(upstream, addr).await.map_err(|e| e.to_string())?;
+    let conn = LiteConnection::handshake(stream, public_key)
+        .await
+        .map_err(|e| e.to_string())?;
+    conn.ping().await.map_err(|e| e.to_string())
+}
@@ -0,0 +1,81 @@
+//! Per-network tuning for proxy sessions.
+//!
+//! Read from the `settings` table when a session opens. The value is a JSON
+//! object stored under `proxy_session_config:<network_id>`, falling back to
+//! `proxy_session_config` and then to the defaults below. Missing fields use
+//! their defaults, so `{"idle_timeout_secs": 300}` is a valid setting.
+
+use crate::db::Database;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub const SESSION_CONFIG_SETTING: &str = "proxy_session_config";
+
+const MIN_READ_BUFFER: usize = 1024;
+const MAX_READ_BUFFER: usize = 1024 * 1024;
+/// Longest timeout or interval accepted. Larger values would overflow the
+/// `Instant` arithmetic of the session loops.
+const MAX_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// Close the session after this long without data from the webview
+    pub idle_timeout_secs: u64,
+    /// Size of the buffer used for each read from the liteserver
+    pub read_buffer_bytes: usize,
+    /// Interval between WebSocket pings. 0 disables pings.
+    pub ws_ping_interval_secs: u64,
+    /// Idle time before TCP keepalive probes start. 0 disables TCP keepalive.
+    pub tcp_keepalive_secs: u64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout_secs: 60,
+            read_buffer_bytes: 1024 * 3,
+            ws_ping_interval_secs: 0,
+            tcp_keepalive_secs: 0,
+        }
+    }
+}
+
+impl SessionConfig {
+    /// Load the config for the selected network
+    pub fn load(db: &Database) -> Self {
+        match db.get_network_setting(SESSION_CONFIG_SETTING) {
+            Ok(Some(raw)) => match serde_json::from_str::<SessionConfig>(&raw) {
+                Ok(config) => return config.clamped(),
+                Err(e) => warn!("Proxy session config: invalid setting: {}", e),
+            },
+            Ok(None) => {}
+            Err(e) => warn!("Proxy session config: failed to read setting: {}", e),
+        }
+
+        Self::default()
+    }
+
+    fn clamped(mut self) -> Self {
+        self.read_buffer_bytes = self
+            .read_buffer_bytes
+            .clamp(MIN_READ_BUFFER, MAX_READ_BUFFER);
+        self.idle_timeout_secs = self.idle_timeout_secs.clamp(1, MAX_INTERVAL_SECS);
+        self.ws_ping_interval_secs = self.ws_ping_interval_secs.min(MAX_INTERVAL_SECS);
+        self.tcp_keepalive_secs = self.tcp_keepalive_secs.min(MAX_INTERVAL_SECS);
+        self
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.idle_timeout_secs)
+    }
+
+    pub fn ws_ping_interval(&self) -> Option<Duration> {
+        (self.ws_ping_interval_secs > 0).then(|| Duration::from_secs(self.ws_ping_interval_secs))
+    }
+
+    pub fn tcp_keepalive(&self) -> Option<Duration> {
+        (self.tcp_keepalive_secs > 0).then(|| Duration::from_secs(self.tcp_keepalive_secs))
+    }
+}
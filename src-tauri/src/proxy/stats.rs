@@ -0,0 +1,252 @@
+//! Bandwidth and latency metrics for proxied liteserver sessions.
+//!
+//! Counters are kept per connection and aggregated per destination. The UI
+//! reads them through `get_proxy_stats` and receives periodic `proxy_stats`
+//! events while they change.
+
+use super::Direction;
+use crate::util::unix_millis;
+use log::info;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+
+const STATS_EVENT_INTERVAL: Duration = Duration::from_secs(5);
+/// Number of closed connections kept for display
+const CLOSED_HISTORY: usize = 100;
+
+/// Counters for a single proxied connection.
+/// `in` is liteserver to webview, `out` is webview to liteserver.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionStats {
+    pub id: u64,
+    pub peer: String,
+    pub destination: String,
+    pub opened_at: i64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub messages_in: u64,
+    pub messages_out: u64,
+    pub first_byte_ms: Option<u64>,
+    pub open_ms: u64,
+    pub close_reason: Option<String>,
+}
+
+/// Counters aggregated over all connections to one liteserver
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DestinationStats {
+    pub destination: String,
+    pub connections: u64,
+    pub open_connections: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub messages_in: u64,
+    pub messages_out: u64,
+    pub avg_first_byte_ms: Option<u64>,
+    pub last_close_reason: Option<String>,
+    #[serde(skip)]
+    first_byte_total_ms: u64,
+    #[serde(skip)]
+    first_byte_samples: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProxyStatsSnapshot {
+    pub connections: Vec<ConnectionStats>,
+    pub destinations: Vec<DestinationStats>,
+}
+
+struct OpenConnection {
+    stats: ConnectionStats,
+    started: Instant,
+}
+
+#[derive(Default)]
+struct StatsInner {
+    open: HashMap<u64, OpenConnection>,
+    closed: VecDeque<ConnectionStats>,
+    destinations: HashMap<String, DestinationStats>,
+    /// Bumped on every change so idle periods do not produce events
+    version: u64,
+}
+
+#[derive(Default)]
+pub struct ProxyStats {
+    inner: Mutex<StatsInner>,
+    next_id: AtomicU64,
+}
+
+/// Records metrics for one connection. Shared by both relay directions.
+#[derive(Clone)]
+pub struct ConnectionHandle {
+    id: u64,
+    stats: Arc<ProxyStats>,
+}
+
+impl ProxyStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a new connection
+    pub fn open(self: &Arc<Self>, peer: SocketAddr, destination: SocketAddr) -> ConnectionHandle {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let destination = destination.to_string();
+
+        let mut inner = self.inner.lock().unwrap();
+        let dest = inner
+            .destinations
+            .entry(destination.clone())
+            .or_insert_with(|| DestinationStats {
+                destination: destination.clone(),
+                ..Default::default()
+            });
+        dest.connections += 1;
+        dest.open_connections += 1;
+
+        inner.open.insert(
+            id,
+            OpenConnection {
+                stats: ConnectionStats {
+                    id,
+                    peer: peer.to_string(),
+                    destination,
+                    opened_at: unix_millis(),
+                    bytes_in: 0,
+                    bytes_out: 0,
+                    messages_in: 0,
+                    messages_out: 0,
+                    first_byte_ms: None,
+                    open_ms: 0,
+                    close_reason: None,
+                },
+                started: Instant::now(),
+            },
+        );
+        inner.version += 1;
+
+        ConnectionHandle {
+            id,
+            stats: self.clone(),
+        }
+    }
+
+    pub fn snapshot(&self) -> ProxyStatsSnapshot {
+        let inner = self.inner.lock().unwrap();
+
+        let mut connections: Vec<ConnectionStats> = inner
+            .open
+            .values()
+            .map(|conn| {
+                let mut stats = conn.stats.clone();
+                stats.open_ms = conn.started.elapsed().as_millis() as u64;
+                stats
+            })
+            .collect();
+        connections.sort_by_key(|c| c.id);
+        connections.extend(inner.closed.iter().rev().cloned());
+
+        let mut destinations: Vec<DestinationStats> =
+            inner.destinations.values().cloned().collect();
+        destinations.sort_by(|a, b| a.destination.cmp(&b.destination));
+
+        ProxyStatsSnapshot {
+            connections,
+            destinations,
+        }
+    }
+
+    /// Push a `proxy_stats` event every few seconds while the counters change
+    pub fn spawn_event_stream(self: &Arc<Self>, app_handle: AppHandle) {
+        let stats = self.clone();
+        tokio::spawn(async move {
+            let mut last_version = 0;
+            let mut interval = tokio::time::interval(STATS_EVENT_INTERVAL);
+            loop {
+                interval.tick().await;
+
+                let (version, has_open) = {
+                    let inner = stats.inner.lock().unwrap();
+                    (inner.version, !inner.open.is_empty())
+                };
+                if version == last_version && !has_open {
+                    continue;
+                }
+                last_version = version;
+
+                if let Err(e) = app_handle.emit("proxy_stats", stats.snapshot()) {
+                    info!("Error emitting proxy_stats: {:?}", e);
+                }
+            }
+        });
+    }
+}
+
+impl ConnectionHandle {
+    /// Count one relayed message of `len` bytes
+    pub fn record(&self, dir: Direction, len: usize) {
+        let mut inner = self.stats.inner.lock().unwrap();
+        let inner = &mut *inner;
+        let Some(conn) = inner.open.get_mut(&self.id) else {
+            return;
+        };
+        let dest = inner.destinations.get_mut(&conn.stats.destination);
+
+        match dir {
+            Direction::ClientToServer => {
+                conn.stats.bytes_out += len as u64;
+                conn.stats.messages_out += 1;
+                if let Some(dest) = dest {
+                    dest.bytes_out += len as u64;
+                    dest.messages_out += 1;
+                }
+            }
+            Direction::ServerToClient => {
+                let first_byte = conn.stats.first_byte_ms.is_none();
+                if first_byte {
+                    conn.stats.first_byte_ms = Some(conn.started.elapsed().as_millis() as u64);
+                }
+                conn.stats.bytes_in += len as u64;
+                conn.stats.messages_in += 1;
+                if let Some(dest) = dest {
+                    dest.bytes_in += len as u64;
+                    dest.messages_in += 1;
+                    if first_byte {
+                        dest.first_byte_total_ms += conn.stats.first_byte_ms.unwrap_or(0);
+                        dest.first_byte_samples += 1;
+                        dest.avg_first_byte_ms =
+                            Some(dest.first_byte_total_ms / dest.first_byte_samples);
+                    }
+                }
+            }
+        }
+        inner.version += 1;
+    }
+
+    /// Mark the connection closed. Only the first reason is kept.
+    pub fn close(&self, reason: &str) {
+        let mut inner = self.stats.inner.lock().unwrap();
+        let Some(conn) = inner.open.remove(&self.id) else {
+            return;
+        };
+
+        let mut stats = conn.stats;
+        stats.open_ms = conn.started.elapsed().as_millis() as u64;
+        stats.close_reason = Some(reason.to_string());
+
+        if let Some(dest) = inner.destinations.get_mut(&stats.destination) {
+            dest.open_connections = dest.open_connections.saturating_sub(1);
+            dest.last_close_reason = Some(reason.to_string());
+        }
+
+        inner.closed.push_back(stats);
+        while inner.closed.len() > CLOSED_HISTORY {
+            inner.closed.pop_front();
+        }
+        inner.version += 1;
+    }
+}
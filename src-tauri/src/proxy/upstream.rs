@@ -0,0 +1,529 @@
+//! Upstream proxy used to dial liteservers.
+//!
+//! Configured per network through the `proxy_upstream` setting (see
+//! `Database::get_network_setting`), as JSON:
+//!
+//! - `{"type": "direct"}` (the default)
+//! - `{"type": "socks5", "address": "127.0.0.1:1080", "username": "u", "password": "p"}`
+//! - `{"type": "http_connect", "address": "proxy.corp:3128", "username": "u", "password": "p"}`
+//!
+//! Credentials are optional. SOCKS5 uses username/password auth (RFC 1929),
+//! HTTP CONNECT sends them as `Proxy-Authorization: Basic`.
+
+use crate::db::Database;
+use base64::{engine::general_purpose, Engine as _};
+use log::warn;
+use serde::Deserialize;
+use std::io::{Error, ErrorKind};
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+pub const UPSTREAM_SETTING: &str = "proxy_upstream";
+
+/// Upper bound for the HTTP CONNECT response head
+const MAX_CONNECT_RESPONSE: usize = 8 * 1024;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Upstream {
+    #[default]
+    Direct,
+    Socks5 {
+        address: String,
+        username: Option<String>,
+        password: Option<String>,
+    },
+    HttpConnect {
+        address: String,
+        username: Option<String>,
+        password: Option<String>,
+    },
+}
+
+impl Upstream {
+    /// Load the upstream for the selected network
+    pub fn load(db: &Database) -> Self {
+        match db.get_network_setting(UPSTREAM_SETTING) {
+            Ok(Some(raw)) => match serde_json::from_str::<Upstream>(&raw) {
+                Ok(upstream) => return upstream,
+                Err(e) => warn!("Proxy upstream: invalid setting: {}", e),
+            },
+            Ok(None) => {}
+            Err(e) => warn!("Proxy upstream: failed to read setting: {}", e),
+        }
+
+        Self::Direct
+    }
+
+    /// Open a TCP stream to `target`, tunnelled through the upstream if one is set
+    pub async fn connect(&self, target: SocketAddr) -> Result<TcpStream, Error> {
+        match self {
+            Upstream::Direct => TcpStream::connect(target).await,
+            Upstream::Socks5 {
+                address,
+                username,
+                password,
+            } => {
+                let stream = TcpStream::connect(address.as_str()).await?;
+                socks5_connect(stream, target, credentials(username, password)).await
+            }
+            Upstream::HttpConnect {
+                address,
+                username,
+                password,
+            } => {
+                let stream = TcpStream::connect(address.as_str()).await?;
+                http_connect(stream, target, credentials(username, password)).await
+            }
+        }
+    }
+}
+
+fn credentials<'a>(
+    username: &'a Option<String>,
+    password: &'a Option<String>,
+) -> Option<(&'a str, &'a str)> {
+    username
+        .as_deref()
+        .map(|user| (user, password.as_deref().unwrap_or("")))
+}
+
+fn upstream_error(message: String) -> Error {
+    Error::new(ErrorKind::ConnectionRefused, message)
+}
+
+async fn socks5_connect(
+    mut stream: TcpStream,
+    target: SocketAddr,
+    auth: Option<(&str, &str)>,
+) -> Result<TcpStream, Error> {
+    // Greeting: offer "no auth", plus username/password when configured
+    let greeting: &[u8] = match auth {
+        Some(_) => &[0x05, 0x02, 0x00, 0x02],
+        None => &[0x05, 0x01, 0x00],
+    };
+    stream.write_all(greeting).await?;
+
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).await?;
+    if choice[0] != 0x05 {
+        return Err(upstream_error(format!(
+            "SOCKS5 upstream replied with version {}",
+            choice[0]
+        )));
+    }
+
+    match (choice[1], auth) {
+        (0x00, _) => {}
+        (0x02, Some((username, password))) => {
+            if username.len() > 255 || password.len() > 255 {
+                return Err(upstream_error(
+                    "SOCKS5 username and password must be at most 255 bytes".to_string(),
+                ));
+            }
+            let mut request = vec![0x01, username.len() as u8];
+            request.extend_from_slice(username.as_bytes());
+            request.push(password.len() as u8);
+            request.extend_from_slice(password.as_bytes());
+            stream.write_all(&request).await?;
+
+            let mut status = [0u8; 2];
+            stream.read_exact(&mut status).await?;
+            if status[0] != 0x01 {
+                return Err(upstream_error(format!(
+                    "SOCKS5 upstream replied to the credentials with version {}",
+                    status[0]
+                )));
+            }
+            if status[1] != 0x00 {
+                return Err(upstream_error(
+                    "SOCKS5 upstream rejected the credentials".to_string(),
+                ));
+            }
+        }
+        (method, _) => {
+            return Err(upstream_error(format!(
+                "SOCKS5 upstream requires an unsupported auth method ({:#04x})",
+                method
+            )));
+        }
+    }
+
+    let mut request = vec![0x05, 0x01, 0x00];
+    match target {
+        SocketAddr::V4(addr) => {
+            request.push(0x01);
+            request.extend_from_slice(&addr.ip().octets());
+        }
+        SocketAddr::V6(addr) => {
+            request.push(0x04);
+            request.extend_from_slice(&addr.ip().octets());
+        }
+    }
+    request.extend_from_slice(&target.port().to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0x00 {
+        return Err(upstream_error(format!(
+            "SOCKS5 upstream could not reach {}: {}",
+            target,
+            socks5_reply_message(reply[1])
+        )));
+    }
+
+    // Skip the bound address, the tunnel starts right after it
+    let bound_len = match reply[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => stream.read_u8().await? as usize,
+        atyp => {
+            return Err(upstream_error(format!(
+                "SOCKS5 upstream replied with unknown address type {}",
+                atyp
+            )));
+        }
+    };
+    let mut bound = vec![0u8; bound_len + 2];
+    stream.read_exact(&mut bound).await?;
+
+    Ok(stream)
+}
+
+fn socks5_reply_message(code: u8) -> &'static str {
+    match code {
+        0x01 => "general failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+async fn http_connect(
+    mut stream: TcpStream,
+    target: SocketAddr,
+    auth: Option<(&str, &str)>,
+) -> Result<TcpStream, Error> {
+    let mut request = format!(
+        "CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n",
+        target = target
+    );
+    if let Some((username, password)) = auth {
+        let token = general_purpose::STANDARD.encode(format!("{}:{}", username, password));
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read the response head byte by byte so no tunnel data is consumed
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_CONNECT_RESPONSE {
+            return Err(upstream_error(
+                "HTTP upstream sent an oversized CONNECT response".to_string(),
+            ));
+        }
+        head.push(stream.read_u8().await?);
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let status_line = head.lines().next().unwrap_or_default();
+    let status = status_line.split_whitespace().nth(1).unwrap_or_default();
+    if !status.starts_with('2') {
+        return Err(upstream_error(format!(
+            "HTTP upstream refused CONNECT to {}: {}",
+            target, status_line
+        )));
+    }
+
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    const USERNAME: &str = "wallet";
+    const PASSWORD: &str = "s3cret";
+
+    /// How the stand-in SOCKS5 server answers the credentials
+    #[derive(Clone, Copy)]
+    enum AuthReply {
+        Checked,
+        WrongVersion,
+    }
+
+    /// A server that echoes everything back, the liteserver of the tests
+    async fn echo_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (mut rx, mut tx) = stream.split();
+                    let _ = tokio::io::copy(&mut rx, &mut tx).await;
+                });
+            }
+        });
+        addr
+    }
+
+    /// A SOCKS5 server that requires the test credentials when `auth` is set
+    async fn socks5_server(auth: Option<AuthReply>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(socks5_session(stream, auth));
+            }
+        });
+        addr
+    }
+
+    async fn socks5_session(mut stream: TcpStream, auth: Option<AuthReply>) {
+        let mut head = [0u8; 2];
+        stream.read_exact(&mut head).await.unwrap();
+        assert_eq!(head[0], 0x05);
+        let mut methods = vec![0u8; head[1] as usize];
+        stream.read_exact(&mut methods).await.unwrap();
+
+        match auth {
+            None => stream.write_all(&[0x05, 0x00]).await.unwrap(),
+            Some(reply) => {
+                if !methods.contains(&0x02) {
+                    stream.write_all(&[0x05, 0xff]).await.unwrap();
+                    return;
+                }
+                stream.write_all(&[0x05, 0x02]).await.unwrap();
+
+                assert_eq!(stream.read_u8().await.unwrap(), 0x01);
+                let mut username = vec![0u8; stream.read_u8().await.unwrap() as usize];
+                stream.read_exact(&mut username).await.unwrap();
+                let mut password = vec![0u8; stream.read_u8().await.unwrap() as usize];
+                stream.read_exact(&mut password).await.unwrap();
+
+                let accepted = username == USERNAME.as_bytes() && password == PASSWORD.as_bytes();
+                let status = [
+                    match reply {
+                        AuthReply::Checked => 0x01,
+                        AuthReply::WrongVersion => 0x05,
+                    },
+                    if accepted { 0x00 } else { 0x01 },
+                ];
+                stream.write_all(&status).await.unwrap();
+                if !accepted {
+                    return;
+                }
+            }
+        }
+
+        let mut request = [0u8; 4];
+        if stream.read_exact(&mut request).await.is_err() {
+            return;
+        }
+        assert_eq!(request[..3], [0x05, 0x01, 0x00]);
+        assert_eq!(request[3], 0x01, "tests connect to IPv4 targets");
+        let mut target = [0u8; 6];
+        stream.read_exact(&mut target).await.unwrap();
+        let ip = std::net::Ipv4Addr::new(target[0], target[1], target[2], target[3]);
+        let port = u16::from_be_bytes([target[4], target[5]]);
+
+        let mut upstream = match TcpStream::connect((ip, port)).await {
+            Ok(upstream) => upstream,
+            Err(_) => {
+                let _ = stream
+                    .write_all(&[0x05, 0x05, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+                    .await;
+                return;
+            }
+        };
+        // Bound to a domain name, so the client has to skip a variable length
+        let mut reply = vec![0x05, 0x00, 0x00, 0x03, 9];
+        reply.extend_from_slice(b"localhost");
+        reply.extend_from_slice(&1080u16.to_be_bytes());
+        stream.write_all(&reply).await.unwrap();
+        let _ = tokio::io::copy_bidirectional(&mut stream, &mut upstream).await;
+    }
+
+    /// An HTTP proxy that only answers CONNECT, and requires the test
+    /// credentials when `auth` is set
+    async fn http_connect_server(auth: bool) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut head = Vec::new();
+                    while !head.ends_with(b"\r\n\r\n") {
+                        head.push(stream.read_u8().await.unwrap());
+                    }
+                    let head = String::from_utf8(head).unwrap();
+                    let mut lines = head.lines();
+                    let request_line = lines.next().unwrap().to_string();
+                    let target = request_line
+                        .strip_prefix("CONNECT ")
+                        .and_then(|rest| rest.strip_suffix(" HTTP/1.1"))
+                        .unwrap()
+                        .to_string();
+
+                    let token =
+                        general_purpose::STANDARD.encode(format!("{}:{}", USERNAME, PASSWORD));
+                    let expected = format!("Proxy-Authorization: Basic {}", token);
+                    if auth && !lines.any(|line| line == expected) {
+                        let _ = stream
+                            .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+                            .await;
+                        return;
+                    }
+
+                    let mut upstream = TcpStream::connect(target.as_str()).await.unwrap();
+                    stream
+                        .write_all(b"HTTP/1.1 200 Connection established\r\nVia: test\r\n\r\n")
+                        .await
+                        .unwrap();
+                    let _ = tokio::io::copy_bidirectional(&mut stream, &mut upstream).await;
+                });
+            }
+        });
+        addr
+    }
+
+    fn socks5(address: SocketAddr, password: Option<&str>) -> Upstream {
+        Upstream::Socks5 {
+            address: address.to_string(),
+            username: password.map(|_| USERNAME.to_string()),
+            password: password.map(str::to_string),
+        }
+    }
+
+    fn http(address: SocketAddr, password: Option<&str>) -> Upstream {
+        Upstream::HttpConnect {
+            address: address.to_string(),
+            username: password.map(|_| USERNAME.to_string()),
+            password: password.map(str::to_string),
+        }
+    }
+
+    /// Connect through `upstream` and check bytes make the round trip
+    async fn assert_tunnels(upstream: Upstream, target: SocketAddr) {
+        let mut stream = upstream.connect(target).await.unwrap();
+        stream.write_all(b"adnl bytes").await.unwrap();
+        let mut echoed = [0u8; 10];
+        stream.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"adnl bytes");
+    }
+
+    fn assert_refused(result: Result<TcpStream, Error>, message: &str) {
+        match result {
+            Err(e) => {
+                assert_eq!(e.kind(), ErrorKind::ConnectionRefused);
+                assert!(e.to_string().contains(message), "unexpected error: {}", e);
+            }
+            Ok(_) => panic!("connected, expected: {}", message),
+        }
+    }
+
+    #[tokio::test]
+    async fn socks5_without_auth() {
+        let target = echo_server().await;
+        let proxy = socks5_server(None).await;
+        assert_tunnels(socks5(proxy, None), target).await;
+    }
+
+    #[tokio::test]
+    async fn socks5_with_auth() {
+        let target = echo_server().await;
+        let proxy = socks5_server(Some(AuthReply::Checked)).await;
+        assert_tunnels(socks5(proxy, Some(PASSWORD)), target).await;
+    }
+
+    #[tokio::test]
+    async fn socks5_rejects_wrong_credentials() {
+        let target = echo_server().await;
+        let proxy = socks5_server(Some(AuthReply::Checked)).await;
+        let result = socks5(proxy, Some("wrong")).connect(target).await;
+        assert_refused(result, "rejected the credentials");
+    }
+
+    #[tokio::test]
+    async fn socks5_requires_credentials_when_the_server_does() {
+        let target = echo_server().await;
+        let proxy = socks5_server(Some(AuthReply::Checked)).await;
+        let result = socks5(proxy, None).connect(target).await;
+        assert_refused(result, "unsupported auth method (0xff)");
+    }
+
+    #[tokio::test]
+    async fn socks5_checks_the_auth_reply_version() {
+        let target = echo_server().await;
+        let proxy = socks5_server(Some(AuthReply::WrongVersion)).await;
+        let result = socks5(proxy, Some(PASSWORD)).connect(target).await;
+        assert_refused(result, "replied to the credentials with version 5");
+    }
+
+    #[tokio::test]
+    async fn socks5_reports_an_unreachable_target() {
+        // Bound and dropped, so nothing listens there
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let proxy = socks5_server(None).await;
+        let result = socks5(proxy, None).connect(closed).await;
+        assert_refused(result, "connection refused");
+    }
+
+    #[tokio::test]
+    async fn http_connect_without_auth() {
+        let target = echo_server().await;
+        let proxy = http_connect_server(false).await;
+        assert_tunnels(http(proxy, None), target).await;
+    }
+
+    #[tokio::test]
+    async fn http_connect_with_auth() {
+        let target = echo_server().await;
+        let proxy = http_connect_server(true).await;
+        assert_tunnels(http(proxy, Some(PASSWORD)), target).await;
+    }
+
+    #[tokio::test]
+    async fn http_connect_reports_a_refusal() {
+        let target = echo_server().await;
+        let proxy = http_connect_server(true).await;
+        let result = http(proxy, Some("wrong")).connect(target).await;
+        assert_refused(result, "407 Proxy Authentication Required");
+    }
+
+    #[test]
+    fn parses_the_setting() {
+        let upstream: Upstream = serde_json::from_str(
+            r#"{"type": "socks5", "address": "127.0.0.1:1080", "username": "u"}"#,
+        )
+        .unwrap();
+        match upstream {
+            Upstream::Socks5 {
+                address,
+                username,
+                password,
+            } => {
+                assert_eq!(address, "127.0.0.1:1080");
+                assert_eq!(credentials(&username, &password), Some(("u", "")));
+            }
+            other => panic!("parsed as {:?}", other),
+        }
+        assert!(matches!(
+            serde_json::from_str(r#"{"type": "direct"}"#).unwrap(),
+            Upstream::Direct
+        ));
+    }
+}
@@ -0,0 +1,316 @@
+//! Multi-pass decoding.
+//!
+//! One pass with the hybrid binarizer reads most codes. Dark mode codes,
+//! light modules on a dark background, are only read with the luminance
+//! inverted, and flat low-contrast renderings are read better by the global
+//! histogram binarizer. These passes are cheap and always run.
+//!
+//! Only when none of them finds a code, the image is rescaled and cut into
+//! overlapping tiles. That finds codes that are a small part of a large
+//! screen, or too blurry at their native size, without slowing down the
+//! common case of one clearly visible code.
+
+use super::BoundingBox;
+use image::imageops::FilterType;
+use image::DynamicImage;
+use rxing::multi::MultipleBarcodeReader;
+use std::collections::HashMap;
+
+/// Scale factors tried when the native size finds nothing
+const RESCALES: [f32; 2] = [0.5, 2.0];
+/// Bounds for the longer side of a rescaled image
+const MIN_SIDE: u32 = 400;
+const MAX_SIDE: u32 = 6000;
+/// Tiles per row and per column
+const TILE_GRID: u32 = 3;
+/// Tiles with a shorter longer side are upscaled twice
+const TILE_UPSCALE_BELOW: u32 = 1000;
+
+/// A code in the coordinates of the decoded image
+pub struct Decoded {
+    pub text: String,
+    pub format: String,
+    pub bbox: Option<BoundingBox>,
+}
+
+impl Decoded {
+    /// Whether both are the same code, found by different passes
+    fn same_code(&self, other: &Decoded) -> bool {
+        if self.text != other.text || self.format != other.format {
+            return false;
+        }
+        match (self.bbox, other.bbox) {
+            (Some(a), Some(b)) => {
+                let tolerance = a.width.max(a.height).max(b.width).max(b.height) / 2.0 + 8.0;
+                let (ax, ay) = (a.x + a.width / 2.0, a.y + a.height / 2.0);
+                let (bx, by) = (b.x + b.width / 2.0, b.y + b.height / 2.0);
+                (ax - bx).abs() <= tolerance && (ay - by).abs() <= tolerance
+            }
+            _ => true,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Binarizer {
+    Hybrid,
+    GlobalHistogram,
+}
+
+/// Every code in the image, each once
+pub fn decode(image: &DynamicImage) -> Vec<Decoded> {
+    let luma = DynamicImage::ImageLuma8(image.to_luma8());
+    let (width, height) = (luma.width(), luma.height());
+    let mut found = Found::default();
+
+    found.pass(luma.clone(), Binarizer::Hybrid, 1.0, (0, 0));
+    let mut inverted = luma.clone();
+    inverted.invert();
+    found.pass(inverted, Binarizer::Hybrid, 1.0, (0, 0));
+    found.pass(luma.clone(), Binarizer::GlobalHistogram, 1.0, (0, 0));
+    if !found.codes.is_empty() {
+        return found.codes;
+    }
+
+    for scale in RESCALES {
+        let w = (width as f32 * scale) as u32;
+        let h = (height as f32 * scale) as u32;
+        if w == 0 || h == 0 || w.max(h) < MIN_SIDE || w.max(h) > MAX_SIDE {
+            continue;
+        }
+        // Nearest keeps module edges sharp when upscaling
+        let filter = if scale < 1.0 {
+            FilterType::Triangle
+        } else {
+            FilterType::Nearest
+        };
+        found.pass(
+            luma.resize_exact(w, h, filter),
+            Binarizer::Hybrid,
+            scale,
+            (0, 0),
+        );
+    }
+
+    // Tiles of small images are no larger than the rescaled image above
+    if width.max(height) < 2 * MIN_SIDE {
+        return found.codes;
+    }
+    for (x, y, w, h) in tiles(width, height) {
+        let tile = luma.crop_imm(x, y, w, h);
+        if w.max(h) < TILE_UPSCALE_BELOW {
+            let tile = tile.resize_exact(w * 2, h * 2, FilterType::Nearest);
+            found.pass(tile, Binarizer::Hybrid, 2.0, (x, y));
+        } else {
+            found.pass(tile, Binarizer::Hybrid, 1.0, (x, y));
+        }
+    }
+
+    found.codes
+}
+
+#[derive(Default)]
+struct Found {
+    codes: Vec<Decoded>,
+}
+
+impl Found {
+    /// Decode `image`, which shows the original at `scale` from `offset` on
+    fn pass(&mut self, image: DynamicImage, binarizer: Binarizer, scale: f32, offset: (u32, u32)) {
+        for result in read(image, binarizer) {
+            let bbox = bounding_box(result.getPoints()).map(|bbox| BoundingBox {
+                x: bbox.x / scale + offset.0 as f32,
+                y: bbox.y / scale + offset.1 as f32,
+                width: bbox.width / scale,
+                height: bbox.height / scale,
+            });
+            let code = Decoded {
+                text: result.getText().to_string(),
+                format: format!("{:?}", result.getBarcodeFormat()),
+                bbox,
+            };
+            if !self.codes.iter().any(|found| found.same_code(&code)) {
+                self.codes.push(code);
+            }
+        }
+    }
+}
+
+fn read(image: DynamicImage, binarizer: Binarizer) -> Vec<rxing::RXingResult> {
+    let multi_format_reader = rxing::MultiUseMultiFormatReader::default();
+    let mut scanner = rxing::multi::GenericMultipleBarcodeReader::new(multi_format_reader);
+    let mut hints = HashMap::new();
+
+    hints
+        .entry(rxing::DecodeHintType::TRY_HARDER)
+        .or_insert(rxing::DecodeHintValue::TryHarder(true));
+
+    let source = rxing::BufferedImageLuminanceSource::new(image);
+    let results = match binarizer {
+        Binarizer::Hybrid => scanner.decode_multiple_with_hints(
+            &mut rxing::BinaryBitmap::new(rxing::common::HybridBinarizer::new(source)),
+            &mut hints,
+        ),
+        Binarizer::GlobalHistogram => scanner.decode_multiple_with_hints(
+            &mut rxing::BinaryBitmap::new(rxing::common::GlobalHistogramBinarizer::new(source)),
+            &mut hints,
+        ),
+    };
+    results.unwrap_or_default()
+}
+
+/// Overlapping tiles covering the image, so a code cut by the edge of one
+/// tile is whole in its neighbour
+fn tiles(width: u32, height: u32) -> Vec<(u32, u32, u32, u32)> {
+    let spans = |len: u32| -> Vec<(u32, u32)> {
+        let step = len.div_ceil(TILE_GRID);
+        let size = (step + step / 2).min(len);
+        let mut spans: Vec<(u32, u32)> = (0..TILE_GRID)
+            .map(|i| ((i * step).saturating_sub(step / 4).min(len - size), size))
+            .collect();
+        spans.dedup();
+        spans
+    };
+
+    let columns = spans(width);
+    let mut tiles = Vec::new();
+    for (y, h) in spans(height) {
+        for &(x, w) in &columns {
+            if w > 0 && h > 0 {
+                tiles.push((x, y, w, h));
+            }
+        }
+    }
+    tiles
+}
+
+fn bounding_box(points: &[rxing::Point]) -> Option<BoundingBox> {
+    let first = points.first()?;
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (first.x, first.y, first.x, first.y);
+    for point in &points[1..] {
+        min_x = min_x.min(point.x);
+        min_y = min_y.min(point.y);
+        max_x = max_x.max(point.x);
+        max_y = max_y.max(point.y);
+    }
+    Some(BoundingBox {
+        x: min_x,
+        y: min_y,
+        width: max_x - min_x,
+        height: max_y - min_y,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The link every fixture encodes
+    const LINK: &str = "tc://?v=2&id=230f1e4df32364888a5dbd92a410266fcb974b73e30ff3e546a654fc8ee2c953&r=%7B%22manifestUrl%22%3A%22https%3A%2F%2Fdapp.example%2Ftonconnect-manifest.json%22%2C%22items%22%3A%5B%7B%22name%22%3A%22ton_addr%22%7D%5D%7D&ret=none";
+
+    fn decode_fixture(png: &[u8]) -> Vec<Decoded> {
+        decode(&image::load_from_memory(png).unwrap())
+    }
+
+    /// Assert a single code was found, centred within `tolerance` pixels
+    fn assert_found(codes: &[Decoded], center: (f32, f32), tolerance: f32) {
+        assert_eq!(codes.len(), 1, "expected one code");
+        let code = &codes[0];
+        assert_eq!(code.text, LINK);
+        assert_eq!(code.format, "QR_CODE");
+
+        let bbox = code.bbox.expect("no position");
+        let (x, y) = (bbox.x + bbox.width / 2.0, bbox.y + bbox.height / 2.0);
+        assert!(
+            (x - center.0).abs() <= tolerance && (y - center.1).abs() <= tolerance,
+            "code found at ({}, {}), expected ({}, {})",
+            x,
+            y,
+            center.0,
+            center.1
+        );
+    }
+
+    // The fixtures are browser pages rendered from SVG, not screen captures:
+    // antialiased text, rounded modules and finders, the TON logo over the
+    // centre of the code, and fractional module sizes from display scaling
+
+    #[test]
+    fn finds_a_small_code_on_a_5k_screen() {
+        // A connect widget in a browser window on a 5120x2880 desktop at
+        // 100% scaling, 2.9 pixel modules
+        let codes = decode_fixture(include_bytes!("fixtures/small_code_5k.png"));
+        assert_found(&codes, (4450.0, 890.0), 12.0);
+    }
+
+    #[test]
+    fn finds_a_dark_mode_code() {
+        // Light modules on the modal of a dark theme page at 150% scaling
+        let codes = decode_fixture(include_bytes!("fixtures/dark_mode.png"));
+        assert_found(&codes, (960.0, 582.0), 20.0);
+    }
+
+    #[test]
+    fn finds_a_low_contrast_code() {
+        // Gray modules on a gray card at 125% scaling
+        let codes = decode_fixture(include_bytes!("fixtures/low_contrast.png"));
+        assert_found(&codes, (337.5, 512.5), 20.0);
+    }
+
+    #[test]
+    fn finds_a_code_under_an_overlay() {
+        // The backdrop of a cookie dialog dims the page and a toast hides
+        // part of the code
+        let codes = decode_fixture(include_bytes!("fixtures/covered.png"));
+        assert_found(&codes, (780.0, 360.0), 20.0);
+    }
+
+    #[test]
+    fn finds_nothing_in_a_blank_image() {
+        let blank = DynamicImage::ImageLuma8(image::GrayImage::from_pixel(
+            1600,
+            1000,
+            image::Luma([0xf0]),
+        ));
+        assert!(decode(&blank).is_empty());
+    }
+
+    #[test]
+    fn tiles_cover_the_image_and_overlap() {
+        for (width, height) in [(800, 800), (1001, 799), (5120, 2880), (6016, 3384)] {
+            let tiles = tiles(width, height);
+            assert_eq!(tiles.len(), (TILE_GRID * TILE_GRID) as usize);
+            for &(x, y, w, h) in &tiles {
+                assert!(x + w <= width && y + h <= height);
+            }
+
+            // Every row and column is in some tile, and neighbours overlap
+            // by at least a quarter of a step
+            let step = width.div_ceil(TILE_GRID);
+            let mut columns: Vec<(u32, u32)> = tiles.iter().map(|t| (t.0, t.2)).collect();
+            columns.sort_unstable();
+            columns.dedup();
+            assert_eq!(columns[0].0, 0);
+            assert_eq!(columns.last().map(|(x, w)| x + w), Some(width));
+            for pair in columns.windows(2) {
+                assert!(pair[0].0 + pair[0].1 >= pair[1].0 + step / 4);
+            }
+        }
+    }
+
+    #[test]
+    fn same_code_tolerates_small_shifts() {
+        let code = |x: f32| Decoded {
+            text: LINK.to_string(),
+            format: "QR_CODE".to_string(),
+            bbox: Some(BoundingBox {
+                x,
+                y: 100.0,
+                width: 40.0,
+                height: 40.0,
+            }),
+        };
+        assert!(code(100.0).same_code(&code(110.0)));
+        assert!(!code(100.0).same_code(&code(400.0)));
+    }
+}
@@ -0,0 +1,314 @@
+//! QR code detection on screenshots and pasted images.
+//!
+//! Every decoded code is returned, not just the first one, so the webview can
+//! let the user choose when several dapps show a code at once. Codes are
+//! classified by what the wallet can do with them and sorted TonConnect links
+//! first. Failures are returned as a `QrError` so the webview can tell a
+//! missing screen recording permission from a corrupt image.
+//!
+//! Screens, encoded images of any format the `image` crate reads, raw RGBA
+//! buffers and image files all go through the same `scan`. Scans limited to
+//! part of the screen live in `target`.
+
+mod decode;
+pub mod target;
+
+use crate::ton_address::TonAddress;
+use crate::tonconnect_link;
+use base64::{engine::general_purpose, Engine as _};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use xcap::Monitor;
+
+/// A decoded code
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetectedQrCode {
+    pub text: String,
+    pub kind: QrCodeKind,
+    /// Barcode format as named by rxing, e.g. `QR_CODE`
+    pub format: String,
+    /// Index of the screen in the capture order. None for pasted images.
+    pub screen: Option<usize>,
+    /// Display ID of the screen, which unlike the index survives
+    /// reconnecting screens. None for pasted images and windows.
+    pub screen_id: Option<u32>,
+    /// Area spanned by the code's finder points, in pixels of the scanned
+    /// image. Screen regions are scanned as part of the whole screen.
+    pub bbox: Option<BoundingBox>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct BoundingBox {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// What the text of a code is, in the order codes are returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QrCodeKind {
+    /// A connect link `tonconnect_link::parse` accepts
+    TonConnect,
+    /// A `ton://transfer/…` payment link
+    TonTransfer,
+    /// A raw or user-friendly address
+    Address,
+    Other,
+}
+
+pub fn classify(text: &str) -> QrCodeKind {
+    let text = text.trim();
+    if tonconnect_link::parse(text).is_ok() {
+        QrCodeKind::TonConnect
+    } else if text
+        .get(..15)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("ton://transfer/"))
+    {
+        QrCodeKind::TonTransfer
+    } else if TonAddress::parse(text).is_ok() {
+        QrCodeKind::Address
+    } else {
+        QrCodeKind::Other
+    }
+}
+
+/// Why a scan failed. Sent to the webview as `{ code, message }`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QrError {
+    /// The OS does not let the app record the screen
+    CapturePermissionDenied,
+    NoScreens,
+    /// No connected screen has the ID of a region
+    ScreenNotFound(u32),
+    /// No window matches the target
+    WindowNotFound(String),
+    /// Listing or capturing the screens failed
+    Capture(String),
+    /// The data is in no image format the decoder reads
+    UnsupportedFormat(String),
+    /// The image data is corrupt
+    Decode(String),
+    /// The image file could not be read
+    Read(String),
+}
+
+impl QrError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            QrError::CapturePermissionDenied => "capture_permission_denied",
+            QrError::NoScreens => "no_screens",
+            QrError::ScreenNotFound(_) => "screen_not_found",
+            QrError::WindowNotFound(_) => "window_not_found",
+            QrError::Capture(_) => "capture",
+            QrError::UnsupportedFormat(_) => "unsupported_format",
+            QrError::Decode(_) => "decode",
+            QrError::Read(_) => "read",
+        }
+    }
+}
+
+impl fmt::Display for QrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QrError::CapturePermissionDenied => write!(
+                f,
+                "The app is not allowed to record the screen, grant it screen recording permission in the system settings"
+            ),
+            QrError::NoScreens => write!(f, "No screens to scan"),
+            QrError::ScreenNotFound(id) => write!(f, "Screen {} is not connected", id),
+            QrError::WindowNotFound(target) => write!(f, "No window matches {}", target),
+            QrError::Capture(e) => write!(f, "Could not capture the screen: {}", e),
+            QrError::UnsupportedFormat(e) => write!(f, "Unsupported image format: {}", e),
+            QrError::Decode(e) => write!(f, "Could not read the image: {}", e),
+            QrError::Read(e) => write!(f, "Could not open the image file: {}", e),
+        }
+    }
+}
+
+impl Serialize for QrError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("QrError", 2)?;
+        error.serialize_field("code", self.code())?;
+        error.serialize_field("message", &self.to_string())?;
+        error.end()
+    }
+}
+
+impl From<image::ImageError> for QrError {
+    fn from(e: image::ImageError) -> Self {
+        match e {
+            image::ImageError::Unsupported(_) => QrError::UnsupportedFormat(e.to_string()),
+            _ => QrError::Decode(e.to_string()),
+        }
+    }
+}
+
+/// Capture libraries report a refused permission as a plain error
+fn capture_error(e: impl fmt::Display) -> QrError {
+    let message = e.to_string();
+    let lower = message.to_lowercase();
+    if lower.contains("permission") || lower.contains("denied") || lower.contains("not authorized")
+    {
+        QrError::CapturePermissionDenied
+    } else {
+        QrError::Capture(message)
+    }
+}
+
+/// Without the permission macOS still captures, but only the desktop
+/// background, so it has to be checked up front
+#[cfg(target_os = "macos")]
+fn screen_capture_allowed() -> bool {
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGPreflightScreenCaptureAccess() -> bool;
+    }
+    unsafe { CGPreflightScreenCaptureAccess() }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn screen_capture_allowed() -> bool {
+    true
+}
+
+/// Find the codes in an image. `screen`, the index and ID of the screen the
+/// image shows, is recorded on the results.
+fn scan(image: image::DynamicImage, screen: Option<(usize, u32)>) -> Vec<DetectedQrCode> {
+    decode::decode(&image)
+        .into_iter()
+        .map(|code| DetectedQrCode {
+            kind: classify(&code.text),
+            format: code.format,
+            screen: screen.map(|(index, _)| index),
+            screen_id: screen.map(|(_, id)| id),
+            bbox: code.bbox,
+            text: code.text,
+        })
+        .collect()
+}
+
+/// Load an encoded image, whatever its format
+fn load(bytes: &[u8]) -> Result<image::DynamicImage, QrError> {
+    let format = image::guess_format(bytes)?;
+    Ok(image::load_from_memory_with_format(bytes, format)?)
+}
+
+fn decode_base64(data: &str) -> Result<Vec<u8>, QrError> {
+    general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| QrError::Decode(format!("invalid base64: {}", e)))
+}
+
+/// Most useful codes first; codes of one kind keep their screen order
+fn sorted(mut codes: Vec<DetectedQrCode>) -> Vec<DetectedQrCode> {
+    codes.sort_by_key(|code| code.kind);
+    codes
+}
+
+/// Run capturing and decoding on the blocking pool. Decoding a large screen
+/// takes long enough to stall the async runtime.
+async fn blocking<T, F>(f: F) -> Result<T, QrError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, QrError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| QrError::Decode(format!("the scan stopped: {}", e)))?
+}
+
+/// Scan every screen for codes
+#[tauri::command]
+pub async fn detect_qr_code() -> Result<Vec<DetectedQrCode>, QrError> {
+    if !screen_capture_allowed() {
+        return Err(QrError::CapturePermissionDenied);
+    }
+    blocking(scan_screens).await
+}
+
+fn scan_screens() -> Result<Vec<DetectedQrCode>, QrError> {
+    let screens = Monitor::all().map_err(capture_error)?;
+    if screens.is_empty() {
+        return Err(QrError::NoScreens);
+    }
+
+    // A screen that fails to capture, e.g. one just unplugged, does not
+    // spoil the others
+    let mut codes = Vec::new();
+    let mut failure = None;
+    let mut captured = 0;
+    for (index, screen) in screens.iter().enumerate() {
+        let captured_screen = screen
+            .id()
+            .map_err(capture_error)
+            .and_then(|id| Ok((id, capture(screen)?)));
+        match captured_screen {
+            Ok((id, i)) => {
+                captured += 1;
+                codes.extend(scan(i, Some((index, id))));
+            }
+            Err(e) => {
+                log::warn!("Could not capture screen {}: {}", index, e);
+                failure = Some(e);
+            }
+        }
+    }
+
+    match failure {
+        Some(e) if captured == 0 => Err(e),
+        _ => Ok(sorted(codes)),
+    }
+}
+
+fn capture(screen: &Monitor) -> Result<image::DynamicImage, QrError> {
+    captured_image(screen.capture_image().map_err(capture_error)?)
+}
+
+/// xcap depends on a newer `image` than this crate, but the pixels are laid
+/// out the same
+fn captured_image(captured: xcap::image::RgbaImage) -> Result<image::DynamicImage, QrError> {
+    let (width, height) = (captured.width(), captured.height());
+    image::RgbaImage::from_raw(width, height, captured.into_raw())
+        .map(image::DynamicImage::ImageRgba8)
+        .ok_or_else(|| {
+            QrError::Capture(format!("captured image is not {}x{} pixels", width, height))
+        })
+}
+
+/// Scan a base64 encoded image, e.g. a pasted PNG or JPEG
+#[tauri::command]
+pub async fn detect_qr_code_from_image(data: String) -> Result<Vec<DetectedQrCode>, QrError> {
+    let bytes = decode_base64(&data)?;
+    blocking(move || Ok(sorted(scan(load(&bytes)?, None)))).await
+}
+
+/// Scan a base64 buffer of 8-bit RGBA pixels, row by row, as canvas
+/// `ImageData` holds them
+#[tauri::command]
+pub async fn detect_qr_code_from_rgba(
+    width: u32,
+    height: u32,
+    data: String,
+) -> Result<Vec<DetectedQrCode>, QrError> {
+    let pixels = decode_base64(&data)?;
+    let len = pixels.len();
+    let Some(i) = image::RgbaImage::from_raw(width, height, pixels) else {
+        return Err(QrError::Decode(format!(
+            "{} bytes do not hold {}x{} RGBA pixels",
+            len, width, height
+        )));
+    };
+    blocking(move || Ok(sorted(scan(image::DynamicImage::ImageRgba8(i), None)))).await
+}
+
+/// Scan an image file, e.g. one dropped on the window
+#[tauri::command]
+pub async fn detect_qr_code_from_file(path: String) -> Result<Vec<DetectedQrCode>, QrError> {
+    let bytes = tokio::fs::read(&path)
+        .await
+        .map_err(|e| QrError::Read(format!("{}: {}", path, e)))?;
+    blocking(move || Ok(sorted(scan(load(&bytes)?, None)))).await
+}
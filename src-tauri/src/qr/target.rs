@@ -0,0 +1,429 @@
+//! Scanning part of the screen instead of all of it.
+//!
+//! A scan can be limited to a rectangle of one screen or to one window. When
+//! the user connects with a code found on screen, the rectangle around it is
+//! remembered for the origin of the dapp's manifest. The next scan looks at
+//! the few most recently used targets first, so reconnecting to the same dev
+//! page skips capturing and decoding every screen. Older targets are left to
+//! the full scan, where the user chooses between codes, so a dapp page left
+//! open since long ago does not connect on its own.
+
+use super::{
+    blocking, capture_error, captured_image, scan, screen_capture_allowed, sorted, BoundingBox,
+    DetectedQrCode, QrCodeKind, QrError,
+};
+use crate::db::Database;
+use crate::tonconnect_link;
+use crate::util::unix_millis;
+use base64::{engine::general_purpose, Engine as _};
+use image::imageops::FilterType;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Cursor;
+use url::Url;
+use xcap::{Monitor, Window};
+
+/// JSON object of remembered targets by dapp origin
+pub const QR_TARGETS_SETTING: &str = "qr_capture_targets";
+
+/// Origins remembered at most; the least recently used are forgotten
+const MAX_TARGETS: usize = 50;
+/// Remembered targets scanned before falling back to a full scan
+const RECENT_TARGETS: usize = 3;
+/// Targets not used for this long are only found by a full scan
+const RECENT_TARGET_MAX_AGE_MS: i64 = 24 * 60 * 60 * 1000;
+/// Space kept around a remembered code, as a share of its size per side
+const REGION_MARGIN: f32 = 0.5;
+/// Width of the screen previews a region is chosen on
+const PREVIEW_WIDTH: u32 = 640;
+
+/// A rectangle of one screen, in logical pixels from its top left corner
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CaptureRegion {
+    /// Display ID, which unlike the index survives reconnecting screens
+    pub screen_id: u32,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A window, matched by process ID, a case-insensitive part of its title,
+/// or both
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowTarget {
+    #[serde(default)]
+    pub pid: Option<u32>,
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CaptureTarget {
+    Region(CaptureRegion),
+    Window(WindowTarget),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RememberedTarget {
+    target: CaptureTarget,
+    /// Unix millis of the last connect through this target
+    used_at: i64,
+}
+
+/// A remembered target, as listed to the user
+#[derive(Debug, Clone, Serialize)]
+pub struct QrTarget {
+    /// Origin of the dapp's manifest
+    pub origin: String,
+    pub target: CaptureTarget,
+    pub used_at: i64,
+}
+
+/// A window the user can choose to scan
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureWindow {
+    pub pid: u32,
+    pub app_name: String,
+    pub title: String,
+}
+
+/// A screen the user can choose a region of
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureScreen {
+    pub id: u32,
+    pub name: String,
+    /// Size in logical pixels, the unit of `CaptureRegion`
+    pub width: u32,
+    pub height: u32,
+    /// Base64 PNG of the screen, `PREVIEW_WIDTH` pixels wide
+    pub preview: String,
+}
+
+/// ID, logical size and scale factor of a screen
+struct ScreenInfo {
+    id: u32,
+    width: u32,
+    height: u32,
+    scale: f32,
+}
+
+fn screen_info(screen: &Monitor) -> Result<ScreenInfo, QrError> {
+    Ok(ScreenInfo {
+        id: screen.id().map_err(capture_error)?,
+        width: screen.width().map_err(capture_error)?,
+        height: screen.height().map_err(capture_error)?,
+        scale: screen.scale_factor().map_err(capture_error)?,
+    })
+}
+
+fn scan_region(region: &CaptureRegion) -> Result<Vec<DetectedQrCode>, QrError> {
+    let screens = Monitor::all().map_err(capture_error)?;
+    let mut found = None;
+    for (index, screen) in screens.iter().enumerate() {
+        let info = screen_info(screen)?;
+        if info.id == region.screen_id {
+            found = Some((index, screen, info));
+            break;
+        }
+    }
+    let (index, screen, info) = found.ok_or(QrError::ScreenNotFound(region.screen_id))?;
+
+    // Screens may have shrunk since the region was chosen
+    let x = region.x.clamp(0, info.width as i32) as u32;
+    let y = region.y.clamp(0, info.height as i32) as u32;
+    let width = region.width.min(info.width - x);
+    let height = region.height.min(info.height - y);
+    if width == 0 || height == 0 {
+        return Err(QrError::Capture(format!(
+            "the region is outside screen {}",
+            region.screen_id
+        )));
+    }
+
+    let captured = screen
+        .capture_region(x, y, width, height)
+        .map_err(capture_error)?;
+
+    // Report positions on the whole screen, as a full scan does
+    let mut codes = scan(captured_image(captured)?, Some((index, info.id)));
+    for bbox in codes.iter_mut().filter_map(|code| code.bbox.as_mut()) {
+        bbox.x += x as f32 * info.scale;
+        bbox.y += y as f32 * info.scale;
+    }
+    Ok(codes)
+}
+
+fn find_window(target: &WindowTarget) -> Result<Window, QrError> {
+    let describe = || match (target.pid, target.title.as_deref()) {
+        (Some(pid), Some(title)) => format!("process {} and title {:?}", pid, title),
+        (Some(pid), None) => format!("process {}", pid),
+        (None, Some(title)) => format!("title {:?}", title),
+        (None, None) => "an empty target".to_string(),
+    };
+    if target.pid.is_none() && target.title.is_none() {
+        return Err(QrError::WindowNotFound(describe()));
+    }
+
+    let title = target.title.as_deref().map(str::to_lowercase);
+    Window::all()
+        .map_err(capture_error)?
+        .into_iter()
+        .find(|window| {
+            !window.is_minimized().unwrap_or(false)
+                && target
+                    .pid
+                    .is_none_or(|pid| window.pid().is_ok_and(|own| own == pid))
+                && title.as_deref().is_none_or(|title| {
+                    window
+                        .title()
+                        .is_ok_and(|own| own.to_lowercase().contains(title))
+                })
+        })
+        .ok_or_else(|| QrError::WindowNotFound(describe()))
+}
+
+fn scan_window(target: &WindowTarget) -> Result<Vec<DetectedQrCode>, QrError> {
+    let window = find_window(target)?;
+    let captured = window.capture_image().map_err(capture_error)?;
+    Ok(scan(captured_image(captured)?, None))
+}
+
+fn scan_target(target: &CaptureTarget) -> Result<Vec<DetectedQrCode>, QrError> {
+    match target {
+        CaptureTarget::Region(region) => scan_region(region),
+        CaptureTarget::Window(window) => scan_window(window),
+    }
+}
+
+fn preview(screen: &Monitor) -> Result<CaptureScreen, QrError> {
+    let info = screen_info(screen)?;
+    let captured = captured_image(screen.capture_image().map_err(capture_error)?)?;
+    let height = (captured.height() as u64 * PREVIEW_WIDTH as u64 / captured.width().max(1) as u64)
+        .max(1) as u32;
+    let mut png = Vec::new();
+    captured
+        .resize_exact(PREVIEW_WIDTH, height, FilterType::Triangle)
+        .write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)
+        .map_err(|e| QrError::Capture(e.to_string()))?;
+
+    Ok(CaptureScreen {
+        id: info.id,
+        name: screen.name().unwrap_or_default(),
+        width: info.width,
+        height: info.height,
+        preview: general_purpose::STANDARD.encode(png),
+    })
+}
+
+fn load_targets(db: &Database) -> HashMap<String, RememberedTarget> {
+    match db.get_setting(QR_TARGETS_SETTING) {
+        Ok(Some(raw)) => match serde_json::from_str(&raw) {
+            Ok(targets) => return targets,
+            Err(e) => warn!("Invalid {} setting: {}", QR_TARGETS_SETTING, e),
+        },
+        Ok(None) => {}
+        Err(e) => warn!("Failed to read {} setting: {}", QR_TARGETS_SETTING, e),
+    }
+    HashMap::new()
+}
+
+fn save_targets(db: &Database, targets: &HashMap<String, RememberedTarget>) -> Result<(), String> {
+    let raw = serde_json::to_string(targets).map_err(|e| e.to_string())?;
+    db.set_setting(QR_TARGETS_SETTING, &raw)
+}
+
+/// Origin of the manifest of a connect link
+fn link_origin(link: &str) -> Option<String> {
+    let request = tonconnect_link::parse(link).ok()?;
+    let url = Url::parse(&request.manifest_url).ok()?;
+    Some(url.origin().ascii_serialization())
+}
+
+/// The region of a screen around a code found there
+fn region_around(screen_id: u32, bbox: &BoundingBox) -> Result<CaptureRegion, QrError> {
+    let mut found = None;
+    for screen in Monitor::all().map_err(capture_error)? {
+        let info = screen_info(&screen)?;
+        if info.id == screen_id {
+            found = Some(info);
+            break;
+        }
+    }
+    let info = found.ok_or(QrError::ScreenNotFound(screen_id))?;
+
+    // The box spans the finder patterns' centres, in physical pixels
+    let scale = info.scale;
+    let margin = bbox.width.max(bbox.height) * REGION_MARGIN;
+    let left = ((bbox.x - margin) / scale).max(0.0);
+    let top = ((bbox.y - margin) / scale).max(0.0);
+    let right = ((bbox.x + bbox.width + margin) / scale).min(info.width as f32);
+    let bottom = ((bbox.y + bbox.height + margin) / scale).min(info.height as f32);
+
+    Ok(CaptureRegion {
+        screen_id: info.id,
+        x: left as i32,
+        y: top as i32,
+        width: (right - left).ceil().max(1.0) as u32,
+        height: (bottom - top).ceil().max(1.0) as u32,
+    })
+}
+
+/// Scan a rectangle of one screen
+#[tauri::command]
+pub async fn detect_qr_code_in_region(
+    region: CaptureRegion,
+) -> Result<Vec<DetectedQrCode>, QrError> {
+    if !screen_capture_allowed() {
+        return Err(QrError::CapturePermissionDenied);
+    }
+    blocking(move || Ok(sorted(scan_region(&region)?))).await
+}
+
+/// Scan one window, even when other windows cover it
+#[tauri::command]
+pub async fn detect_qr_code_in_window(
+    window: WindowTarget,
+) -> Result<Vec<DetectedQrCode>, QrError> {
+    if !screen_capture_allowed() {
+        return Err(QrError::CapturePermissionDenied);
+    }
+    blocking(move || Ok(sorted(scan_window(&window)?))).await
+}
+
+/// Windows that can be scanned, for the user to choose from
+#[tauri::command]
+pub async fn list_capture_windows() -> Result<Vec<CaptureWindow>, QrError> {
+    blocking(|| {
+        let windows = Window::all().map_err(capture_error)?;
+        Ok(windows
+            .into_iter()
+            .filter(|window| !window.is_minimized().unwrap_or(true))
+            .filter_map(|window| {
+                Some(CaptureWindow {
+                    pid: window.pid().ok()?,
+                    app_name: window.app_name().unwrap_or_default(),
+                    title: window.title().ok().filter(|title| !title.is_empty())?,
+                })
+            })
+            .collect())
+    })
+    .await
+}
+
+/// Screens with a preview each, for the user to draw a region on
+#[tauri::command]
+pub async fn list_capture_screens() -> Result<Vec<CaptureScreen>, QrError> {
+    if !screen_capture_allowed() {
+        return Err(QrError::CapturePermissionDenied);
+    }
+    blocking(|| {
+        let screens = Monitor::all().map_err(capture_error)?;
+        if screens.is_empty() {
+            return Err(QrError::NoScreens);
+        }
+        screens.iter().map(preview).collect()
+    })
+    .await
+}
+
+/// Scan the few most recently used targets and return the codes of the
+/// first that shows a connect code. Empty when none does.
+#[tauri::command]
+pub async fn detect_qr_code_remembered(
+    db: tauri::State<'_, Database>,
+) -> Result<Vec<DetectedQrCode>, QrError> {
+    if !screen_capture_allowed() {
+        return Err(QrError::CapturePermissionDenied);
+    }
+
+    let now = unix_millis();
+    let mut targets: Vec<(String, RememberedTarget)> = load_targets(&db)
+        .into_iter()
+        .filter(|(_, remembered)| now - remembered.used_at < RECENT_TARGET_MAX_AGE_MS)
+        .collect();
+    targets.sort_by_key(|(_, remembered)| std::cmp::Reverse(remembered.used_at));
+    targets.truncate(RECENT_TARGETS);
+
+    blocking(move || {
+        for (origin, remembered) in targets {
+            match scan_target(&remembered.target) {
+                Ok(codes) if codes.iter().any(|code| code.kind == QrCodeKind::TonConnect) => {
+                    return Ok(sorted(codes));
+                }
+                Ok(_) => {}
+                Err(e) => info!("Remembered QR target of {} not scanned: {}", origin, e),
+            }
+        }
+        Ok(Vec::new())
+    })
+    .await
+}
+
+/// Remember where a connect code was found, for the origin of its manifest.
+/// Codes from a window scan are remembered by `window`, others by the
+/// region of the screen around them.
+#[tauri::command]
+pub async fn remember_qr_code(
+    db: tauri::State<'_, Database>,
+    code: DetectedQrCode,
+    window: Option<WindowTarget>,
+) -> Result<(), String> {
+    let origin = link_origin(&code.text).ok_or_else(|| "Code is not a connect link".to_string())?;
+    let target = match (window, code.screen_id, code.bbox) {
+        (Some(window), _, _) => CaptureTarget::Window(window),
+        (None, Some(screen_id), Some(bbox)) => CaptureTarget::Region(
+            blocking(move || region_around(screen_id, &bbox))
+                .await
+                .map_err(|e| e.to_string())?,
+        ),
+        (None, _, _) => return Err("Code was not found on a screen".into()),
+    };
+
+    let mut targets = load_targets(&db);
+    targets.insert(
+        origin,
+        RememberedTarget {
+            target,
+            used_at: unix_millis(),
+        },
+    );
+    while targets.len() > MAX_TARGETS {
+        let oldest = targets
+            .iter()
+            .min_by_key(|(_, remembered)| remembered.used_at)
+            .map(|(origin, _)| origin.clone());
+        match oldest {
+            Some(origin) => targets.remove(&origin),
+            None => break,
+        };
+    }
+    save_targets(&db, &targets)
+}
+
+/// Remembered targets, most recently used first
+#[tauri::command]
+pub fn list_qr_targets(db: tauri::State<'_, Database>) -> Vec<QrTarget> {
+    let mut targets: Vec<QrTarget> = load_targets(&db)
+        .into_iter()
+        .map(|(origin, remembered)| QrTarget {
+            origin,
+            target: remembered.target,
+            used_at: remembered.used_at,
+        })
+        .collect();
+    targets.sort_by_key(|target| std::cmp::Reverse(target.used_at));
+    targets
+}
+
+/// Scan the whole screen again next time a dapp of this origin connects
+#[tauri::command]
+pub fn forget_qr_target(db: tauri::State<'_, Database>, origin: String) -> Result<(), String> {
+    let mut targets = load_targets(&db);
+    if targets.remove(origin.trim_end_matches('/')).is_some() {
+        save_targets(&db, &targets)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,340 @@
+//! Supervises the local WebSocket servers (liteserver proxy and TON echo).
+//!
+//! Each server runs in a supervisor task that binds the listener, restarts it
+//! with backoff when it fails or panics and reports every bind through a
+//! `server_port_changed` event. Stopping a server closes its listener and
+//! drains open sessions: they receive the shutdown signal and get a short
+//! grace period to send close frames before the supervisor gives up on them.
+
+use crate::proxy::{spawn_proxy, ProxyContext};
+use crate::ton_echo::{bind_ton_echo_listener, run_echo_server, EchoContext};
+use log::{info, warn};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+use tokio::net::TcpListener;
+use tokio::sync::{oneshot, watch, Mutex};
+use tokio::task::JoinHandle;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// A listener that ran this long is considered healthy and resets the backoff
+const STABLE_RUN: Duration = Duration::from_secs(60);
+/// How long open sessions get to close after a stop is requested
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerKind {
+    Proxy,
+    TonEcho,
+}
+
+impl std::fmt::Display for ServerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerKind::Proxy => write!(f, "proxy"),
+            ServerKind::TonEcho => write!(f, "TON echo"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PortChanged {
+    server: ServerKind,
+    port: u16,
+}
+
+#[derive(Default)]
+struct SessionCount {
+    active: AtomicUsize,
+}
+
+/// Keeps a session counted as open until dropped
+pub struct SessionGuard(Arc<SessionCount>);
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Shutdown signal for one server run, shared with its listener and sessions
+#[derive(Clone)]
+pub struct Shutdown {
+    rx: watch::Receiver<bool>,
+    sessions: Arc<SessionCount>,
+}
+
+impl Shutdown {
+    pub(crate) fn new() -> (watch::Sender<bool>, Self) {
+        let (tx, rx) = watch::channel(false);
+        (
+            tx,
+            Self {
+                rx,
+                sessions: Arc::default(),
+            },
+        )
+    }
+
+    /// Resolves once the server is asked to stop
+    pub async fn wait(&mut self) {
+        let _ = self.rx.wait_for(|stop| *stop).await;
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    /// Count a session as open until the returned guard is dropped
+    pub fn track(&self) -> SessionGuard {
+        self.sessions.active.fetch_add(1, Ordering::AcqRel);
+        SessionGuard(self.sessions.clone())
+    }
+
+    fn active_sessions(&self) -> usize {
+        self.sessions.active.load(Ordering::Acquire)
+    }
+}
+
+struct RunningServer {
+    stop: watch::Sender<bool>,
+    supervisor: JoinHandle<()>,
+}
+
+pub struct ServerManager {
+    app_handle: AppHandle,
+    proxy_ctx: ProxyContext,
+    echo_ctx: EchoContext,
+    proxy_port: AtomicU16,
+    echo_port: AtomicU16,
+    proxy: Mutex<Option<RunningServer>>,
+    echo: Mutex<Option<RunningServer>>,
+    stopped: AtomicBool,
+}
+
+impl ServerManager {
+    pub fn new(app_handle: AppHandle, proxy_ctx: ProxyContext, echo_ctx: EchoContext) -> Self {
+        Self {
+            app_handle,
+            proxy_ctx,
+            echo_ctx,
+            proxy_port: AtomicU16::new(0),
+            echo_port: AtomicU16::new(0),
+            proxy: Mutex::new(None),
+            echo: Mutex::new(None),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    pub fn port(&self, kind: ServerKind) -> u16 {
+        self.port_slot(kind).load(Ordering::Relaxed)
+    }
+
+    /// True once `stop_all` has finished, so the app can exit
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    pub async fn start_all(self: &Arc<Self>) {
+        self.start(ServerKind::Proxy).await;
+        self.start(ServerKind::TonEcho).await;
+    }
+
+    pub async fn stop_all(&self) {
+        self.stop(ServerKind::Proxy).await;
+        self.stop(ServerKind::TonEcho).await;
+        self.stopped.store(true, Ordering::Release);
+    }
+
+    /// Stop the server, drain its sessions and bind it again
+    pub async fn restart(self: &Arc<Self>, kind: ServerKind) {
+        info!("Restarting {} server", kind);
+        self.stop(kind).await;
+        self.start(kind).await;
+    }
+
+    /// Start the supervisor and wait for its first bind attempt
+    async fn start(self: &Arc<Self>, kind: ServerKind) {
+        let mut slot = self.slot(kind).lock().await;
+        if slot.is_some() {
+            return;
+        }
+
+        let (stop, shutdown) = Shutdown::new();
+        let (ready_tx, ready_rx) = oneshot::channel();
+        let manager = self.clone();
+        let supervisor =
+            tokio::spawn(async move { manager.supervise(kind, shutdown, ready_tx).await });
+        *slot = Some(RunningServer { stop, supervisor });
+        drop(slot);
+
+        let _ = ready_rx.await;
+    }
+
+    async fn stop(&self, kind: ServerKind) {
+        let running = self.slot(kind).lock().await.take();
+        let Some(running) = running else {
+            return;
+        };
+
+        let _ = running.stop.send(true);
+        if let Err(e) = running.supervisor.await {
+            warn!("{} server supervisor failed: {}", kind, e);
+        }
+        if kind == ServerKind::TonEcho {
+            self.echo_ctx.discovery.remove();
+        }
+    }
+
+    async fn supervise(
+        self: Arc<Self>,
+        kind: ServerKind,
+        mut shutdown: Shutdown,
+        ready: oneshot::Sender<()>,
+    ) {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut ready = Some(ready);
+
+        while !shutdown.is_stopping() {
+            let started = Instant::now();
+            let bound = self.bind(kind).await;
+            if let Ok(listener) = &bound {
+                let port = listener.local_addr().map(|a| a.port()).unwrap_or(0);
+                self.set_port(kind, port);
+                info!("{} server listening on port {}", kind, port);
+            }
+            if let Some(ready) = ready.take() {
+                let _ = ready.send(());
+            }
+
+            let result = match bound {
+                // Served in its own task, so a panic is restarted like a failure
+                Ok(listener) => {
+                    let manager = self.clone();
+                    let shutdown = shutdown.clone();
+                    tokio::spawn(async move { manager.serve(kind, listener, shutdown).await })
+                        .await
+                        .unwrap_or_else(|e| Err(e.to_string()))
+                }
+                Err(e) => Err(e),
+            };
+
+            if shutdown.is_stopping() {
+                break;
+            }
+
+            if started.elapsed() >= STABLE_RUN {
+                backoff = INITIAL_BACKOFF;
+            }
+            match result {
+                Ok(()) => warn!(
+                    "{} server stopped unexpectedly, restarting in {:?}",
+                    kind, backoff
+                ),
+                Err(e) => warn!("{} server failed: {}, restarting in {:?}", kind, e, backoff),
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(backoff) => {}
+                _ = shutdown.wait() => break,
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+
+        self.drain(kind, &shutdown).await;
+    }
+
+    async fn bind(&self, kind: ServerKind) -> Result<TcpListener, String> {
+        match kind {
+            ServerKind::Proxy => {
+                // Keep the previous port when possible so open webviews can reconnect
+                let previous = self.port(kind);
+                if previous != 0 {
+                    if let Ok(listener) = TcpListener::bind(("127.0.0.1", previous)).await {
+                        return Ok(listener);
+                    }
+                }
+                TcpListener::bind("127.0.0.1:0")
+                    .await
+                    .map_err(|e| format!("Failed to bind proxy listener: {}", e))
+            }
+            ServerKind::TonEcho => bind_ton_echo_listener().await,
+        }
+    }
+
+    async fn serve(
+        &self,
+        kind: ServerKind,
+        mut listener: TcpListener,
+        shutdown: Shutdown,
+    ) -> Result<(), String> {
+        match kind {
+            ServerKind::Proxy => spawn_proxy(&mut listener, self.proxy_ctx.clone(), shutdown)
+                .await
+                .map_err(|e| e.to_string()),
+            ServerKind::TonEcho => run_echo_server(listener, self.echo_ctx.clone(), shutdown)
+                .await
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    async fn drain(&self, kind: ServerKind, shutdown: &Shutdown) {
+        let deadline = Instant::now() + DRAIN_TIMEOUT;
+        while shutdown.active_sessions() > 0 && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        let remaining = shutdown.active_sessions();
+        if remaining > 0 {
+            warn!(
+                "{} server stopped with {} sessions still open",
+                kind, remaining
+            );
+        } else {
+            info!("{} server stopped", kind);
+        }
+    }
+
+    fn set_port(&self, kind: ServerKind, port: u16) {
+        self.port_slot(kind).store(port, Ordering::Relaxed);
+        if kind == ServerKind::TonEcho {
+            self.echo_ctx.discovery.publish(port);
+        }
+        let payload = PortChanged { server: kind, port };
+        if let Err(e) = self.app_handle.emit("server_port_changed", payload) {
+            info!("Error emitting server_port_changed: {:?}", e);
+        }
+    }
+
+    fn port_slot(&self, kind: ServerKind) -> &AtomicU16 {
+        match kind {
+            ServerKind::Proxy => &self.proxy_port,
+            ServerKind::TonEcho => &self.echo_port,
+        }
+    }
+
+    fn slot(&self, kind: ServerKind) -> &Mutex<Option<RunningServer>> {
+        match kind {
+            ServerKind::Proxy => &self.proxy,
+            ServerKind::TonEcho => &self.echo,
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn restart_proxy(manager: tauri::State<'_, Arc<ServerManager>>) -> Result<u16, String> {
+    manager.restart(ServerKind::Proxy).await;
+    Ok(manager.port(ServerKind::Proxy))
+}
+
+#[tauri::command]
+pub async fn restart_ton_echo(
+    manager: tauri::State<'_, Arc<ServerManager>>,
+) -> Result<u16, String> {
+    manager.restart(ServerKind::TonEcho).await;
+    Ok(manager.port(ServerKind::TonEcho))
+}
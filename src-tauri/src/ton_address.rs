@@ -0,0 +1,252 @@
+use base64::{engine::general_purpose, Engine as _};
+use std::fmt;
+
+/// A parsed TON account address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TonAddress {
+    pub workchain: i32,
+    pub hash: [u8; 32],
+}
+
+impl TonAddress {
+    /// Parse a raw (`0:abcd…`) or user-friendly (48 chars, base64 or base64url) address
+    pub fn parse(address: &str) -> Result<Self, String> {
+        let address = address.trim();
+        if address.contains(':') {
+            Self::parse_raw(address)
+        } else {
+            Self::parse_friendly(address)
+        }
+    }
+
+    /// User-friendly form with the URL-safe alphabet, as `Address.toString`
+    /// in `@ton/core` produces it
+    pub fn to_user_friendly(&self, bounceable: bool, test_only: bool) -> String {
+        let mut data = Vec::with_capacity(36);
+        let mut tag = if bounceable { 0x11 } else { 0x51 };
+        if test_only {
+            tag |= 0x80;
+        }
+        data.push(tag);
+        data.push(self.workchain as i8 as u8);
+        data.extend_from_slice(&self.hash);
+        data.extend_from_slice(&crc16(&data).to_be_bytes());
+        general_purpose::URL_SAFE.encode(data)
+    }
+
+    fn parse_raw(address: &str) -> Result<Self, String> {
+        let (workchain, hash) = address
+            .split_once(':')
+            .ok_or_else(|| "Invalid raw address".to_string())?;
+
+        let workchain: i32 = workchain
+            .parse()
+            .map_err(|_| format!("Invalid workchain: {}", workchain))?;
+        // User-friendly addresses keep the workchain in a single byte
+        if i8::try_from(workchain).is_err() {
+            return Err(format!("Workchain {} is out of range", workchain));
+        }
+
+        // Checked before slicing, so multibyte characters cannot split
+        if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err("Raw address hash must be 64 hex characters".to_string());
+        }
+        let mut bytes = [0u8; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hash[i * 2..i * 2 + 2], 16)
+                .map_err(|_| "Raw address hash is not valid hex".to_string())?;
+        }
+
+        Ok(Self {
+            workchain,
+            hash: bytes,
+        })
+    }
+
+    fn parse_friendly(address: &str) -> Result<Self, String> {
+        if address.len() != 48 {
+            return Err("User-friendly address must be 48 characters".to_string());
+        }
+
+        let data = if address.contains('-') || address.contains('_') {
+            general_purpose::URL_SAFE.decode(address)
+        } else {
+            general_purpose::STANDARD.decode(address)
+        }
+        .map_err(|_| "User-friendly address is not valid base64".to_string())?;
+
+        if data.len() != 36 {
+            return Err("User-friendly address must decode to 36 bytes".to_string());
+        }
+
+        let checksum = crc16(&data[..34]);
+        if data[34..] != checksum.to_be_bytes() {
+            return Err("Address checksum mismatch".to_string());
+        }
+
+        let tag = data[0] & !0x80; // strip the test-only flag
+        if tag != 0x11 && tag != 0x51 {
+            return Err("Unknown address tag".to_string());
+        }
+
+        Ok(Self {
+            workchain: data[1] as i8 as i32,
+            hash: data[2..34].try_into().unwrap(),
+        })
+    }
+}
+
+impl fmt::Display for TonAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.workchain)?;
+        for b in self.hash {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// CRC16/XMODEM as used by user-friendly addresses
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZERO_RAW: &str = "0:0000000000000000000000000000000000000000000000000000000000000000";
+    const ZERO_BOUNCEABLE: &str = "EQAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAM9c";
+    const ZERO_NON_BOUNCEABLE: &str = "UQAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAJKZ";
+    const ZERO_TEST_ONLY: &str = "kQAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAHTW";
+    const ELECTOR_RAW: &str = "-1:3333333333333333333333333333333333333333333333333333333333333333";
+    const ELECTOR_BOUNCEABLE: &str = "Ef8zMzMzMzMzMzMzMzMzMzMzMzMzMzMzMzMzMzMzMzMzM0vF";
+
+    #[test]
+    fn parses_raw_addresses() {
+        let address = TonAddress::parse(ELECTOR_RAW).unwrap();
+        assert_eq!(address.workchain, -1);
+        assert_eq!(address.hash, [0x33; 32]);
+        assert_eq!(address.to_string(), ELECTOR_RAW);
+
+        // Upper case hex and surrounding whitespace are accepted
+        let upper = format!(" 0:{} ", "AB".repeat(32));
+        assert_eq!(TonAddress::parse(&upper).unwrap().hash, [0xab; 32]);
+    }
+
+    #[test]
+    fn user_friendly_round_trip() {
+        let zero = TonAddress::parse(ZERO_RAW).unwrap();
+        assert_eq!(zero.to_user_friendly(true, false), ZERO_BOUNCEABLE);
+        assert_eq!(zero.to_user_friendly(false, false), ZERO_NON_BOUNCEABLE);
+        assert_eq!(zero.to_user_friendly(true, true), ZERO_TEST_ONLY);
+
+        for friendly in [ZERO_BOUNCEABLE, ZERO_NON_BOUNCEABLE, ZERO_TEST_ONLY] {
+            assert_eq!(TonAddress::parse(friendly).unwrap(), zero);
+        }
+
+        let elector = TonAddress::parse(ELECTOR_BOUNCEABLE).unwrap();
+        assert_eq!(elector.to_string(), ELECTOR_RAW);
+        assert_eq!(elector.to_user_friendly(true, false), ELECTOR_BOUNCEABLE);
+    }
+
+    #[test]
+    fn accepts_the_standard_base64_alphabet() {
+        let address = TonAddress::parse(&format!("0:{}", "ff".repeat(32))).unwrap();
+        let url_safe = address.to_user_friendly(true, false);
+        assert!(url_safe.contains('_'));
+
+        let data = general_purpose::URL_SAFE.decode(&url_safe).unwrap();
+        let standard = general_purpose::STANDARD.encode(data);
+        assert!(standard.contains('/'));
+        assert_eq!(TonAddress::parse(&standard).unwrap(), address);
+    }
+
+    #[test]
+    fn rejects_a_wrong_checksum() {
+        let mut data = general_purpose::URL_SAFE.decode(ZERO_BOUNCEABLE).unwrap();
+        data[35] ^= 1;
+        let tampered = general_purpose::URL_SAFE.encode(data);
+        assert_eq!(
+            TonAddress::parse(&tampered).unwrap_err(),
+            "Address checksum mismatch"
+        );
+
+        // A changed hash byte no longer matches the checksum either
+        let mut data = general_purpose::URL_SAFE.decode(ZERO_BOUNCEABLE).unwrap();
+        data[10] = 1;
+        let tampered = general_purpose::URL_SAFE.encode(data);
+        assert!(TonAddress::parse(&tampered).is_err());
+    }
+
+    #[test]
+    fn rejects_an_unknown_tag() {
+        let mut data = vec![0x22, 0];
+        data.extend_from_slice(&[0; 32]);
+        data.extend_from_slice(&crc16(&data).to_be_bytes());
+        let address = general_purpose::URL_SAFE.encode(data);
+        assert_eq!(
+            TonAddress::parse(&address).unwrap_err(),
+            "Unknown address tag"
+        );
+    }
+
+    #[test]
+    fn rejects_workchains_that_do_not_fit_a_byte() {
+        for workchain in ["128", "-129", "2147483647"] {
+            let raw = format!("{}:{}", workchain, "00".repeat(32));
+            assert!(TonAddress::parse(&raw).is_err(), "{}", raw);
+        }
+        for workchain in [127, -128] {
+            let raw = format!("{}:{}", workchain, "00".repeat(32));
+            let address = TonAddress::parse(&raw).unwrap();
+            let friendly = address.to_user_friendly(true, false);
+            assert_eq!(TonAddress::parse(&friendly).unwrap().workchain, workchain);
+        }
+    }
+
+    #[test]
+    fn rejects_non_ascii_input_without_panicking() {
+        // 64 bytes, but not 64 characters
+        let raw = format!("0:{}é", "0".repeat(62));
+        assert_eq!(raw.len(), 66);
+        assert!(TonAddress::parse(&raw).is_err());
+
+        let raw = format!("0:{}", "ж".repeat(32));
+        assert!(TonAddress::parse(&raw).is_err());
+
+        // 48 bytes, as a user-friendly address is
+        let friendly = format!("{}ё", &ZERO_BOUNCEABLE[..46]);
+        assert_eq!(friendly.len(), 48);
+        assert!(TonAddress::parse(&friendly).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_addresses() {
+        for address in [
+            "",
+            "0:",
+            ":00",
+            "x:0000000000000000000000000000000000000000000000000000000000000000",
+            "0:000000000000000000000000000000000000000000000000000000000000000",
+            "0:000000000000000000000000000000000000000000000000000000000000000g",
+            "0:+000000000000000000000000000000000000000000000000000000000000000",
+            "EQAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAM9",
+            "EQAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAM9c=",
+            "EQAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAM9*",
+        ] {
+            assert!(TonAddress::parse(address).is_err(), "{:?}", address);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Address book entries written by dev tooling.
+//!
+//! `address_book_upsert` labels one address and `label_contracts` a batch,
+//! typically the contracts a deploy script just created. Entries are written
+//! to the `address_book` table of every network on the message's chain ID, in
+//! the address format the webview uses, so they sit next to entries added by
+//! hand. An address already in the book gets the new title and description.
+
+use super::protocol::AddressLabel;
+use crate::db::Database;
+use crate::ton_address::TonAddress;
+use crate::util::unix_millis;
+use serde::Serialize;
+use std::fmt;
+
+/// Emitted to the webview after entries were written
+pub const ADDRESS_BOOK_CHANGED_EVENT: &str = "address_book_changed";
+
+/// Entries accepted in one `label_contracts` message
+const MAX_LABELS: usize = 1000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AddressBookChanged {
+    pub network_ids: Vec<i64>,
+    pub inserted: usize,
+    pub updated: usize,
+}
+
+pub enum LabelError {
+    UnknownNetwork(i64),
+    Invalid(String),
+    Internal(String),
+}
+
+impl fmt::Display for LabelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LabelError::UnknownNetwork(chain_id) => {
+                write!(f, "No network with chain ID {}", chain_id)
+            }
+            LabelError::Invalid(message) | LabelError::Internal(message) => {
+                write!(f, "{}", message)
+            }
+        }
+    }
+}
+
+/// A label checked and normalized for storage
+struct Entry {
+    address: String,
+    title: String,
+    description: Option<String>,
+}
+
+/// Write the labels to every network on the chain. Nothing is written when
+/// any label is invalid.
+pub fn upsert_labels(
+    db: &Database,
+    chain_id: i64,
+    labels: &[AddressLabel],
+) -> Result<AddressBookChanged, LabelError> {
+    if labels.is_empty() {
+        return Err(LabelError::Invalid("No addresses to label".into()));
+    }
+    if labels.len() > MAX_LABELS {
+        return Err(LabelError::Invalid(format!(
+            "At most {} addresses may be labelled at once",
+            MAX_LABELS
+        )));
+    }
+    let entries = labels
+        .iter()
+        .map(normalize)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let network_ids: Vec<i64> = db
+        .networks_by_chain_id(chain_id)
+        .map_err(LabelError::Internal)?
+        .into_iter()
+        .map(|network| network.network_id)
+        .collect();
+    if network_ids.is_empty() {
+        return Err(LabelError::UnknownNetwork(chain_id));
+    }
+
+    let now = unix_millis();
+    let (inserted, updated) = db
+        .with_conn(|conn| {
+            let tx = conn.transaction()?;
+            let (mut inserted, mut updated) = (0, 0);
+            for &network_id in &network_ids {
+                for entry in &entries {
+                    let changed = tx.execute(
+                        "UPDATE address_book SET title = ?1, description = ?2
+                         WHERE network_id = ?3 AND address = ?4",
+                        rusqlite::params![entry.title, entry.description, network_id, entry.address],
+                    )?;
+                    if changed > 0 {
+                        updated += 1;
+                        continue;
+                    }
+                    tx.execute(
+                        "INSERT INTO address_book (network_id, address, title, description, created_at)
+                         VALUES (?1, ?2, ?3, ?4, ?5)",
+                        rusqlite::params![network_id, entry.address, entry.title, entry.description, now],
+                    )?;
+                    inserted += 1;
+                }
+            }
+            tx.commit()?;
+            Ok((inserted, updated))
+        })
+        .map_err(LabelError::Internal)?;
+
+    Ok(AddressBookChanged {
+        network_ids,
+        inserted,
+        updated,
+    })
+}
+
+fn normalize(label: &AddressLabel) -> Result<Entry, LabelError> {
+    let address = TonAddress::parse(&label.address)
+        .map_err(|e| LabelError::Invalid(format!("Invalid address {:?}: {}", label.address, e)))?;
+
+    let title = label.title.trim();
+    if title.is_empty() {
+        return Err(LabelError::Invalid(format!(
+            "Title for {} is empty",
+            label.address
+        )));
+    }
+
+    Ok(Entry {
+        // Same form as `formatTonAddress` in the webview
+        address: address.to_user_friendly(true, false),
+        title: title.to_string(),
+        description: label
+            .description
+            .as_deref()
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+            .map(str::to_string),
+    })
+}
@@ -0,0 +1,126 @@
+//! Reassembly of dumps sent as `transactions_dump_chunk` messages.
+//!
+//! A dump over the message size limit is split by the client into numbered
+//! chunks sharing a `dump_id`. Chunks may arrive in any order; the dump is
+//! stored once all of them are in. Partial dumps belong to their connection
+//! and are discarded when it closes.
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// Partial dumps a connection may have in flight at once
+const MAX_PARTIAL_DUMPS: usize = 4;
+/// Upper bound on the chunk count announced by a client
+const MAX_CHUNKS: u32 = 1 << 16;
+
+struct PartialDump {
+    parts: Vec<Option<String>>,
+    received: usize,
+    size: usize,
+}
+
+#[derive(Default)]
+pub struct DumpChunks {
+    partial: HashMap<String, PartialDump>,
+}
+
+pub enum ChunkError {
+    /// The dump would exceed the size limit
+    TooLarge(usize),
+    Invalid(String),
+}
+
+impl fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkError::TooLarge(max) => write!(f, "Dump is larger than {} bytes", max),
+            ChunkError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// Result of adding a chunk
+pub enum ChunkProgress {
+    /// More chunks are expected
+    Partial,
+    /// The last chunk arrived; holds the joined dump
+    Complete(String),
+}
+
+impl DumpChunks {
+    /// Add a chunk. On error the partial dump is discarded.
+    pub fn add(
+        &mut self,
+        dump_id: &str,
+        index: u32,
+        total: u32,
+        data: String,
+        max_dump_bytes: usize,
+    ) -> Result<ChunkProgress, ChunkError> {
+        if total == 0 || total > MAX_CHUNKS {
+            self.partial.remove(dump_id);
+            return Err(ChunkError::Invalid(format!(
+                "Chunk total must be between 1 and {}",
+                MAX_CHUNKS
+            )));
+        }
+        if index >= total {
+            self.partial.remove(dump_id);
+            return Err(ChunkError::Invalid(format!(
+                "Chunk index {} is out of range for {} chunks",
+                index, total
+            )));
+        }
+        if !self.partial.contains_key(dump_id) && self.partial.len() >= MAX_PARTIAL_DUMPS {
+            return Err(ChunkError::Invalid(format!(
+                "At most {} chunked dumps may be in flight per connection",
+                MAX_PARTIAL_DUMPS
+            )));
+        }
+
+        let dump = self
+            .partial
+            .entry(dump_id.to_string())
+            .or_insert_with(|| PartialDump {
+                parts: vec![None; total as usize],
+                received: 0,
+                size: 0,
+            });
+
+        if dump.parts.len() != total as usize {
+            self.partial.remove(dump_id);
+            return Err(ChunkError::Invalid(
+                "Chunk total changed within a dump".into(),
+            ));
+        }
+        if dump.size + data.len() > max_dump_bytes {
+            self.partial.remove(dump_id);
+            return Err(ChunkError::TooLarge(max_dump_bytes));
+        }
+
+        let part = &mut dump.parts[index as usize];
+        if part.is_none() {
+            dump.received += 1;
+        } else {
+            dump.size -= part.as_ref().map_or(0, String::len);
+        }
+        dump.size += data.len();
+        *part = Some(data);
+
+        if dump.received < dump.parts.len() {
+            return Ok(ChunkProgress::Partial);
+        }
+
+        let dump = self.partial.remove(dump_id).expect("dump is present");
+        let mut joined = String::with_capacity(dump.size);
+        for part in dump.parts.into_iter().flatten() {
+            joined.push_str(&part);
+        }
+        Ok(ChunkProgress::Complete(joined))
+    }
+
+    /// Number of partial dumps, discarded when the connection closes
+    pub fn pending(&self) -> usize {
+        self.partial.len()
+    }
+}
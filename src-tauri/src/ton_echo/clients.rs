@@ -0,0 +1,345 @@
+//! Who may talk to the echo server.
+//!
+//! Two checks guard the server:
+//! - The `Origin` of the WebSocket handshake. Non-browser clients send none;
+//!   browser pages and extensions have to be listed in the
+//!   `ton_echo_allowed_origins` setting.
+//! - Pairing. A `handshake` names its client with `client_id`. The first
+//!   handshake of an unknown client waits until the user approves it in the
+//!   app, which issues a secret the client sends on every later handshake.
+//!   Only a hash of the secret is kept in the `echo_clients` table, until the
+//!   client is revoked. A client id alone, which any local process can send,
+//!   is not enough to pass as a paired client.
+
+use super::EchoEvents;
+use crate::db::Database;
+use crate::util::unix_millis;
+use log::{info, warn};
+use rusqlite::OptionalExtension;
+use serde::Serialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// Setting holding a JSON array of extra allowed origins, e.g. `["https://example.com"]`
+pub const ALLOWED_ORIGINS_SETTING: &str = "ton_echo_allowed_origins";
+
+/// Event asking the webview to approve a new client
+pub const PAIRING_REQUEST_EVENT: &str = "echo_pairing_request";
+
+/// How long a handshake waits for the user to approve the client
+const PAIRING_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Row from the `echo_clients` table
+#[derive(Debug, Clone, Serialize)]
+pub struct EchoClientRow {
+    pub client_id: String,
+    pub name: Option<String>,
+    pub origin: Option<String>,
+    pub created_at: i64,
+    pub last_seen_at: Option<i64>,
+}
+
+/// Whether a handshake comes from a paired client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PairingCheck {
+    Paired,
+    /// The client id is paired, but the secret is missing or wrong
+    WrongSecret,
+    Unknown,
+}
+
+pub struct EchoClients {
+    db: Database,
+    next_pairing_id: AtomicU64,
+    pairings: Mutex<HashMap<u64, oneshot::Sender<bool>>>,
+}
+
+impl EchoClients {
+    pub fn new(db: Database) -> Self {
+        Self {
+            db,
+            next_pairing_id: AtomicU64::new(0),
+            pairings: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Browser origins allowed to connect
+    pub fn allowed_origins(&self) -> Vec<String> {
+        let raw = match self.db.get_setting(ALLOWED_ORIGINS_SETTING) {
+            Ok(Some(raw)) => raw,
+            Ok(None) => return Vec::new(),
+            Err(e) => {
+                warn!("TON echo: failed to read allowed origins: {}", e);
+                return Vec::new();
+            }
+        };
+
+        match serde_json::from_str::<Vec<String>>(&raw) {
+            Ok(origins) => origins
+                .into_iter()
+                .map(|origin| origin.trim().trim_end_matches('/').to_string())
+                .collect(),
+            Err(e) => {
+                warn!(
+                    "TON echo: invalid {} setting: {}",
+                    ALLOWED_ORIGINS_SETTING, e
+                );
+                Vec::new()
+            }
+        }
+    }
+
+    pub fn is_paired(&self, client_id: &str) -> Result<bool, String> {
+        self.db.with_conn(|conn| {
+            conn.query_row(
+                "SELECT 1 FROM echo_clients WHERE client_id = ?1",
+                [client_id],
+                |_| Ok(()),
+            )
+            .optional()
+            .map(|row| row.is_some())
+        })
+    }
+
+    /// Check the secret a handshake sent for `client_id`
+    pub fn check(&self, client_id: &str, secret: Option<&str>) -> Result<PairingCheck, String> {
+        let stored: Option<String> = self.db.with_conn(|conn| {
+            conn.query_row(
+                "SELECT secret_hash FROM echo_clients WHERE client_id = ?1",
+                [client_id],
+                |row| row.get(0),
+            )
+            .optional()
+        })?;
+        let Some(stored) = stored else {
+            return Ok(PairingCheck::Unknown);
+        };
+
+        let matches = secret.is_some_and(|secret| {
+            crate::proxy::constant_time_eq(secret_hash(secret).as_bytes(), stored.as_bytes())
+        });
+        if !matches {
+            return Ok(PairingCheck::WrongSecret);
+        }
+
+        self.db.with_conn(|conn| {
+            conn.execute(
+                "UPDATE echo_clients SET last_seen_at = ?1 WHERE client_id = ?2",
+                rusqlite::params![unix_millis(), client_id],
+            )
+        })?;
+        Ok(PairingCheck::Paired)
+    }
+
+    /// Ask the user to pair a new client. Returns the secret issued to it.
+    pub async fn pair(
+        &self,
+        client_id: &str,
+        name: Option<&str>,
+        origin: Option<&str>,
+        events: &EchoEvents,
+    ) -> Result<String, String> {
+        let pairing_id = self.next_pairing_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let (tx, rx) = oneshot::channel();
+        self.pairings.lock().unwrap().insert(pairing_id, tx);
+
+        info!(
+            "TON echo: pairing request {} from {:?}",
+            pairing_id, client_id
+        );
+        let requested = events
+            .emit(
+                PAIRING_REQUEST_EVENT,
+                json!({
+                    "pairing_id": pairing_id,
+                    "client_id": client_id,
+                    "name": name,
+                    "origin": origin,
+                }),
+            )
+            .map_err(|e| e.to_string());
+        let decision = match requested {
+            Ok(()) => tokio::time::timeout(PAIRING_TIMEOUT, rx).await,
+            Err(e) => {
+                self.pairings.lock().unwrap().remove(&pairing_id);
+                return Err(e);
+            }
+        };
+        self.pairings.lock().unwrap().remove(&pairing_id);
+
+        match decision {
+            Ok(Ok(true)) => {
+                let secret = new_secret();
+                let now = unix_millis();
+                self.db.with_conn(|conn| {
+                    conn.execute(
+                        "INSERT INTO echo_clients (client_id, name, origin, created_at, last_seen_at, secret_hash)
+                         VALUES (?1, ?2, ?3, ?4, ?4, ?5)
+                         ON CONFLICT(client_id) DO UPDATE SET
+                             name = excluded.name,
+                             origin = excluded.origin,
+                             last_seen_at = excluded.last_seen_at,
+                             secret_hash = excluded.secret_hash",
+                        rusqlite::params![client_id, name, origin, now, secret_hash(&secret)],
+                    )
+                })?;
+                info!("TON echo: paired {:?}", client_id);
+                Ok(secret)
+            }
+            Ok(Ok(false)) | Ok(Err(_)) => Err("Pairing was rejected in the wallet".into()),
+            Err(_) => Err("Pairing was not approved in time".into()),
+        }
+    }
+
+    /// Answer a pending pairing request
+    pub fn respond(&self, pairing_id: u64, approve: bool) -> Result<(), String> {
+        let tx = self
+            .pairings
+            .lock()
+            .unwrap()
+            .remove(&pairing_id)
+            .ok_or_else(|| format!("No pending pairing request {}", pairing_id))?;
+        tx.send(approve)
+            .map_err(|_| format!("Pairing request {} was abandoned", pairing_id))
+    }
+
+    pub fn list(&self) -> Result<Vec<EchoClientRow>, String> {
+        self.db.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT client_id, name, origin, created_at, last_seen_at
+                 FROM echo_clients ORDER BY created_at",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok(EchoClientRow {
+                    client_id: row.get(0)?,
+                    name: row.get(1)?,
+                    origin: row.get(2)?,
+                    created_at: row.get(3)?,
+                    last_seen_at: row.get(4)?,
+                })
+            })?;
+            rows.collect()
+        })
+    }
+
+    /// Forget a client. Its open connections are refused from the next message.
+    pub fn revoke(&self, client_id: &str) -> Result<(), String> {
+        let removed = self.db.with_conn(|conn| {
+            conn.execute("DELETE FROM echo_clients WHERE client_id = ?1", [client_id])
+        })?;
+        if removed == 0 {
+            return Err(format!("Unknown echo client {:?}", client_id));
+        }
+        info!("TON echo: revoked {:?}", client_id);
+        Ok(())
+    }
+}
+
+/// Check the `Origin` header of a handshake
+pub fn is_origin_allowed(origin: Option<&str>, allowed: &[String]) -> bool {
+    match origin {
+        // Not a browser; pairing still applies
+        None => true,
+        Some(origin) => allowed.iter().any(|allowed| allowed == origin),
+    }
+}
+
+fn new_secret() -> String {
+    let bytes: [u8; 32] = rand::random();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn secret_hash(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migration_commands::run_migrations_on_db;
+
+    fn clients(name: &str) -> EchoClients {
+        let dir =
+            std::env::temp_dir().join(format!("echo-clients-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.db");
+        run_migrations_on_db(path.to_str().unwrap()).unwrap();
+        EchoClients::new(Database::open(&path).unwrap())
+    }
+
+    fn insert(clients: &EchoClients, client_id: &str, secret: &str) {
+        clients
+            .db
+            .with_conn(|conn| {
+                conn.execute(
+                    "INSERT INTO echo_clients (client_id, created_at, secret_hash) VALUES (?1, 0, ?2)",
+                    rusqlite::params![client_id, secret_hash(secret)],
+                )
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn a_paired_client_needs_its_secret() {
+        let clients = clients("secret");
+        let secret = new_secret();
+        insert(&clients, "deploy-script", &secret);
+
+        assert_eq!(
+            clients.check("deploy-script", Some(&secret)),
+            Ok(PairingCheck::Paired)
+        );
+        assert_eq!(
+            clients.check("deploy-script", None),
+            Ok(PairingCheck::WrongSecret)
+        );
+        assert_eq!(
+            clients.check("deploy-script", Some(&new_secret())),
+            Ok(PairingCheck::WrongSecret)
+        );
+        // The hash itself is not a secret that passes
+        assert_eq!(
+            clients.check("deploy-script", Some(&secret_hash(&secret))),
+            Ok(PairingCheck::WrongSecret)
+        );
+        assert_eq!(
+            clients.check("other-script", Some(&secret)),
+            Ok(PairingCheck::Unknown)
+        );
+    }
+
+    #[test]
+    fn revoking_forgets_the_secret() {
+        let clients = clients("revoke");
+        let secret = new_secret();
+        insert(&clients, "deploy-script", &secret);
+
+        clients.revoke("deploy-script").unwrap();
+        assert_eq!(
+            clients.check("deploy-script", Some(&secret)),
+            Ok(PairingCheck::Unknown)
+        );
+    }
+
+    #[test]
+    fn only_listed_browser_origins_are_allowed() {
+        let allowed = vec!["https://dapp.example".to_string()];
+        assert!(is_origin_allowed(None, &allowed));
+        assert!(is_origin_allowed(Some("https://dapp.example"), &allowed));
+        assert!(!is_origin_allowed(Some("https://evil.example"), &allowed));
+        assert!(!is_origin_allowed(Some("http://localhost:3000"), &allowed));
+        assert!(!is_origin_allowed(
+            Some("chrome-extension://abcdefghijklmnop"),
+            &allowed
+        ));
+    }
+}
@@ -0,0 +1,192 @@
+//! Discovery files for local echo clients.
+//!
+//! While the echo server listens, the app keeps `<pid>.json` in a per-user
+//! directory: `$TONDEVWALLET_DISCOVERY_DIR` when set, `~/.tondevwallet/instances`
+//! otherwise. Each running instance writes its own file, so a dev build and a
+//! release build are both listed and clients pick one instead of probing ports.
+//! The file is removed when the server stops; files left behind by a crash are
+//! pruned by the next instance.
+//!
+//! On Unix the file carries a token generated per run. A handshake sending it
+//! is paired without asking the user, since only the same OS user can read the
+//! file. Elsewhere the file keeps default permissions, so the token is left out
+//! and clients pair as any other.
+
+use super::protocol::PROTOCOL_VERSION;
+use crate::util::unix_millis;
+use log::{info, warn};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use sysinfo::{Pid, PidExt, System, SystemExt};
+
+/// Environment variable overriding the discovery directory
+pub const DISCOVERY_DIR_ENV: &str = "TONDEVWALLET_DISCOVERY_DIR";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoveryEntry {
+    pub pid: u32,
+    pub port: u16,
+    pub protocol_version: u32,
+    /// Handshake `token` that skips pairing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    pub app_version: String,
+    pub started_at: i64,
+    /// Unix socket speaking newline-delimited JSON
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub socket: Option<PathBuf>,
+}
+
+pub struct Discovery {
+    /// None when no home directory could be found
+    dir: Option<PathBuf>,
+    /// None where the file cannot be made private to the user
+    token: Option<String>,
+    app_version: String,
+    started_at: i64,
+    socket: Option<PathBuf>,
+    published: Mutex<Option<PathBuf>>,
+}
+
+impl Discovery {
+    pub fn new(home_dir: Option<PathBuf>, app_version: String, socket: Option<PathBuf>) -> Self {
+        let dir = std::env::var_os(DISCOVERY_DIR_ENV)
+            .map(PathBuf::from)
+            .or_else(|| home_dir.map(|home| home.join(".tondevwallet").join("instances")));
+        let bytes: [u8; 32] = rand::thread_rng().gen();
+
+        Self {
+            dir,
+            token: cfg!(unix).then(|| bytes.iter().map(|b| format!("{:02x}", b)).collect()),
+            app_version,
+            started_at: unix_millis(),
+            socket,
+            published: Mutex::new(None),
+        }
+    }
+
+    /// Whether a handshake token matches this run's token
+    pub fn is_valid_token(&self, token: &str) -> bool {
+        self.token
+            .as_ref()
+            .is_some_and(|own| crate::proxy::constant_time_eq(token.as_bytes(), own.as_bytes()))
+    }
+
+    /// Write the discovery file for the port the server listens on
+    pub fn publish(&self, port: u16) {
+        let Some(dir) = &self.dir else {
+            warn!("TON echo discovery: no home directory, not writing a discovery file");
+            return;
+        };
+
+        let pid = std::process::id();
+        let entry = DiscoveryEntry {
+            pid,
+            port,
+            protocol_version: PROTOCOL_VERSION,
+            token: self.token.clone(),
+            app_version: self.app_version.clone(),
+            started_at: self.started_at,
+            socket: self.socket.clone(),
+        };
+        let path = dir.join(format!("{}.json", pid));
+        match write_entry(dir, &path, &entry) {
+            Ok(()) => {
+                info!("TON echo discovery file written to {}", path.display());
+                *self.published.lock().unwrap() = Some(path);
+            }
+            Err(e) => warn!("TON echo discovery: {}", e),
+        }
+
+        prune_stale(dir);
+    }
+
+    /// Remove the discovery file, if one was written
+    pub fn remove(&self) {
+        let Some(path) = self.published.lock().unwrap().take() else {
+            return;
+        };
+        if let Err(e) = fs::remove_file(&path) {
+            warn!(
+                "TON echo discovery: failed to remove {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+fn write_entry(dir: &Path, path: &Path, entry: &DiscoveryEntry) -> Result<(), String> {
+    create_private_dir(dir).map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+
+    let json = serde_json::to_vec_pretty(entry).map_err(|e| e.to_string())?;
+    // Write then rename, so clients never read a partial file
+    let tmp = path.with_extension("json.tmp");
+    write_private_file(&tmp, &json)
+        .map_err(|e| format!("failed to write {}: {}", tmp.display(), e))?;
+    fs::rename(&tmp, path).map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
+
+/// Remove files of instances that are no longer running
+fn prune_stale(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    let mut system = System::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let pid = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".json"))
+            .and_then(|pid| pid.parse::<u32>().ok());
+        let Some(pid) = pid else {
+            continue;
+        };
+        if pid == std::process::id() || system.refresh_process(Pid::from_u32(pid)) {
+            continue;
+        }
+
+        info!("TON echo discovery: removing stale {}", path.display());
+        let _ = fs::remove_file(&path);
+    }
+}
+
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    // Directories that already exist, such as an override, keep their mode
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dir)
+}
+
+#[cfg(unix)]
+fn write_private_file(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(contents)
+}
+
+#[cfg(not(unix))]
+fn write_private_file(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    fs::write(path, contents)
+}
@@ -0,0 +1,114 @@
+//! Transaction dumps received from echo clients.
+//!
+//! Each `transactions_dump` is written to the `trace_dumps` table before the
+//! webview hears about it, so a dump sent while the window is closed or still
+//! loading is not lost. The webview is notified with the row's metadata only
+//! and loads the dump itself through `get_trace_dump`.
+
+use crate::db::Database;
+use crate::util::unix_millis;
+use rusqlite::OptionalExtension;
+use serde::Serialize;
+
+/// Event emitted after a dump is stored, carrying its [`TraceDumpInfo`]
+pub const TRACE_DUMP_ADDED_EVENT: &str = "trace_dump_added";
+
+/// Metadata of a stored dump
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceDumpInfo {
+    pub trace_dump_id: i64,
+    /// Client id of the echo client that sent the dump
+    pub source: Option<String>,
+    pub created_at: i64,
+    /// Size of the serialized dump in bytes
+    pub size: i64,
+}
+
+/// Stored dump with its serialized contents
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceDumpRow {
+    #[serde(flatten)]
+    pub info: TraceDumpInfo,
+    /// Dump serialized by the `traces` package
+    pub dump: String,
+}
+
+pub struct TraceDumps {
+    db: Database,
+}
+
+impl TraceDumps {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    pub fn insert(&self, source: Option<&str>, dump: &str) -> Result<TraceDumpInfo, String> {
+        let created_at = unix_millis();
+        let size = dump.len() as i64;
+        let trace_dump_id = self.db.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO trace_dumps (source, created_at, size, dump) VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![source, created_at, size, dump],
+            )?;
+            Ok(conn.last_insert_rowid())
+        })?;
+
+        Ok(TraceDumpInfo {
+            trace_dump_id,
+            source: source.map(str::to_string),
+            created_at,
+            size,
+        })
+    }
+
+    /// Metadata of all stored dumps, oldest first
+    pub fn list(&self) -> Result<Vec<TraceDumpInfo>, String> {
+        self.db.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT trace_dump_id, source, created_at, size
+                 FROM trace_dumps ORDER BY created_at, trace_dump_id",
+            )?;
+            let rows = stmt.query_map([], info_from_row)?;
+            rows.collect()
+        })
+    }
+
+    pub fn get(&self, trace_dump_id: i64) -> Result<Option<TraceDumpRow>, String> {
+        self.db.with_conn(|conn| {
+            conn.query_row(
+                "SELECT trace_dump_id, source, created_at, size, dump
+                 FROM trace_dumps WHERE trace_dump_id = ?1",
+                [trace_dump_id],
+                |row| {
+                    Ok(TraceDumpRow {
+                        info: info_from_row(row)?,
+                        dump: row.get(4)?,
+                    })
+                },
+            )
+            .optional()
+        })
+    }
+
+    pub fn delete(&self, trace_dump_id: i64) -> Result<(), String> {
+        let removed = self.db.with_conn(|conn| {
+            conn.execute(
+                "DELETE FROM trace_dumps WHERE trace_dump_id = ?1",
+                [trace_dump_id],
+            )
+        })?;
+        if removed == 0 {
+            return Err(format!("Unknown trace dump {}", trace_dump_id));
+        }
+        Ok(())
+    }
+}
+
+fn info_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<TraceDumpInfo> {
+    Ok(TraceDumpInfo {
+        trace_dump_id: row.get(0)?,
+        source: row.get(1)?,
+        created_at: row.get(2)?,
+        size: row.get(3)?,
+    })
+}
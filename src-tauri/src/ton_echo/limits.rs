@@ -0,0 +1,120 @@
+//! Size and rate limits for echo connections.
+//!
+//! Read from the `ton_echo_limits` setting when a connection opens. The value
+//! is a JSON object, and missing fields use the defaults below, so
+//! `{"messages_per_sec": 50}` is a valid setting.
+//!
+//! Frames and messages over the size limits close the connection with a
+//! `message too big` close frame; larger dumps are sent as
+//! `transactions_dump_chunk` messages. Clients over the rate limit are
+//! throttled rather than rejected: their next message is not read until the
+//! rate allows it, which leaves other connections unaffected.
+
+use crate::db::Database;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tungstenite::protocol::WebSocketConfig;
+
+pub const LIMITS_SETTING: &str = "ton_echo_limits";
+
+const MIN_MESSAGE_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EchoLimits {
+    /// Largest WebSocket frame accepted from a client
+    pub max_frame_bytes: usize,
+    /// Largest message accepted from a client, after joining its frames
+    pub max_message_bytes: usize,
+    /// Largest dump reassembled from chunks
+    pub max_dump_bytes: usize,
+    /// Messages per second a connection may send. 0 disables the limit.
+    pub messages_per_sec: u32,
+    /// Messages a connection may send at once before throttling starts
+    pub burst: u32,
+}
+
+impl Default for EchoLimits {
+    fn default() -> Self {
+        Self {
+            max_frame_bytes: 1024 * 1024,
+            max_message_bytes: 4 * 1024 * 1024,
+            max_dump_bytes: 64 * 1024 * 1024,
+            messages_per_sec: 20,
+            burst: 40,
+        }
+    }
+}
+
+impl EchoLimits {
+    pub fn load(db: &Database) -> Self {
+        match db.get_setting(LIMITS_SETTING) {
+            Ok(Some(raw)) => match serde_json::from_str::<EchoLimits>(&raw) {
+                Ok(limits) => return limits.clamped(),
+                Err(e) => warn!("TON echo limits: invalid setting: {}", e),
+            },
+            Ok(None) => {}
+            Err(e) => warn!("TON echo limits: failed to read setting: {}", e),
+        }
+
+        Self::default()
+    }
+
+    fn clamped(mut self) -> Self {
+        self.max_message_bytes = self.max_message_bytes.max(MIN_MESSAGE_BYTES);
+        self.max_frame_bytes = self
+            .max_frame_bytes
+            .clamp(MIN_MESSAGE_BYTES, self.max_message_bytes);
+        self.max_dump_bytes = self.max_dump_bytes.max(self.max_message_bytes);
+        self.burst = self.burst.max(1);
+        self
+    }
+
+    pub fn ws_config(&self) -> WebSocketConfig {
+        WebSocketConfig {
+            max_message_size: Some(self.max_message_bytes),
+            max_frame_size: Some(self.max_frame_bytes),
+            ..WebSocketConfig::default()
+        }
+    }
+}
+
+/// Token bucket refilled at `messages_per_sec`
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(limits: &EchoLimits) -> Self {
+        Self {
+            rate: limits.messages_per_sec as f64,
+            burst: limits.burst as f64,
+            tokens: limits.burst as f64,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Take a token for one message. Returns how long to wait first when the
+    /// bucket is empty.
+    pub fn acquire(&mut self) -> Option<Duration> {
+        if self.rate <= 0.0 {
+            return None;
+        }
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.updated = now;
+
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            None
+        } else {
+            Some(Duration::from_secs_f64(-self.tokens / self.rate))
+        }
+    }
+}
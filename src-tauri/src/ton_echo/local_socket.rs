@@ -0,0 +1,262 @@
+//! Newline-delimited JSON transport for the echo server.
+//!
+//! Besides its WebSocket port, the echo server listens on a Unix domain socket
+//! in the app data directory. Every line a client writes is one protocol
+//! message and every reply is written back as one line, so test runners and
+//! CLI tools can talk to the wallet without opening a TCP port.
+//!
+//! The socket lives in a directory only the user can enter and is itself
+//! mode 0600. Reaching it proves the client runs as the same user, so its
+//! handshakes are paired without asking.
+
+use super::limits::{EchoLimits, RateLimiter};
+use super::protocol::{EchoErrorCode, EchoResponse};
+use super::stats::EchoCounter;
+use super::{
+    handle_text, throttle, ClientSender, Connection, ConnectionState, EchoContext,
+    NEXT_CONNECTION_ID,
+};
+use crate::server_manager::Shutdown;
+use log::{info, warn};
+use std::fs;
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tungstenite::Message;
+
+/// Accepts local connections for one run of the echo server.
+/// Dropping it stops accepting and removes the socket file.
+pub struct LocalSocket {
+    path: PathBuf,
+    task: JoinHandle<()>,
+}
+
+impl Drop for LocalSocket {
+    fn drop(&mut self) {
+        self.task.abort();
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Listen on `path`. Failing to bind leaves the WebSocket server running alone.
+pub fn listen(path: &Path, ctx: &EchoContext, shutdown: &Shutdown) -> Option<LocalSocket> {
+    let listener = match bind(path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("TON echo: not listening on {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    info!("TON echo server listening on {}", path.display());
+
+    let ctx = ctx.clone();
+    let shutdown = shutdown.clone();
+    let task = tokio::spawn(async move {
+        let mut stop = shutdown.clone();
+        loop {
+            let stream = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        warn!("TON echo: local socket failed: {}", e);
+                        return;
+                    }
+                },
+                _ = stop.wait() => return,
+            };
+
+            info!("New TON echo connection on the local socket");
+            let ctx = ctx.clone();
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move {
+                let _session = shutdown.track();
+                if let Err(e) = handle_stream(stream, ctx, shutdown).await {
+                    info!("Error processing local connection: {}", e);
+                }
+            });
+        }
+    });
+
+    Some(LocalSocket {
+        path: path.to_path_buf(),
+        task,
+    })
+}
+
+fn bind(path: &Path) -> io::Result<UnixListener> {
+    // Only the user may enter the directory, so nobody can connect before
+    // the socket's own mode is set
+    if let Some(dir) = path.parent() {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+    }
+
+    if path.exists() {
+        // A socket that still accepts belongs to another running instance
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "the socket is used by another instance",
+            ));
+        }
+        fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+async fn handle_stream(
+    stream: UnixStream,
+    ctx: EchoContext,
+    shutdown: Shutdown,
+) -> Result<(), String> {
+    let limits = EchoLimits::load(&ctx.db);
+    let (read, write) = stream.into_split();
+    let (tx, rx) = mpsc::channel(32);
+    let writer = tokio::spawn(write_lines(write, rx));
+
+    let connection = Connection {
+        id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+        client: ClientSender { tx },
+        origin: None,
+        local: true,
+    };
+    let result = read_lines(read, &connection, &limits, &ctx, shutdown).await;
+
+    // Pending replies hold senders too; the writer stops once all are gone
+    ctx.pending.drop_connection(connection.id);
+    drop(connection);
+    let _ = writer.await;
+
+    result
+}
+
+async fn read_lines(
+    read: OwnedReadHalf,
+    connection: &Connection,
+    limits: &EchoLimits,
+    ctx: &EchoContext,
+    mut shutdown: Shutdown,
+) -> Result<(), String> {
+    let mut reader = BufReader::new(read);
+    let mut line = Vec::new();
+    let mut state = ConnectionState::default();
+    let mut rate = RateLimiter::new(limits);
+
+    let result = loop {
+        if !throttle(&mut rate, ctx, &mut shutdown).await {
+            break Ok(());
+        }
+
+        let read = tokio::select! {
+            read = read_line(&mut reader, &mut line, limits.max_message_bytes) => read,
+            _ = shutdown.wait() => break Ok(()),
+        };
+        match read {
+            Ok(true) => {}
+            Ok(false) => break Ok(()),
+            Err(LineError::TooLong) => {
+                info!("TON echo: closing local connection with an oversized line");
+                ctx.stats.incr(EchoCounter::Oversized);
+                let _ = connection
+                    .client
+                    .send(&EchoResponse::error(
+                        None,
+                        EchoErrorCode::TooLarge,
+                        format!(
+                            "Line is larger than {} bytes, send dumps as transactions_dump_chunk",
+                            limits.max_message_bytes
+                        ),
+                    ))
+                    .await;
+                break Ok(());
+            }
+            Err(LineError::Io(e)) => break Err(e.to_string()),
+        }
+
+        let text = match std::str::from_utf8(&line) {
+            Ok(text) if text.trim().is_empty() => continue,
+            Ok(text) => text,
+            Err(_) => {
+                let response =
+                    EchoResponse::error(None, EchoErrorCode::InvalidJson, "Line is not UTF-8");
+                if let Err(e) = connection.client.send(&response).await {
+                    break Err(e);
+                }
+                continue;
+            }
+        };
+        if let Err(e) = handle_text(text, connection, &mut state, limits, ctx).await {
+            break Err(e);
+        }
+    };
+
+    state.discard_chunks(ctx);
+    let _ = connection.client.tx.send(Message::Close(None)).await;
+    result
+}
+
+enum LineError {
+    /// The line is longer than the message size limit
+    TooLong,
+    Io(io::Error),
+}
+
+/// Read one line into `line`, without its line ending.
+/// False at the end of the stream.
+async fn read_line(
+    reader: &mut BufReader<OwnedReadHalf>,
+    line: &mut Vec<u8>,
+    max_bytes: usize,
+) -> Result<bool, LineError> {
+    line.clear();
+    let read = (&mut *reader)
+        .take(max_bytes as u64 + 1)
+        .read_until(b'\n', line)
+        .await
+        .map_err(LineError::Io)?;
+    if read == 0 {
+        return Ok(false);
+    }
+
+    if line.last() == Some(&b'\n') {
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+    } else if line.len() > max_bytes {
+        return Err(LineError::TooLong);
+    }
+    Ok(true)
+}
+
+/// Writes replies as lines until the connection is closed
+async fn write_lines(write: OwnedWriteHalf, mut rx: mpsc::Receiver<Message>) {
+    let mut write = BufWriter::new(write);
+    while let Some(message) = rx.recv().await {
+        let Message::Text(text) = message else {
+            break;
+        };
+        let written = async {
+            write.write_all(text.as_bytes()).await?;
+            write.write_all(b"\n").await?;
+            write.flush().await
+        };
+        if let Err(e) = written.await {
+            info!("Error writing to local echo client: {:?}", e);
+            break;
+        }
+    }
+    let _ = write.shutdown().await;
+}
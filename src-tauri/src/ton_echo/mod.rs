@@ -0,0 +1,933 @@
+mod address_book;
+mod chunks;
+mod clients;
+mod discovery;
+mod dumps;
+mod limits;
+#[cfg(unix)]
+mod local_socket;
+mod pending;
+mod protocol;
+mod stats;
+mod topics;
+
+pub use clients::{EchoClientRow, EchoClients};
+pub use discovery::Discovery;
+pub use dumps::{TraceDumpInfo, TraceDumpRow, TraceDumps};
+pub use pending::PendingReplies;
+pub use stats::{EchoStats, EchoStatsSnapshot};
+pub use topics::{EchoTopics, Topic};
+
+use crate::db::Database;
+use crate::server_manager::Shutdown;
+use address_book::LabelError;
+use chunks::{ChunkError, ChunkProgress, DumpChunks};
+use clients::PairingCheck;
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use limits::{EchoLimits, RateLimiter};
+use log::info;
+use protocol::{
+    parse_request, AddressLabel, EchoErrorCode, EchoRequest, EchoResponse, Handshake,
+    TransactionOutcome, PROTOCOL_VERSION, SERVER_NAME,
+};
+use serde_json::Value;
+use stats::EchoCounter;
+use std::collections::HashMap;
+use std::error::Error as stdError;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::{accept_hdr_async_with_config, WebSocketStream};
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::protocol::CloseFrame;
+use tungstenite::{http, Error as WsError, Message};
+
+struct EchoValue {
+    msg_type: String,
+    data: Value,
+}
+
+/// Events queued for the app before new requests are rejected as `busy`
+const EVENT_QUEUE_CAPACITY: usize = 100;
+/// Pause after a failed accept, so a persistent error does not spin
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Forwards echo messages to the app as Tauri events.
+/// Shared by every run of the echo server.
+///
+/// The queue is bounded and never waited on: when the app falls behind, the
+/// newest event is refused so a slow webview cannot stall the connections.
+#[derive(Clone)]
+pub struct EchoEvents {
+    tx: mpsc::Sender<EchoValue>,
+}
+
+impl EchoEvents {
+    pub fn spawn(app_handle: AppHandle) -> Self {
+        // Create a channel for sending events to main thread
+        let (tx, mut rx) = mpsc::channel::<EchoValue>(EVENT_QUEUE_CAPACITY);
+
+        // Spawn a task to handle the events
+        tauri::async_runtime::spawn(async move {
+            while let Some(value) = rx.recv().await {
+                match app_handle.emit(&value.msg_type, value.data.clone()) {
+                    Ok(_) => info!("Emitted event to app"),
+                    Err(err) => info!("Error emitting event: {:?}", err),
+                }
+            }
+        });
+
+        Self { tx }
+    }
+
+    fn emit(&self, msg_type: &str, data: Value) -> Result<(), EmitError> {
+        self.tx
+            .try_send(EchoValue {
+                msg_type: msg_type.to_string(),
+                data,
+            })
+            .map_err(|e| match e {
+                mpsc::error::TrySendError::Full(_) => EmitError::Busy,
+                mpsc::error::TrySendError::Closed(_) => EmitError::Closed,
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum EmitError {
+    /// The event queue is full
+    Busy,
+    Closed,
+}
+
+impl fmt::Display for EmitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmitError::Busy => write!(f, "The app is busy, retry later"),
+            EmitError::Closed => write!(f, "The app is not accepting messages"),
+        }
+    }
+}
+
+/// State shared by every run of the echo server
+#[derive(Clone)]
+pub struct EchoContext {
+    pub db: Database,
+    pub events: EchoEvents,
+    pub pending: Arc<PendingReplies>,
+    pub clients: Arc<EchoClients>,
+    pub dumps: Arc<TraceDumps>,
+    pub topics: Arc<EchoTopics>,
+    pub stats: Arc<EchoStats>,
+    pub discovery: Arc<Discovery>,
+    /// Unix socket served next to the WebSocket port
+    pub socket_path: Option<PathBuf>,
+}
+
+/// Outgoing side of one echo connection
+#[derive(Clone)]
+pub struct ClientSender {
+    tx: mpsc::Sender<Message>,
+}
+
+impl ClientSender {
+    pub async fn send(&self, response: &EchoResponse) -> Result<(), String> {
+        let json = serde_json::to_string(response).map_err(|e| e.to_string())?;
+        self.tx
+            .send(Message::Text(json))
+            .await
+            .map_err(|_| "Connection closed".to_string())
+    }
+}
+
+struct Connection {
+    id: u64,
+    client: ClientSender,
+    /// `Origin` of the WebSocket handshake
+    origin: Option<String>,
+    /// Connected through the local socket, which only the user can open
+    local: bool,
+}
+
+/// What a connection has set up through its messages
+#[derive(Default)]
+struct ConnectionState {
+    /// Client id of the last successful handshake
+    paired: Option<String>,
+    /// Paired with the discovery file token rather than by the user
+    trusted: bool,
+    /// Tasks forwarding topic updates to the client
+    subscriptions: HashMap<Topic, JoinHandle<()>>,
+    /// Dumps arriving in chunks
+    chunks: DumpChunks,
+}
+
+impl ConnectionState {
+    fn unsubscribe(&mut self, topic: Topic) {
+        if let Some(task) = self.subscriptions.remove(&topic) {
+            task.abort();
+        }
+    }
+
+    fn unsubscribe_all(&mut self) {
+        for (_, task) in self.subscriptions.drain() {
+            task.abort();
+        }
+    }
+
+    /// Count the partial dumps left when the connection closes
+    fn discard_chunks(&self, ctx: &EchoContext) {
+        let discarded = self.chunks.pending();
+        if discarded > 0 {
+            ctx.stats.add(EchoCounter::DiscardedDump, discarded as u64);
+        }
+    }
+}
+
+impl Drop for ConnectionState {
+    fn drop(&mut self) {
+        self.unsubscribe_all();
+    }
+}
+
+/// Recorded as the client of trusted connections that send no `client_id`
+const LOCAL_CLIENT: &str = "local";
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Report the user's decision on a `proxy_transaction` to the client that sent it
+#[tauri::command]
+pub async fn resolve_echo_request(
+    request_id: u64,
+    result: TransactionOutcome,
+    pending: tauri::State<'_, Arc<PendingReplies>>,
+) -> Result<(), String> {
+    pending.resolve(request_id, result).await
+}
+
+/// Publish a new value of a state topic to subscribed echo clients
+#[tauri::command]
+pub fn publish_echo_state(
+    topic: Topic,
+    data: Value,
+    topics: tauri::State<'_, Arc<EchoTopics>>,
+) -> Result<(), String> {
+    if topic == Topic::TraceAdded {
+        return Err("trace_added is published by the echo server".into());
+    }
+    topics.publish(topic, data);
+    Ok(())
+}
+
+/// Approve or reject a client waiting on its first handshake
+#[tauri::command]
+pub fn respond_echo_pairing(
+    pairing_id: u64,
+    approve: bool,
+    clients: tauri::State<'_, Arc<EchoClients>>,
+) -> Result<(), String> {
+    clients.respond(pairing_id, approve)
+}
+
+#[tauri::command]
+pub fn list_echo_clients(
+    clients: tauri::State<'_, Arc<EchoClients>>,
+) -> Result<Vec<EchoClientRow>, String> {
+    clients.list()
+}
+
+#[tauri::command]
+pub fn revoke_echo_client(
+    client_id: String,
+    clients: tauri::State<'_, Arc<EchoClients>>,
+) -> Result<(), String> {
+    clients.revoke(&client_id)
+}
+
+#[tauri::command]
+pub fn get_echo_stats(stats: tauri::State<'_, Arc<EchoStats>>) -> EchoStatsSnapshot {
+    stats.snapshot()
+}
+
+#[tauri::command]
+pub fn list_trace_dumps(
+    dumps: tauri::State<'_, Arc<TraceDumps>>,
+) -> Result<Vec<TraceDumpInfo>, String> {
+    dumps.list()
+}
+
+#[tauri::command]
+pub fn get_trace_dump(
+    trace_dump_id: i64,
+    dumps: tauri::State<'_, Arc<TraceDumps>>,
+) -> Result<TraceDumpRow, String> {
+    dumps
+        .get(trace_dump_id)?
+        .ok_or_else(|| format!("Unknown trace dump {}", trace_dump_id))
+}
+
+#[tauri::command]
+pub fn delete_trace_dump(
+    trace_dump_id: i64,
+    dumps: tauri::State<'_, Arc<TraceDumps>>,
+) -> Result<(), String> {
+    dumps.delete(trace_dump_id)
+}
+
+/// Bind the first free port between 33000 and 34000
+pub async fn bind_ton_echo_listener() -> Result<TcpListener, String> {
+    for port in 33000..34000 {
+        let addr = format!("127.0.0.1:{}", port);
+        match TcpListener::bind(&addr).await {
+            Ok(listener) => return Ok(listener),
+            Err(_) => {
+                // Port already in use, try the next one
+                continue;
+            }
+        }
+    }
+
+    Err("Could not find an available port between 33000 and 34000".into())
+}
+
+/// Path of the newline-delimited JSON socket, on platforms that have one
+pub fn local_socket_path(app_data_dir: &Path) -> Option<PathBuf> {
+    if cfg!(unix) {
+        Some(app_data_dir.join("ton_echo").join("echo.sock"))
+    } else {
+        None
+    }
+}
+
+/// Accept echo connections until the listener fails or a shutdown is requested
+pub async fn run_echo_server(
+    listener: TcpListener,
+    ctx: EchoContext,
+    mut shutdown: Shutdown,
+) -> Result<(), Box<dyn stdError + Send + Sync>> {
+    let _ = env_logger::try_init();
+
+    // Closed together with the listener, so a restart can bind it again
+    #[cfg(unix)]
+    let _local_socket = ctx
+        .socket_path
+        .as_deref()
+        .and_then(|path| local_socket::listen(path, &ctx, &shutdown));
+
+    loop {
+        let (stream, addr) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    // Usually out of file descriptors; the listener itself is fine
+                    info!("TON echo failed to accept a connection: {}", e);
+                    tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                    continue;
+                }
+            },
+            _ = shutdown.wait() => return Ok(()),
+        };
+
+        info!("New TON echo connection from: {}", addr);
+        let ctx = ctx.clone();
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            let _session = shutdown.track();
+            if let Err(e) = handle_connection(stream, ctx, shutdown).await {
+                info!("Error processing connection: {:?}", e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    ctx: EchoContext,
+    shutdown: Shutdown,
+) -> Result<(), Box<dyn stdError + Send + Sync>> {
+    let allowed_origins = ctx.clients.allowed_origins();
+    let mut origin = None;
+    // The error type is fixed by tungstenite
+    #[allow(clippy::result_large_err)]
+    let origin_callback = |req: &Request, res: Response| {
+        origin = req
+            .headers()
+            .get(http::header::ORIGIN)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+
+        if clients::is_origin_allowed(origin.as_deref(), &allowed_origins) {
+            Ok(res)
+        } else {
+            info!("Rejected TON echo connection from origin {:?}", origin);
+            Err(forbidden_response())
+        }
+    };
+
+    let limits = EchoLimits::load(&ctx.db);
+    let ws_stream =
+        accept_hdr_async_with_config(stream, origin_callback, Some(limits.ws_config())).await?;
+    info!("WebSocket connection established");
+
+    let (write, read) = ws_stream.split();
+    let (tx, rx) = mpsc::channel(32);
+    let writer = tokio::spawn(write_messages(write, rx));
+
+    let connection = Connection {
+        id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+        client: ClientSender { tx },
+        origin,
+        local: false,
+    };
+    let result = process_messages(read, &connection, &limits, &ctx, shutdown).await;
+
+    // Pending replies hold senders too; the writer stops once all are gone
+    ctx.pending.drop_connection(connection.id);
+    drop(connection);
+    let _ = writer.await;
+
+    result
+}
+
+fn forbidden_response() -> ErrorResponse {
+    http::Response::builder()
+        .status(http::StatusCode::FORBIDDEN)
+        .body(Some(format!(
+            "Origin is not allowed. Add it to the {} setting.",
+            clients::ALLOWED_ORIGINS_SETTING
+        )))
+        .unwrap()
+}
+
+/// Owns the WebSocket sink so handlers and pending replies can write to it.
+/// Stops after the first close frame.
+async fn write_messages(
+    mut write: SplitSink<WebSocketStream<TcpStream>, Message>,
+    mut rx: mpsc::Receiver<Message>,
+) {
+    while let Some(message) = rx.recv().await {
+        let is_close = matches!(message, Message::Close(_));
+        if let Err(e) = write.send(message).await {
+            info!("Error writing to echo client: {:?}", e);
+            break;
+        }
+        if is_close {
+            break;
+        }
+    }
+    let _ = write.close().await;
+}
+
+async fn process_messages(
+    mut read: SplitStream<WebSocketStream<TcpStream>>,
+    connection: &Connection,
+    limits: &EchoLimits,
+    ctx: &EchoContext,
+    mut shutdown: Shutdown,
+) -> Result<(), Box<dyn stdError + Send + Sync>> {
+    let mut state = ConnectionState::default();
+    let mut rate = RateLimiter::new(limits);
+
+    let result = loop {
+        if !throttle(&mut rate, ctx, &mut shutdown).await {
+            break close(connection, CloseCode::Away, "server shutting down").await;
+        }
+
+        let message = tokio::select! {
+            message = read.next() => message,
+            _ = shutdown.wait() => break close(connection, CloseCode::Away, "server shutting down").await,
+        };
+        let message = match message {
+            None => break Ok(()),
+            Some(Ok(message)) => message,
+            Some(Err(WsError::Capacity(e))) => {
+                info!("TON echo: closing oversized connection: {}", e);
+                ctx.stats.incr(EchoCounter::Oversized);
+                break close(
+                    connection,
+                    CloseCode::Size,
+                    "message too big, send dumps as transactions_dump_chunk",
+                )
+                .await;
+            }
+            Some(Err(e)) => break Err(e.into()),
+        };
+
+        if !message.is_text() {
+            continue;
+        }
+
+        let text = message.to_text()?;
+        if let Err(e) = handle_text(text, connection, &mut state, limits, ctx).await {
+            break Err(e.into());
+        }
+    };
+
+    state.discard_chunks(ctx);
+    result
+}
+
+/// Wait until the rate limit lets the next message in. False when the server
+/// stops first.
+async fn throttle(rate: &mut RateLimiter, ctx: &EchoContext, shutdown: &mut Shutdown) -> bool {
+    // Throttle before reading, so a flooding client is held back by the transport
+    let Some(delay) = rate.acquire() else {
+        return true;
+    };
+    ctx.stats.incr(EchoCounter::Throttled);
+    tokio::select! {
+        _ = tokio::time::sleep(delay) => true,
+        _ = shutdown.wait() => false,
+    }
+}
+
+/// Answer one message from a client
+async fn handle_text(
+    text: &str,
+    connection: &Connection,
+    state: &mut ConnectionState,
+    limits: &EchoLimits,
+    ctx: &EchoContext,
+) -> Result<(), String> {
+    ctx.stats.incr(EchoCounter::Received);
+    info!("Received message ({} bytes)", text.len());
+
+    let response = match parse_request(text) {
+        Ok(request) => handle_request(request, connection, state, limits, ctx).await,
+        Err(error) => {
+            info!("Rejected echo message: {:?}", error);
+            error
+        }
+    };
+
+    connection.client.send(&response).await
+}
+
+async fn close(
+    connection: &Connection,
+    code: CloseCode,
+    reason: &'static str,
+) -> Result<(), Box<dyn stdError + Send + Sync>> {
+    let _ = connection
+        .client
+        .tx
+        .send(Message::Close(Some(CloseFrame {
+            code,
+            reason: reason.into(),
+        })))
+        .await;
+    Ok(())
+}
+
+/// Pair the client of a handshake and announce the server
+async fn handle_handshake(
+    handshake: Handshake,
+    connection: &Connection,
+    state: &mut ConnectionState,
+    ctx: &EchoContext,
+) -> EchoResponse {
+    let Handshake {
+        id,
+        version,
+        client_id,
+        client_secret,
+        client_name,
+        token,
+    } = handshake;
+    if version == Some(0) {
+        return EchoResponse::error(
+            Some(id),
+            EchoErrorCode::UnsupportedVersion,
+            format!(
+                "Protocol version 0 is not supported, server speaks {}",
+                PROTOCOL_VERSION
+            ),
+        );
+    }
+
+    // The token and the local socket are only reachable by the user running the app
+    let trusted = match token {
+        Some(token) if !ctx.discovery.is_valid_token(&token) => {
+            info!("TON echo: {:?} sent an invalid token", client_id);
+            return EchoResponse::error(
+                Some(id),
+                EchoErrorCode::Unauthorized,
+                "Invalid token, read it again from the discovery file",
+            );
+        }
+        Some(_) => true,
+        None => connection.local,
+    };
+    if trusted {
+        state.paired = Some(client_id.unwrap_or_else(|| LOCAL_CLIENT.to_string()));
+        state.trusted = true;
+        return handshake_response(id, None);
+    }
+
+    let Some(client_id) = client_id else {
+        return EchoResponse::error(
+            Some(id),
+            EchoErrorCode::InvalidMessage,
+            "Send a client_id to pair this client under",
+        );
+    };
+
+    match ctx.clients.check(&client_id, client_secret.as_deref()) {
+        Ok(PairingCheck::Paired) => {
+            state.paired = Some(client_id);
+            state.trusted = false;
+            return handshake_response(id, None);
+        }
+        Ok(PairingCheck::WrongSecret) => {
+            info!("TON echo: {:?} sent a wrong client secret", client_id);
+            return EchoResponse::error(
+                Some(id),
+                EchoErrorCode::Unauthorized,
+                "Wrong client_secret for this client_id, revoke the client in the wallet to pair it again",
+            );
+        }
+        Ok(PairingCheck::Unknown) => {}
+        Err(e) => return EchoResponse::error(Some(id), EchoErrorCode::Internal, e),
+    }
+
+    // Let the client extend its handshake timeout while the user decides
+    let _ = connection
+        .client
+        .send(&EchoResponse::PairingPending { id: id.clone() })
+        .await;
+
+    let secret = match ctx
+        .clients
+        .pair(
+            &client_id,
+            client_name.as_deref(),
+            connection.origin.as_deref(),
+            &ctx.events,
+        )
+        .await
+    {
+        Ok(secret) => secret,
+        Err(e) => {
+            info!("TON echo: {:?} not paired: {}", client_id, e);
+            return EchoResponse::error(Some(id), EchoErrorCode::PairingRejected, e);
+        }
+    };
+    state.paired = Some(client_id);
+    state.trusted = false;
+    handshake_response(id, Some(secret))
+}
+
+fn handshake_response(id: Value, client_secret: Option<String>) -> EchoResponse {
+    info!("Sent handshake response");
+    EchoResponse::Response {
+        id,
+        name: SERVER_NAME,
+        version: PROTOCOL_VERSION,
+        client_secret,
+    }
+}
+
+/// Answer a handshake or forward the request to the app
+async fn handle_request(
+    mut request: EchoRequest,
+    connection: &Connection,
+    state: &mut ConnectionState,
+    limits: &EchoLimits,
+    ctx: &EchoContext,
+) -> EchoResponse {
+    let (id, kind) = match &request {
+        EchoRequest::Handshake(handshake) => {
+            return handle_handshake(handshake.clone(), connection, state, ctx).await;
+        }
+        EchoRequest::ProxyTransaction { id, .. }
+        | EchoRequest::TransactionsDump { id, .. }
+        | EchoRequest::TransactionsDumpChunk { id, .. }
+        | EchoRequest::TonconnectSvg { id, .. }
+        | EchoRequest::Subscribe { id, .. }
+        | EchoRequest::Unsubscribe { id, .. }
+        | EchoRequest::AddressBookUpsert { id, .. }
+        | EchoRequest::LabelContracts { id, .. } => (id.clone(), request.kind()),
+        EchoRequest::Unknown => {
+            return EchoResponse::error(None, EchoErrorCode::UnknownType, "Unknown message type")
+        }
+    };
+
+    // Revoking a client also cuts off its open connections
+    let authorized = match state.paired.as_deref() {
+        Some(_) if state.trusted => Ok(true),
+        Some(client_id) => ctx.clients.is_paired(client_id),
+        None => Ok(false),
+    };
+    match authorized {
+        Ok(true) => {}
+        Ok(false) => {
+            state.paired = None;
+            state.unsubscribe_all();
+            return EchoResponse::error(
+                id,
+                EchoErrorCode::Unauthorized,
+                "Send a handshake and pair this client first",
+            );
+        }
+        Err(e) => return EchoResponse::error(id, EchoErrorCode::Internal, e),
+    }
+
+    if let EchoRequest::ProxyTransaction {
+        id,
+        await_result: true,
+        request_id,
+        ..
+    } = &mut request
+    {
+        let Some(client_id) = id.clone() else {
+            return EchoResponse::error(
+                None,
+                EchoErrorCode::InvalidMessage,
+                "await_result requires an id",
+            );
+        };
+        *request_id = Some(ctx.pending.register(
+            connection.id,
+            client_id,
+            connection.client.clone(),
+        ));
+    }
+
+    info!("Received {}", kind);
+    match &request {
+        EchoRequest::TransactionsDump { data, .. } => {
+            return store_dump(id, data, state.paired.as_deref(), ctx);
+        }
+        EchoRequest::TransactionsDumpChunk {
+            dump_id,
+            index,
+            total,
+            data,
+            ..
+        } => {
+            ctx.stats.incr(EchoCounter::ChunkReceived);
+            return match state.chunks.add(
+                dump_id,
+                *index,
+                *total,
+                data.clone(),
+                limits.max_dump_bytes,
+            ) {
+                Ok(ChunkProgress::Partial) => EchoResponse::Ack { id, request: kind },
+                Ok(ChunkProgress::Complete(dump)) => {
+                    ctx.stats.incr(EchoCounter::ChunkedDump);
+                    store_dump(id, &dump, state.paired.as_deref(), ctx)
+                }
+                Err(e) => {
+                    ctx.stats.incr(EchoCounter::DiscardedDump);
+                    let code = match e {
+                        ChunkError::TooLarge(_) => EchoErrorCode::TooLarge,
+                        ChunkError::Invalid(_) => EchoErrorCode::InvalidMessage,
+                    };
+                    EchoResponse::error(id, code, e.to_string())
+                }
+            };
+        }
+        EchoRequest::Subscribe { topics, .. } => {
+            for &topic in topics {
+                subscribe(topic, connection, state, ctx);
+            }
+            return EchoResponse::Ack { id, request: kind };
+        }
+        EchoRequest::Unsubscribe { topics, .. } => {
+            for &topic in topics {
+                state.unsubscribe(topic);
+            }
+            return EchoResponse::Ack { id, request: kind };
+        }
+        EchoRequest::AddressBookUpsert { data, .. } => {
+            return label_addresses(
+                id,
+                kind,
+                data.chain_id,
+                std::slice::from_ref(&data.entry),
+                ctx,
+            );
+        }
+        EchoRequest::LabelContracts { data, .. } => {
+            return label_addresses(id, kind, data.chain_id, &data.contracts, ctx);
+        }
+        _ => {}
+    }
+
+    let data = match serde_json::to_value(&request) {
+        Ok(data) => data,
+        Err(e) => return EchoResponse::error(id, EchoErrorCode::Internal, e.to_string()),
+    };
+
+    // Send the event through the channel
+    if let Err(err) = ctx.events.emit(kind, data) {
+        info!("Error sending {} event: {:?}", kind, err);
+        if let EchoRequest::ProxyTransaction {
+            request_id: Some(request_id),
+            ..
+        } = &request
+        {
+            ctx.pending.forget(*request_id);
+        }
+        let code = match err {
+            EmitError::Busy => {
+                ctx.stats.incr(EchoCounter::RejectedBusy);
+                EchoErrorCode::Busy
+            }
+            EmitError::Closed => EchoErrorCode::Internal,
+        };
+        return EchoResponse::error(id, code, err.to_string());
+    }
+
+    ctx.stats.incr(EchoCounter::Forwarded);
+    EchoResponse::Ack { id, request: kind }
+}
+
+/// Forward updates of `topic` to the client, starting with its current value
+fn subscribe(
+    topic: Topic,
+    connection: &Connection,
+    state: &mut ConnectionState,
+    ctx: &EchoContext,
+) {
+    let Some(client_id) = state.paired.clone() else {
+        return;
+    };
+    if state.subscriptions.contains_key(&topic) {
+        return;
+    }
+
+    let trusted = state.trusted;
+    let (mut rx, latest) = ctx.topics.subscribe(topic);
+    let client = connection.client.clone();
+    let clients = ctx.clients.clone();
+    let task = tokio::spawn(async move {
+        let mut next = latest;
+        loop {
+            let data = match next.take() {
+                Some(data) => data,
+                None => match rx.recv().await {
+                    Ok(data) => data,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        info!(
+                            "TON echo: {:?} missed {} {:?} updates",
+                            client_id, skipped, topic
+                        );
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+            };
+
+            // Stop as soon as the client is revoked, even if it stays silent
+            if !trusted && !matches!(clients.is_paired(&client_id), Ok(true)) {
+                break;
+            }
+            if client
+                .send(&EchoResponse::Update { topic, data })
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+    state.subscriptions.insert(topic, task);
+}
+
+/// Write address book entries and tell the app to reload them
+fn label_addresses(
+    id: Option<Value>,
+    kind: &'static str,
+    chain_id: i64,
+    labels: &[AddressLabel],
+    ctx: &EchoContext,
+) -> EchoResponse {
+    let changed = match address_book::upsert_labels(&ctx.db, chain_id, labels) {
+        Ok(changed) => changed,
+        Err(e) => {
+            info!("Rejected {}: {}", kind, e);
+            let code = match e {
+                LabelError::UnknownNetwork(_) => EchoErrorCode::UnknownNetwork,
+                LabelError::Invalid(_) => EchoErrorCode::InvalidMessage,
+                LabelError::Internal(_) => EchoErrorCode::Internal,
+            };
+            return EchoResponse::error(id, code, e.to_string());
+        }
+    };
+    info!(
+        "Labelled {} addresses ({} new) on networks {:?}",
+        changed.inserted + changed.updated,
+        changed.inserted,
+        changed.network_ids
+    );
+
+    let notification = match serde_json::to_value(&changed) {
+        Ok(notification) => notification,
+        Err(e) => return EchoResponse::error(id, EchoErrorCode::Internal, e.to_string()),
+    };
+    // The entries are saved, so the app sees them on its next reload anyway
+    if let Err(err) = ctx
+        .events
+        .emit(address_book::ADDRESS_BOOK_CHANGED_EVENT, notification)
+    {
+        ctx.stats.incr(EchoCounter::DroppedEvent);
+        info!(
+            "Error sending {} event: {:?}",
+            address_book::ADDRESS_BOOK_CHANGED_EVENT,
+            err
+        );
+    }
+
+    EchoResponse::Ack { id, request: kind }
+}
+
+/// Keep a dump in the database and tell the app where to find it
+fn store_dump(
+    id: Option<Value>,
+    dump: &str,
+    source: Option<&str>,
+    ctx: &EchoContext,
+) -> EchoResponse {
+    let info = match ctx.dumps.insert(source, dump) {
+        Ok(info) => info,
+        Err(e) => {
+            info!("Error storing transactions dump: {}", e);
+            return EchoResponse::error(id, EchoErrorCode::Internal, e);
+        }
+    };
+    info!(
+        "Stored transactions dump {} ({} bytes)",
+        info.trace_dump_id, info.size
+    );
+
+    let notification = match serde_json::to_value(&info) {
+        Ok(notification) => notification,
+        Err(e) => return EchoResponse::error(id, EchoErrorCode::Internal, e.to_string()),
+    };
+    ctx.topics.publish(Topic::TraceAdded, notification.clone());
+
+    // The dump is saved, so the app can still load it later
+    if let Err(err) = ctx.events.emit(dumps::TRACE_DUMP_ADDED_EVENT, notification) {
+        ctx.stats.incr(EchoCounter::DroppedEvent);
+        info!(
+            "Error sending {} event: {:?}",
+            dumps::TRACE_DUMP_ADDED_EVENT,
+            err
+        );
+    }
+
+    EchoResponse::Ack {
+        id,
+        request: "transactions_dump",
+    }
+}
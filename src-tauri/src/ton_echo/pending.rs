@@ -0,0 +1,84 @@
+//! Echo requests waiting for the user's decision in the app.
+//!
+//! A `proxy_transaction` sent with `await_result` keeps its WebSocket open.
+//! The request is registered here under a server-side `request_id`, which is
+//! added to the `proxy_transaction` event. Once the user approves or rejects
+//! the message, the webview calls `resolve_echo_request` and the outcome is
+//! sent to the original client with the client's own `id`.
+
+use super::protocol::{EchoResponse, TransactionOutcome};
+use super::ClientSender;
+use log::info;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+struct PendingReply {
+    connection: u64,
+    client_id: Value,
+    client: ClientSender,
+}
+
+#[derive(Default)]
+pub struct PendingReplies {
+    next_id: AtomicU64,
+    pending: Mutex<HashMap<u64, PendingReply>>,
+}
+
+impl PendingReplies {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a request from `connection` and return its `request_id`
+    pub fn register(&self, connection: u64, client_id: Value, client: ClientSender) -> u64 {
+        let request_id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.pending.lock().unwrap().insert(
+            request_id,
+            PendingReply {
+                connection,
+                client_id,
+                client,
+            },
+        );
+        request_id
+    }
+
+    /// Send the outcome of a request back to its client
+    pub async fn resolve(
+        &self,
+        request_id: u64,
+        outcome: TransactionOutcome,
+    ) -> Result<(), String> {
+        let reply = self
+            .pending
+            .lock()
+            .unwrap()
+            .remove(&request_id)
+            .ok_or_else(|| format!("No pending echo request {}", request_id))?;
+
+        info!("Resolving echo request {}", request_id);
+        reply
+            .client
+            .send(&EchoResponse::TransactionResult {
+                id: reply.client_id,
+                result: outcome,
+            })
+            .await
+            .map_err(|_| format!("Client of echo request {} disconnected", request_id))
+    }
+
+    /// Forget a request the app never received
+    pub fn forget(&self, request_id: u64) {
+        self.pending.lock().unwrap().remove(&request_id);
+    }
+
+    /// Forget every request of a closed connection
+    pub fn drop_connection(&self, connection: u64) {
+        self.pending
+            .lock()
+            .unwrap()
+            .retain(|_, reply| reply.connection != connection);
+    }
+}
@@ -0,0 +1,298 @@
+//! Message types of the TON echo WebSocket protocol.
+//!
+//! Every message is a JSON object tagged by `type`. Requests may carry an `id`
+//! of any JSON type, which is echoed back in the matching ack or error.
+//!
+//! Version history:
+//! - 1: typed messages, `ack` and `error` responses, version in the handshake
+//! - 2: `await_result` on `proxy_transaction` and the `transaction_result` reply
+//! - 3: `client_id` and `client_name` on the handshake, `pairing_pending` while
+//!   the user decides; other messages are refused until a paired handshake
+//! - 4: `subscribe` and `unsubscribe` to topics, pushed as `update`
+//! - 5: `transactions_dump_chunk` for dumps over the message size limit,
+//!   `busy` and `too_large` errors
+//! - 6: `token` on the handshake, read from the discovery file, pairs the
+//!   client without asking the user
+//! - 7: `address_book_upsert` and `label_contracts`, `unknown_network` error
+//! - 8: `client_id` is required to pair; pairing issues a `client_secret`
+//!   that later handshakes of the client must send
+
+use super::topics::Topic;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Version announced in the handshake response
+pub const PROTOCOL_VERSION: u32 = 8;
+/// Name clients look for to recognise the wallet
+pub const SERVER_NAME: &str = "tondevwallet";
+
+/// Messages accepted from echo clients.
+///
+/// Forwarded requests are emitted to the webview as their serialized form,
+/// so listeners keep receiving `{ type, data }`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EchoRequest {
+    Handshake(Handshake),
+    ProxyTransaction {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<Value>,
+        data: ProxyTransactionData,
+        /// Keep the connection open and reply with `transaction_result`.
+        /// Requires an `id`.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        await_result: bool,
+        /// Set by the server for the webview when a result is awaited
+        #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+        request_id: Option<u64>,
+    },
+    TransactionsDump {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<Value>,
+        /// Dump serialized by the `traces` package
+        data: String,
+    },
+    /// Part of a dump too large for one message. Acked per chunk; the last
+    /// chunk is acked as `transactions_dump` once the dump is stored.
+    TransactionsDumpChunk {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<Value>,
+        /// Client-chosen id shared by all chunks of the dump
+        dump_id: String,
+        /// Zero-based position of this chunk
+        index: u32,
+        total: u32,
+        data: String,
+    },
+    TonconnectSvg {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<Value>,
+        data: TonConnectSvgData,
+    },
+    /// Start receiving `update` messages for the topics
+    Subscribe {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<Value>,
+        topics: Vec<Topic>,
+    },
+    Unsubscribe {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<Value>,
+        topics: Vec<Topic>,
+    },
+    /// Add an address book entry, or rename the one for the same address
+    AddressBookUpsert {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<Value>,
+        data: AddressBookUpsertData,
+    },
+    /// Add or rename several entries at once, e.g. contracts a script deployed
+    LabelContracts {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<Value>,
+        data: LabelContractsData,
+    },
+    /// Any `type` this server does not know
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Handshake {
+    pub id: Value,
+    /// Protocol version spoken by the client. Absent for pre-versioning clients.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+    /// Stable id the client is paired under. Required unless the connection
+    /// is trusted through the token or the local socket.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// Secret issued when the client was paired
+    #[serde(default, skip_serializing)]
+    pub client_secret: Option<String>,
+    /// Name shown to the user when pairing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_name: Option<String>,
+    /// Token from the discovery file. Skips pairing; a wrong token is
+    /// refused as `unauthorized`.
+    #[serde(default, skip_serializing)]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProxyTransactionData {
+    /// Decrypted TonConnect bridge message
+    pub payload: Value,
+    /// Hex wallet public key of the TonConnect session
+    #[serde(rename = "publicKey")]
+    pub public_key: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TonConnectSvgData {
+    /// Base64 PNG rendering of the QR code
+    pub image: String,
+}
+
+/// Title and description for one address
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressLabel {
+    /// Raw or user-friendly address
+    pub address: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressBookUpsertData {
+    /// Chain ID of the networks to write to, e.g. -239 or -3
+    pub chain_id: i64,
+    #[serde(flatten)]
+    pub entry: AddressLabel,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabelContractsData {
+    /// Chain ID of the networks to write to, e.g. -239 or -3
+    pub chain_id: i64,
+    pub contracts: Vec<AddressLabel>,
+}
+
+impl EchoRequest {
+    /// Value of the `type` tag
+    pub fn kind(&self) -> &'static str {
+        match self {
+            EchoRequest::Handshake(_) => "handshake",
+            EchoRequest::ProxyTransaction { .. } => "proxy_transaction",
+            EchoRequest::TransactionsDump { .. } => "transactions_dump",
+            EchoRequest::TransactionsDumpChunk { .. } => "transactions_dump_chunk",
+            EchoRequest::TonconnectSvg { .. } => "tonconnect_svg",
+            EchoRequest::Subscribe { .. } => "subscribe",
+            EchoRequest::Unsubscribe { .. } => "unsubscribe",
+            EchoRequest::AddressBookUpsert { .. } => "address_book_upsert",
+            EchoRequest::LabelContracts { .. } => "label_contracts",
+            EchoRequest::Unknown => "unknown",
+        }
+    }
+}
+
+/// Messages sent back to echo clients
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EchoResponse {
+    /// Handshake reply. Keeps the `response` tag and `name` that
+    /// pre-versioning clients check for.
+    Response {
+        id: Value,
+        name: &'static str,
+        version: u32,
+        /// Issued once, when the user pairs the client. Send it on every
+        /// later handshake.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        client_secret: Option<String>,
+    },
+    /// Sent before the handshake response while the user is asked to pair
+    /// the client
+    PairingPending { id: Value },
+    /// The request was accepted and forwarded to the app
+    Ack {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<Value>,
+        request: &'static str,
+    },
+    /// New value of a subscribed topic. The current value is sent right
+    /// after subscribing when the app has published one.
+    Update { topic: Topic, data: Value },
+    /// Outcome of a `proxy_transaction` sent with `await_result`
+    TransactionResult {
+        id: Value,
+        result: TransactionOutcome,
+    },
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<Value>,
+        code: EchoErrorCode,
+        message: String,
+    },
+}
+
+/// What the user decided about a transaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum TransactionOutcome {
+    Approved {
+        /// Base64 BOC of the signed external message
+        boc: String,
+        /// Hex hash of the external message
+        hash: String,
+    },
+    Rejected {
+        reason: String,
+    },
+    /// The user approved, but the wallet could not produce the message
+    Failed {
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EchoErrorCode {
+    /// The message is not valid JSON
+    InvalidJson,
+    /// The `type` is not supported by this server
+    UnknownType,
+    /// The `type` is known but the fields do not match it
+    InvalidMessage,
+    /// The client announced a protocol version this server cannot speak
+    UnsupportedVersion,
+    /// The connection has no paired handshake, or its client was revoked
+    Unauthorized,
+    /// The user did not approve the client
+    PairingRejected,
+    /// The app is not keeping up; retry later
+    Busy,
+    /// The dump is over the size limit
+    TooLarge,
+    /// No network uses the chain ID of the message
+    UnknownNetwork,
+    /// The app could not process the message
+    Internal,
+}
+
+impl EchoResponse {
+    pub fn error(id: Option<Value>, code: EchoErrorCode, message: impl Into<String>) -> Self {
+        EchoResponse::Error {
+            id,
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Parse a text frame. On failure returns the error response to send,
+/// with the request `id` when one could be read.
+pub fn parse_request(text: &str) -> Result<EchoRequest, EchoResponse> {
+    let value: Value = serde_json::from_str(text)
+        .map_err(|e| EchoResponse::error(None, EchoErrorCode::InvalidJson, e.to_string()))?;
+    let id = value.get("id").cloned();
+    let kind = value
+        .get("type")
+        .and_then(|t| t.as_str())
+        .map(str::to_string);
+
+    match serde_json::from_value::<EchoRequest>(value) {
+        Ok(EchoRequest::Unknown) => Err(EchoResponse::error(
+            id,
+            EchoErrorCode::UnknownType,
+            format!("Unknown message type {:?}", kind.unwrap_or_default()),
+        )),
+        Ok(request) => Ok(request),
+        Err(e) => Err(EchoResponse::error(
+            id,
+            EchoErrorCode::InvalidMessage,
+            e.to_string(),
+        )),
+    }
+}
@@ -0,0 +1,91 @@
+//! Counters for the echo server, read by the UI through `get_echo_stats`.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Default)]
+pub struct EchoStats {
+    received: AtomicU64,
+    forwarded: AtomicU64,
+    rejected_busy: AtomicU64,
+    dropped_events: AtomicU64,
+    throttled: AtomicU64,
+    oversized: AtomicU64,
+    chunks_received: AtomicU64,
+    chunked_dumps: AtomicU64,
+    discarded_dumps: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EchoStatsSnapshot {
+    /// Text messages read from clients
+    pub received: u64,
+    /// Requests handed to the app
+    pub forwarded: u64,
+    /// Requests rejected with `busy` because the app's event queue was full
+    pub rejected_busy: u64,
+    /// Server-side notifications lost to a full event queue
+    pub dropped_events: u64,
+    /// Messages delayed by the rate limit
+    pub throttled: u64,
+    /// Connections closed for a frame or message over the size limit
+    pub oversized: u64,
+    pub chunks_received: u64,
+    /// Dumps reassembled from chunks
+    pub chunked_dumps: u64,
+    /// Chunked dumps abandoned for size, bad chunks or a closed connection
+    pub discarded_dumps: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum EchoCounter {
+    Received,
+    Forwarded,
+    RejectedBusy,
+    DroppedEvent,
+    Throttled,
+    Oversized,
+    ChunkReceived,
+    ChunkedDump,
+    DiscardedDump,
+}
+
+impl EchoStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&self, counter: EchoCounter, n: u64) {
+        let field = match counter {
+            EchoCounter::Received => &self.received,
+            EchoCounter::Forwarded => &self.forwarded,
+            EchoCounter::RejectedBusy => &self.rejected_busy,
+            EchoCounter::DroppedEvent => &self.dropped_events,
+            EchoCounter::Throttled => &self.throttled,
+            EchoCounter::Oversized => &self.oversized,
+            EchoCounter::ChunkReceived => &self.chunks_received,
+            EchoCounter::ChunkedDump => &self.chunked_dumps,
+            EchoCounter::DiscardedDump => &self.discarded_dumps,
+        };
+        field.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn incr(&self, counter: EchoCounter) {
+        self.add(counter, 1);
+    }
+
+    pub fn snapshot(&self) -> EchoStatsSnapshot {
+        let get = |field: &AtomicU64| field.load(Ordering::Relaxed);
+        EchoStatsSnapshot {
+            received: get(&self.received),
+            forwarded: get(&self.forwarded),
+            rejected_busy: get(&self.rejected_busy),
+            dropped_events: get(&self.dropped_events),
+            throttled: get(&self.throttled),
+            oversized: get(&self.oversized),
+            chunks_received: get(&self.chunks_received),
+            chunked_dumps: get(&self.chunked_dumps),
+            discarded_dumps: get(&self.discarded_dumps),
+        }
+    }
+}
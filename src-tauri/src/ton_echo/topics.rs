@@ -0,0 +1,83 @@
+//! State pushed to subscribed echo clients.
+//!
+//! Each topic has its own `broadcast` channel. The webview publishes wallet
+//! state through `publish_echo_state` whenever it changes, and the echo server
+//! publishes `trace_added` itself once a dump is stored. The last value of each
+//! topic is kept so a new subscriber starts from the current state instead of
+//! waiting for the next change.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+
+/// Updates buffered per topic before slow subscribers start missing them
+const TOPIC_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
+    /// Network selected in the app
+    SelectedNetwork,
+    /// Wallets with their addresses, without any key material
+    Wallets,
+    /// TonConnect messages waiting for the user
+    PendingMessages,
+    /// A transactions dump was stored
+    TraceAdded,
+}
+
+impl Topic {
+    pub const ALL: [Topic; 4] = [
+        Topic::SelectedNetwork,
+        Topic::Wallets,
+        Topic::PendingMessages,
+        Topic::TraceAdded,
+    ];
+
+    /// Whether the topic describes state, so its last value is replayed
+    fn is_state(self) -> bool {
+        !matches!(self, Topic::TraceAdded)
+    }
+}
+
+pub struct EchoTopics {
+    channels: HashMap<Topic, broadcast::Sender<Value>>,
+    latest: Mutex<HashMap<Topic, Value>>,
+}
+
+impl EchoTopics {
+    pub fn new() -> Self {
+        let channels = Topic::ALL
+            .into_iter()
+            .map(|topic| (topic, broadcast::channel(TOPIC_CAPACITY).0))
+            .collect();
+        Self {
+            channels,
+            latest: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn publish(&self, topic: Topic, data: Value) {
+        if topic.is_state() {
+            self.latest.lock().unwrap().insert(topic, data.clone());
+        }
+        // No subscribers is not an error
+        let _ = self.channels[&topic].send(data);
+    }
+
+    /// Receiver for future updates and the current state of the topic
+    pub fn subscribe(&self, topic: Topic) -> (broadcast::Receiver<Value>, Option<Value>) {
+        // Subscribe under the lock so no update falls between the two
+        let latest = self.latest.lock().unwrap();
+        let rx = self.channels[&topic].subscribe();
+        (rx, latest.get(&topic).cloned())
+    }
+}
+
+impl Default for EchoTopics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,599 @@
+//! Parser for TonConnect connect links.
+//!
+//! Dapps hand the wallet a connect request as a link, either opened directly
+//! (deep link, command line) or rendered as a QR code. The request is the same
+//! in every form, only the wrapper differs:
+//!
+//! - `tc://?v=2&id=…&r=…&ret=…`, the wallet-agnostic deep link
+//! - `tondevwallet://connect/?v=2&id=…`, this wallet's own scheme
+//! - universal links such as `https://app.tonkeeper.com/ton-connect?v=2&id=…`
+//! - Telegram links, `https://t.me/wallet?attach=wallet&startattach=tonconnect-v__2-id__…`,
+//!   which squeeze the query into one start parameter
+//!
+//! The launcher may prefix the link with `--url=`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use url::{form_urlencoded, Url};
+
+/// Connect protocol version this wallet implements
+pub const CONNECT_PROTOCOL_VERSION: u32 = 2;
+
+/// Start parameter prefix of Telegram links
+const TELEGRAM_PREFIX: &str = "tonconnect-";
+
+/// A parsed connect request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectRequest {
+    /// Protocol version of the link, the `v` parameter
+    pub version: u32,
+    /// Hex public key of the dapp's bridge session, the `id` parameter
+    pub client_id: String,
+    pub manifest_url: String,
+    /// Requested items, in the wire format of the `r` parameter
+    pub items: Vec<ConnectItem>,
+    pub return_strategy: ReturnStrategy,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "name", rename_all = "snake_case")]
+pub enum ConnectItem {
+    TonAddr,
+    TonProof { payload: String },
+}
+
+/// Where the wallet sends the user after answering, the `ret` parameter
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "url", rename_all = "snake_case")]
+pub enum ReturnStrategy {
+    Back,
+    None,
+    Url(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectLinkError {
+    Empty,
+    /// Not a URL at all
+    InvalidUrl(String),
+    /// A URL whose scheme no connect link uses
+    UnsupportedScheme(String),
+    /// A web link without connect parameters
+    NotAConnectLink,
+    MissingParameter(&'static str),
+    /// `v` is not a number, or a version this wallet cannot answer
+    UnsupportedVersion(String),
+    /// `id` is not a 32-byte hex key
+    InvalidClientId(String),
+    /// `r` is not a JSON object
+    InvalidRequest(String),
+    MissingManifestUrl,
+    InvalidManifestUrl(String),
+    /// An item without a name, or with fields that do not match its name
+    InvalidItem(String),
+    /// The request does not ask for the wallet address, which every connect must
+    MissingTonAddr,
+    InvalidReturnStrategy(String),
+    /// A Telegram start parameter that does not decode to a query
+    InvalidTelegramParameter(String),
+}
+
+impl fmt::Display for ConnectLinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectLinkError::Empty => write!(f, "Link is empty"),
+            ConnectLinkError::InvalidUrl(e) => write!(f, "Link is not a valid URL: {}", e),
+            ConnectLinkError::UnsupportedScheme(scheme) => {
+                write!(f, "Links with the {}: scheme are not connect links", scheme)
+            }
+            ConnectLinkError::NotAConnectLink => {
+                write!(f, "Link does not carry a TonConnect request")
+            }
+            ConnectLinkError::MissingParameter(name) => {
+                write!(f, "Link has no {} parameter", name)
+            }
+            ConnectLinkError::UnsupportedVersion(v) => write!(
+                f,
+                "Unsupported TonConnect version {:?}, expected {}",
+                v, CONNECT_PROTOCOL_VERSION
+            ),
+            ConnectLinkError::InvalidClientId(id) => {
+                write!(f, "Client id {:?} is not a 64 character hex key", id)
+            }
+            ConnectLinkError::InvalidRequest(e) => write!(f, "Invalid connect request: {}", e),
+            ConnectLinkError::MissingManifestUrl => {
+                write!(f, "Connect request has no manifestUrl")
+            }
+            ConnectLinkError::InvalidManifestUrl(url) => {
+                write!(f, "Manifest URL {:?} is not an http(s) URL", url)
+            }
+            ConnectLinkError::InvalidItem(e) => write!(f, "Invalid connect item: {}", e),
+            ConnectLinkError::MissingTonAddr => {
+                write!(f, "Connect request does not ask for ton_addr")
+            }
+            ConnectLinkError::InvalidReturnStrategy(ret) => {
+                write!(f, "Invalid return strategy {:?}", ret)
+            }
+            ConnectLinkError::InvalidTelegramParameter(param) => {
+                write!(f, "Invalid Telegram start parameter {:?}", param)
+            }
+        }
+    }
+}
+
+impl ConnectLinkError {
+    /// Whether the text is no connect link at all, rather than a broken one
+    pub fn is_not_a_connect_link(&self) -> bool {
+        matches!(
+            self,
+            ConnectLinkError::Empty
+                | ConnectLinkError::InvalidUrl(_)
+                | ConnectLinkError::UnsupportedScheme(_)
+                | ConnectLinkError::NotAConnectLink
+        )
+    }
+}
+
+/// Parse a connect link in any of the supported forms
+pub fn parse(link: &str) -> Result<ConnectRequest, ConnectLinkError> {
+    let link = link.trim();
+    let link = link.strip_prefix("--url=").unwrap_or(link);
+    if link.is_empty() {
+        return Err(ConnectLinkError::Empty);
+    }
+
+    let url = Url::parse(link).map_err(|e| ConnectLinkError::InvalidUrl(e.to_string()))?;
+    let query = || query_pairs(url.query().unwrap_or_default());
+    let params = match url.scheme() {
+        "tc" => return parse_params(&query()),
+        // tondevwallet://trace/… and others are not connect links
+        "tondevwallet" if url.host_str() == Some("connect") => query(),
+        "tondevwallet" => return Err(ConnectLinkError::NotAConnectLink),
+        "http" | "https" => match telegram_start_param(&url) {
+            Some(param) => return parse_params(&decode_telegram_param(&param)?),
+            None => query(),
+        },
+        scheme => return Err(ConnectLinkError::UnsupportedScheme(scheme.to_string())),
+    };
+
+    // Other links to the wallet, such as `tondevwallet://connect/?ret=back`
+    // sent by dapps to bring the wallet forward, carry no request
+    if param(&params, "id").is_none() && param(&params, "r").is_none() {
+        return Err(ConnectLinkError::NotAConnectLink);
+    }
+    parse_params(&params)
+}
+
+fn query_pairs(query: &str) -> Vec<(String, String)> {
+    form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect()
+}
+
+fn telegram_start_param(url: &Url) -> Option<String> {
+    url.query_pairs()
+        .find(|(k, v)| k == "startattach" && v.starts_with(TELEGRAM_PREFIX))
+        .map(|(_, v)| v[TELEGRAM_PREFIX.len()..].to_string())
+}
+
+/// Undo the TonConnect SDK's Telegram encoding: `%` became `--`, `=` became
+/// `__` and `&` became `-`, after `.`, `-` and `_` were percent-encoded
+fn decode_telegram_param(param: &str) -> Result<Vec<(String, String)>, ConnectLinkError> {
+    if param.is_empty() || param.contains('%') {
+        return Err(ConnectLinkError::InvalidTelegramParameter(
+            param.to_string(),
+        ));
+    }
+    let query = param
+        .replace("--", "%")
+        .replace("__", "=")
+        .replace('-', "&");
+    Ok(query_pairs(&query))
+}
+
+fn param<'a>(params: &'a [(String, String)], name: &'static str) -> Option<&'a str> {
+    params
+        .iter()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.as_str())
+}
+
+fn parse_params(params: &[(String, String)]) -> Result<ConnectRequest, ConnectLinkError> {
+    let version = param(params, "v").ok_or(ConnectLinkError::MissingParameter("v"))?;
+    let version = match version.parse::<u32>() {
+        Ok(CONNECT_PROTOCOL_VERSION) => CONNECT_PROTOCOL_VERSION,
+        _ => return Err(ConnectLinkError::UnsupportedVersion(version.to_string())),
+    };
+
+    let client_id = param(params, "id").ok_or(ConnectLinkError::MissingParameter("id"))?;
+    if client_id.len() != 64 || !client_id.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ConnectLinkError::InvalidClientId(client_id.to_string()));
+    }
+
+    let request = param(params, "r").ok_or(ConnectLinkError::MissingParameter("r"))?;
+    let request: Value = serde_json::from_str(request)
+        .map_err(|e| ConnectLinkError::InvalidRequest(e.to_string()))?;
+    let Value::Object(request) = request else {
+        return Err(ConnectLinkError::InvalidRequest("not a JSON object".into()));
+    };
+
+    let manifest_url = match request.get("manifestUrl") {
+        Some(Value::String(url)) => url.clone(),
+        Some(other) => return Err(ConnectLinkError::InvalidManifestUrl(other.to_string())),
+        None => return Err(ConnectLinkError::MissingManifestUrl),
+    };
+    match Url::parse(&manifest_url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+        _ => return Err(ConnectLinkError::InvalidManifestUrl(manifest_url)),
+    }
+
+    let items = match request.get("items") {
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(parse_item)
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>(),
+        Some(other) => {
+            return Err(ConnectLinkError::InvalidRequest(format!(
+                "items must be an array, got {}",
+                other
+            )))
+        }
+        None => Vec::new(),
+    };
+    if !items.contains(&ConnectItem::TonAddr) {
+        return Err(ConnectLinkError::MissingTonAddr);
+    }
+
+    let return_strategy = match param(params, "ret") {
+        None | Some("back") => ReturnStrategy::Back,
+        Some("none") => ReturnStrategy::None,
+        Some(ret) => match Url::parse(ret) {
+            Ok(_) => ReturnStrategy::Url(ret.to_string()),
+            Err(_) => return Err(ConnectLinkError::InvalidReturnStrategy(ret.to_string())),
+        },
+    };
+
+    Ok(ConnectRequest {
+        version,
+        client_id: client_id.to_lowercase(),
+        manifest_url,
+        items,
+        return_strategy,
+    })
+}
+
+/// Items this wallet does not know are skipped, as the protocol asks
+fn parse_item(item: &Value) -> Result<Option<ConnectItem>, ConnectLinkError> {
+    let Some(name) = item.get("name").and_then(Value::as_str) else {
+        return Err(ConnectLinkError::InvalidItem(format!(
+            "{} has no name",
+            item
+        )));
+    };
+    match name {
+        "ton_addr" | "ton_proof" => serde_json::from_value(item.clone())
+            .map(Some)
+            .map_err(|e| ConnectLinkError::InvalidItem(format!("{}: {}", name, e))),
+        _ => Ok(None),
+    }
+}
+
+/// Parse a connect link for the webview
+#[tauri::command]
+pub fn parse_connect_link(link: String) -> Result<ConnectRequest, String> {
+    parse(&link).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT_ID: &str = "230f1e4df32364888a5dbd92a410266fcb974b73e30ff3e546a654fc8ee2c953";
+    const MANIFEST_URL: &str = "https://dapp.example/tonconnect-manifest.json";
+
+    fn request_json() -> String {
+        format!(
+            r#"{{"manifestUrl":"{}","items":[{{"name":"ton_addr"}},{{"name":"ton_proof","payload":"nonce-1"}}]}}"#,
+            MANIFEST_URL
+        )
+    }
+
+    fn encode(value: &str) -> String {
+        form_urlencoded::byte_serialize(value.as_bytes()).collect()
+    }
+
+    fn query(version: &str, id: &str, request: &str) -> String {
+        format!("v={}&id={}&r={}", version, id, encode(request))
+    }
+
+    fn connect_query() -> String {
+        query("2", CLIENT_ID, &request_json())
+    }
+
+    /// Encode a query the way the TonConnect SDK builds `startattach`
+    fn telegram_link(query: &str) -> String {
+        let param = query
+            .replace('.', "%2E")
+            .replace('-', "%2D")
+            .replace('_', "%5F")
+            .replace('%', "--")
+            .replace('=', "__")
+            .replace('&', "-");
+        format!(
+            "https://t.me/wallet?attach=wallet&startattach={}{}",
+            TELEGRAM_PREFIX, param
+        )
+    }
+
+    fn expected() -> ConnectRequest {
+        ConnectRequest {
+            version: CONNECT_PROTOCOL_VERSION,
+            client_id: CLIENT_ID.to_string(),
+            manifest_url: MANIFEST_URL.to_string(),
+            items: vec![
+                ConnectItem::TonAddr,
+                ConnectItem::TonProof {
+                    payload: "nonce-1".to_string(),
+                },
+            ],
+            return_strategy: ReturnStrategy::Back,
+        }
+    }
+
+    fn error(link: &str) -> ConnectLinkError {
+        parse(link).expect_err(link)
+    }
+
+    #[test]
+    fn parses_every_link_form() {
+        let query = connect_query();
+        for link in [
+            format!("tc://?{}", query),
+            format!("tondevwallet://connect?{}", query),
+            format!("tondevwallet://connect/?{}", query),
+            format!("https://app.tonkeeper.com/ton-connect?{}", query),
+            format!("https://app.tonkeeper.com/ton-connect/?{}&ret=back", query),
+            format!("--url=tc://?{}", query),
+            format!("  --url=tondevwallet://connect/?{}\n", query),
+            telegram_link(&query),
+        ] {
+            assert_eq!(parse(&link), Ok(expected()), "{}", link);
+        }
+    }
+
+    #[test]
+    fn parses_return_strategies() {
+        let cases = [
+            ("", ReturnStrategy::Back),
+            ("&ret=back", ReturnStrategy::Back),
+            ("&ret=none", ReturnStrategy::None),
+            (
+                "&ret=https%3A%2F%2Fdapp.example%2Fdone",
+                ReturnStrategy::Url("https://dapp.example/done".to_string()),
+            ),
+            (
+                "&ret=tg%3A%2F%2Fresolve",
+                ReturnStrategy::Url("tg://resolve".to_string()),
+            ),
+        ];
+        for (ret, strategy) in cases {
+            let link = format!("tc://?{}{}", connect_query(), ret);
+            assert_eq!(parse(&link).unwrap().return_strategy, strategy, "{}", link);
+        }
+
+        let link = format!("tc://?{}&ret=later", connect_query());
+        assert_eq!(
+            error(&link),
+            ConnectLinkError::InvalidReturnStrategy("later".to_string())
+        );
+    }
+
+    /// Links as the TonConnect SDK builds them for its public demo dapp,
+    /// spelled out rather than produced by the helpers above
+    #[test]
+    fn parses_links_as_dapps_emit_them() {
+        const DEMO_ID: &str = "9f0b3e6b0d3e4d6c8a2f1b7c5e4d3a2918273645e5f4a3b2c1d0e9f8a7b6c5d4";
+        const DEMO_MANIFEST_URL: &str =
+            "https://ton-connect.github.io/demo-dapp-with-react-ui/tonconnect-manifest.json";
+        let proof = || ConnectItem::TonProof {
+            payload: "b5e0fe8a0f1a4bfa8d3c7c2c6d4b1a90".to_string(),
+        };
+        let cases = [
+            // Tonkeeper universal link
+            (
+                "https://app.tonkeeper.com/ton-connect?v=2&id=9f0b3e6b0d3e4d6c8a2f1b7c5e4d3a2918273645e5f4a3b2c1d0e9f8a7b6c5d4&r=%7B%22manifestUrl%22%3A%22https%3A%2F%2Fton-connect.github.io%2Fdemo-dapp-with-react-ui%2Ftonconnect-manifest.json%22%2C%22items%22%3A%5B%7B%22name%22%3A%22ton_addr%22%7D%2C%7B%22name%22%3A%22ton_proof%22%2C%22payload%22%3A%22b5e0fe8a0f1a4bfa8d3c7c2c6d4b1a90%22%7D%5D%7D&ret=back",
+                vec![ConnectItem::TonAddr, proof()],
+                ReturnStrategy::Back,
+            ),
+            // Wallet-agnostic deep link
+            (
+                "tc://?v=2&id=9f0b3e6b0d3e4d6c8a2f1b7c5e4d3a2918273645e5f4a3b2c1d0e9f8a7b6c5d4&r=%7B%22manifestUrl%22%3A%22https%3A%2F%2Fton-connect.github.io%2Fdemo-dapp-with-react-ui%2Ftonconnect-manifest.json%22%2C%22items%22%3A%5B%7B%22name%22%3A%22ton_addr%22%7D%5D%7D&ret=none",
+                vec![ConnectItem::TonAddr],
+                ReturnStrategy::None,
+            ),
+            // Telegram wallet
+            (
+                "https://t.me/wallet?attach=wallet&startattach=tonconnect-v__2-id__9f0b3e6b0d3e4d6c8a2f1b7c5e4d3a2918273645e5f4a3b2c1d0e9f8a7b6c5d4-r__--7B--22manifestUrl--22--3A--22https--3A--2F--2Fton--2Dconnect--2Egithub--2Eio--2Fdemo--2Ddapp--2Dwith--2Dreact--2Dui--2Ftonconnect--2Dmanifest--2Ejson--22--2C--22items--22--3A--5B--7B--22name--22--3A--22ton--5Faddr--22--7D--5D--7D-ret__back",
+                vec![ConnectItem::TonAddr],
+                ReturnStrategy::Back,
+            ),
+            // Opened from a Telegram mini app, which wants the user back there
+            (
+                "https://app.tonkeeper.com/ton-connect?v=2&id=9f0b3e6b0d3e4d6c8a2f1b7c5e4d3a2918273645e5f4a3b2c1d0e9f8a7b6c5d4&r=%7B%22manifestUrl%22%3A%22https%3A%2F%2Fton-connect.github.io%2Fdemo-dapp-with-react-ui%2Ftonconnect-manifest.json%22%2C%22items%22%3A%5B%7B%22name%22%3A%22ton_addr%22%7D%5D%7D&ret=tg%3A%2F%2Fresolve%3Fdomain%3DDemoDappWithTonConnectBot%26appname%3Ddemo",
+                vec![ConnectItem::TonAddr],
+                ReturnStrategy::Url(
+                    "tg://resolve?domain=DemoDappWithTonConnectBot&appname=demo".to_string(),
+                ),
+            ),
+        ];
+        for (link, items, return_strategy) in cases {
+            let expected = ConnectRequest {
+                version: CONNECT_PROTOCOL_VERSION,
+                client_id: DEMO_ID.to_string(),
+                manifest_url: DEMO_MANIFEST_URL.to_string(),
+                items,
+                return_strategy,
+            };
+            assert_eq!(parse(link), Ok(expected), "{}", link);
+        }
+    }
+
+    #[test]
+    fn wallet_links_without_a_request_are_not_connect_links() {
+        for link in [
+            "tondevwallet://connect/?ret=back",
+            "tondevwallet://connect",
+            "tondevwallet://trace/abc",
+            "https://dapp.example/",
+            "https://app.tonkeeper.com/ton-connect?ret=back",
+            "https://t.me/wallet?attach=wallet",
+        ] {
+            let e = error(link);
+            assert_eq!(e, ConnectLinkError::NotAConnectLink, "{}", link);
+            assert!(e.is_not_a_connect_link());
+        }
+    }
+
+    #[test]
+    fn normalizes_the_client_id_and_skips_unknown_items() {
+        let request = format!(
+            r#"{{"manifestUrl":"{}","items":[{{"name":"ton_addr"}},{{"name":"sign_data","types":["text"]}}]}}"#,
+            MANIFEST_URL
+        );
+        let link = format!("tc://?{}", query("2", &CLIENT_ID.to_uppercase(), &request));
+        let parsed = parse(&link).unwrap();
+        assert_eq!(parsed.client_id, CLIENT_ID);
+        assert_eq!(parsed.items, vec![ConnectItem::TonAddr]);
+    }
+
+    #[test]
+    fn rejects_text_that_is_no_link() {
+        assert_eq!(error(""), ConnectLinkError::Empty);
+        assert_eq!(error("  --url= "), ConnectLinkError::Empty);
+        assert!(matches!(
+            error("connect me"),
+            ConnectLinkError::InvalidUrl(_)
+        ));
+        assert_eq!(
+            error("mailto:dev@dapp.example"),
+            ConnectLinkError::UnsupportedScheme("mailto".to_string())
+        );
+        for e in [error(""), error("connect me"), error("ftp://dapp.example")] {
+            assert!(e.is_not_a_connect_link(), "{:?}", e);
+        }
+    }
+
+    #[test]
+    fn reports_missing_parameters() {
+        let request = encode(&request_json());
+        let cases = [
+            (format!("tc://?id={}&r={}", CLIENT_ID, request), "v"),
+            (format!("tc://?v=2&r={}", request), "id"),
+            (format!("tc://?v=2&id={}", CLIENT_ID), "r"),
+        ];
+        for (link, name) in cases {
+            let e = error(&link);
+            assert_eq!(e, ConnectLinkError::MissingParameter(name), "{}", link);
+            assert!(!e.is_not_a_connect_link());
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_versions() {
+        for version in ["1", "3", "two"] {
+            let link = format!("tc://?{}", query(version, CLIENT_ID, &request_json()));
+            assert_eq!(
+                error(&link),
+                ConnectLinkError::UnsupportedVersion(version.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_client_ids() {
+        let short = &CLIENT_ID[..62];
+        let not_hex = format!("{}zz", short);
+        for id in [short, not_hex.as_str()] {
+            let link = format!("tc://?{}", query("2", id, &request_json()));
+            assert_eq!(
+                error(&link),
+                ConnectLinkError::InvalidClientId(id.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_requests() {
+        for request in ["not json", "[]", r#"{"manifestUrl":"x","items":{}}"#] {
+            let request = request.replace('x', MANIFEST_URL);
+            let link = format!("tc://?{}", query("2", CLIENT_ID, &request));
+            assert!(
+                matches!(error(&link), ConnectLinkError::InvalidRequest(_)),
+                "{}",
+                request
+            );
+        }
+
+        let link = format!("tc://?{}", query("2", CLIENT_ID, r#"{"items":[]}"#));
+        assert_eq!(error(&link), ConnectLinkError::MissingManifestUrl);
+
+        for manifest_url in [r#""ftp://dapp.example/m.json""#, r#""manifest.json""#, "42"] {
+            let request = format!(
+                r#"{{"manifestUrl":{},"items":[{{"name":"ton_addr"}}]}}"#,
+                manifest_url
+            );
+            let link = format!("tc://?{}", query("2", CLIENT_ID, &request));
+            assert!(
+                matches!(error(&link), ConnectLinkError::InvalidManifestUrl(_)),
+                "{}",
+                manifest_url
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_items() {
+        for items in [
+            r#"[{"payload":"x"}]"#,
+            r#"[{"name":"ton_addr"},{"name":"ton_proof"}]"#,
+            r#"[{"name":"ton_addr"},{"name":"ton_proof","payload":1}]"#,
+        ] {
+            let request = format!(r#"{{"manifestUrl":"{}","items":{}}}"#, MANIFEST_URL, items);
+            let link = format!("tc://?{}", query("2", CLIENT_ID, &request));
+            assert!(
+                matches!(error(&link), ConnectLinkError::InvalidItem(_)),
+                "{}",
+                items
+            );
+        }
+
+        for items in ["[]", r#"[{"name":"ton_proof","payload":"x"}]"#] {
+            let request = format!(r#"{{"manifestUrl":"{}","items":{}}}"#, MANIFEST_URL, items);
+            let link = format!("tc://?{}", query("2", CLIENT_ID, &request));
+            assert_eq!(error(&link), ConnectLinkError::MissingTonAddr, "{}", items);
+        }
+    }
+
+    #[test]
+    fn rejects_broken_telegram_parameters() {
+        for param in ["", "v__2%25"] {
+            let link = format!(
+                "https://t.me/wallet?attach=wallet&startattach={}{}",
+                TELEGRAM_PREFIX, param
+            );
+            assert!(
+                matches!(error(&link), ConnectLinkError::InvalidTelegramParameter(_)),
+                "{}",
+                link
+            );
+        }
+
+        // A start parameter that decodes, but to an incomplete request
+        let link = telegram_link(&format!("v=2&id={}", CLIENT_ID));
+        assert_eq!(error(&link), ConnectLinkError::MissingParameter("r"));
+    }
+}
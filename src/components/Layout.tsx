@@ -1,4 +1,5 @@
 import { useTauriEventListener } from '@/eventListener'
+import { useEchoStatePublisher } from '@/echoState'
 import { usePassword } from '@/store/passwordManager'
 import { Outlet, useLocation } from 'react-router-dom'
 import { SavedWalletsList } from './SavedWalletsList/SavedWalletsList'
@@ -14,6 +15,7 @@ export function Layout() {
   const currentPath = useLocation()
 
   useTauriEventListener()
+  useEchoStatePublisher()
 
   const isTracerPage = useMemo(() => currentPath.pathname === '/app/tracer', [currentPath])
 
@@ -1,38 +1,174 @@
 import { getPasswordInteractive } from '@/store/passwordManager'
 import { useTonConnectState } from '@/store/tonConnect'
-import { faPaste, faQrcode } from '@fortawesome/free-solid-svg-icons'
+import {
+  faCropSimple,
+  faPaste,
+  faQrcode,
+  faTrash,
+  faWindowMaximize,
+} from '@fortawesome/free-solid-svg-icons'
 import { FontAwesomeIcon } from '@fortawesome/react-fontawesome'
-import { getQrcodeFromScreen } from '../TonConnect/TonConnect'
+import {
+  getCaptureScreens,
+  getQrcodesFromRegion,
+  getQrcodesFromScreen,
+  qrErrorMessage,
+} from '../TonConnect/TonConnect'
 import { Dialog, DialogContent, DialogHeader, DialogTrigger } from '../ui/dialog'
-// import { useState } from 'react'
+import { useEffect, useState } from 'react'
 import { Card, CardDescription, CardHeader } from '@/components/ui/card'
 import { Input } from '@/components/ui/input'
 import { Label } from '@/components/ui/label'
+import {
+  CaptureRegion,
+  CaptureScreen,
+  CaptureWindow,
+  DetectedQrCode,
+  QrTarget,
+  WindowTarget,
+} from '@/types/connect'
+import { invoke } from '@tauri-apps/api/core'
+import { RegionPicker } from './RegionPicker'
 
 export function DetectTonConnect() {
   // const [open, setOpen] = useState(false)
   const connectState = useTonConnectState()
 
   const tonConnectState = useTonConnectState()
+  // Set when the screens show more than one connect code
+  const [choices, setChoices] = useState<DetectedQrCode[]>([])
+  const [error, setError] = useState<string | undefined>()
+  // Listed when the user wants to scan a single window
+  const [windows, setWindows] = useState<CaptureWindow[]>([])
+  // Window the choices were found in
+  const [scannedWindow, setScannedWindow] = useState<WindowTarget | undefined>()
+  // Listed when the user wants to scan a region of a screen
+  const [screens, setScreens] = useState<CaptureScreen[]>([])
+  // Places codes were found before, scanned first by the screen scan
+  const [targets, setTargets] = useState<QrTarget[]>([])
 
-  const tryToStartConnect = async () => {
-    const code = await getQrcodeFromScreen()
-    if (!code) {
-      return
+  const open = connectState.qrcodeOpen.get()
+
+  const loadTargets = async () => {
+    try {
+      setTargets(await invoke<QrTarget[]>('list_qr_targets'))
+    } catch (e) {
+      console.log('Could not list remembered QR code positions', e)
+    }
+  }
+
+  useEffect(() => {
+    if (open) {
+      loadTargets()
     }
+  }, [open])
+
+  const startConnect = async (code: DetectedQrCode, target?: WindowTarget) => {
+    setChoices([])
+    // Scan the same place first when this dapp connects again
+    invoke('remember_qr_code', { code, window: target }).catch((e) =>
+      console.log('Could not remember QR code position', e)
+    )
 
     const password = await getPasswordInteractive()
     if (password) {
-      tonConnectState.connectArg.set(code)
+      tonConnectState.connectArg.set(code.text)
       tonConnectState.popupOpen.set(true)
     }
   }
 
+  const offerCodes = async (codes: DetectedQrCode[], target?: WindowTarget) => {
+    codes = codes.filter((c) => c.kind === 'ton_connect')
+    if (codes.length === 0) {
+      setError('No TonConnect QR code found')
+    } else if (codes.length === 1) {
+      await startConnect(codes[0], target)
+    } else {
+      setScannedWindow(target)
+      setChoices(codes)
+    }
+  }
+
+  const tryToStartConnect = async () => {
+    setError(undefined)
+    setWindows([])
+    setScreens([])
+    try {
+      await offerCodes(await getQrcodesFromScreen())
+    } catch (e) {
+      setError(qrErrorMessage(e))
+    }
+  }
+
+  const listWindows = async () => {
+    setError(undefined)
+    setChoices([])
+    setScreens([])
+    try {
+      setWindows(await invoke<CaptureWindow[]>('list_capture_windows'))
+    } catch (e) {
+      setError(qrErrorMessage(e))
+    }
+  }
+
+  const scanWindow = async (captureWindow: CaptureWindow) => {
+    setError(undefined)
+    setWindows([])
+    // Titles outlive process IDs, so the window is found again after a restart
+    const target: WindowTarget = { title: captureWindow.title }
+    try {
+      await offerCodes(
+        await invoke<DetectedQrCode[]>('detect_qr_code_in_window', {
+          window: { pid: captureWindow.pid, title: captureWindow.title },
+        }),
+        target
+      )
+    } catch (e) {
+      setError(qrErrorMessage(e))
+    }
+  }
+
+  const listScreens = async () => {
+    setError(undefined)
+    setChoices([])
+    setWindows([])
+    try {
+      setScreens(await getCaptureScreens())
+    } catch (e) {
+      setError(qrErrorMessage(e))
+    }
+  }
+
+  const scanRegion = async (region: CaptureRegion) => {
+    setError(undefined)
+    setScreens([])
+    try {
+      await offerCodes(await getQrcodesFromRegion(region))
+    } catch (e) {
+      setError(qrErrorMessage(e))
+    }
+  }
+
+  const forgetTarget = async (origin: string) => {
+    try {
+      await invoke('forget_qr_target', { origin })
+    } catch (e) {
+      setError(String(e))
+    }
+    await loadTargets()
+  }
+
   return (
     <Dialog
-      open={connectState.qrcodeOpen.get()}
+      open={open}
       onOpenChange={(v) => {
         connectState.qrcodeOpen.set(v)
+        if (!v) {
+          setChoices([])
+          setWindows([])
+          setScreens([])
+          setError(undefined)
+        }
       }}
     >
       {/* Add dialog trigger */}
@@ -66,6 +202,103 @@ export function DetectTonConnect() {
           </button>
         </Card>
 
+        <Card className={'hover:bg-secondary'}>
+          <button className={'flex text-left items-center'} onClick={listWindows}>
+            <FontAwesomeIcon icon={faWindowMaximize} size="2x" className={'ml-4'} />
+            <div className="flex flex-col">
+              <CardHeader>
+                Scan a window
+                <CardDescription>
+                  Choose the browser window with the DApp, it is scanned even when covered
+                </CardDescription>
+              </CardHeader>
+            </div>
+          </button>
+        </Card>
+
+        {windows.length > 0 && (
+          <div className="flex flex-col gap-1 max-h-64 overflow-y-auto">
+            {windows.map((w, i) => (
+              <button
+                key={i}
+                className="text-left text-sm px-4 py-1 rounded hover:bg-secondary truncate"
+                onClick={() => scanWindow(w)}
+              >
+                <b>{w.app_name}</b> {w.title}
+              </button>
+            ))}
+          </div>
+        )}
+
+        <Card className={'hover:bg-secondary'}>
+          <button className={'flex text-left items-center'} onClick={listScreens}>
+            <FontAwesomeIcon icon={faCropSimple} size="2x" className={'ml-4'} />
+            <div className="flex flex-col">
+              <CardHeader>
+                Scan a region
+                <CardDescription>
+                  Wallet will be hidden for a second to take screenshots, then drag a rectangle
+                  around the QR code
+                </CardDescription>
+              </CardHeader>
+            </div>
+          </button>
+        </Card>
+
+        {screens.length > 0 && <RegionPicker screens={screens} onSelect={scanRegion} />}
+
+        {error && <div className="text-sm text-destructive">{error}</div>}
+
+        {choices.length > 0 && (
+          <div className="flex flex-col gap-2">
+            <div className="text-sm text-muted-foreground">
+              Found {choices.length} connect codes, choose one:
+            </div>
+            {choices.map((code, i) => (
+              <Card key={i} className={'hover:bg-secondary'}>
+                <button
+                  className={'flex flex-col text-left w-full px-4 py-2'}
+                  onClick={() => startConnect(code, scannedWindow)}
+                >
+                  <div className="text-sm">
+                    {code.screen === null ? 'Window' : `Screen ${code.screen + 1}`}
+                    {code.bbox && ` at ${Math.round(code.bbox.x)}, ${Math.round(code.bbox.y)}`}
+                  </div>
+                  <div className="text-xs text-muted-foreground truncate w-full">{code.text}</div>
+                </button>
+              </Card>
+            ))}
+          </div>
+        )}
+
+        {targets.length > 0 && (
+          <div className="flex flex-col gap-1">
+            <div className="text-sm text-muted-foreground">
+              Scanned first for DApps connected before:
+            </div>
+            <div className="flex flex-col max-h-32 overflow-y-auto">
+              {targets.map((t) => (
+                <div key={t.origin} className="flex items-center gap-2 text-sm px-4 py-1">
+                  <div className="truncate flex-1">
+                    {t.origin}
+                    <span className="text-xs text-muted-foreground">
+                      {' '}
+                      {t.target.type === 'window' ? 'window' : 'screen region'}
+                    </span>
+                  </div>
+                  <button
+                    className="text-muted-foreground hover:text-destructive"
+                    title="Forget"
+                    onClick={() => forgetTarget(t.origin)}
+                  >
+                    <FontAwesomeIcon icon={faTrash} size="xs" />
+                  </button>
+                </div>
+              ))}
+            </div>
+          </div>
+        )}
+
         <Card>
           <button className={'flex text-left items-center'}>
             <FontAwesomeIcon icon={faPaste} size="2x" className={'ml-4'} />
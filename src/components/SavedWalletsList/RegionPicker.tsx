@@ -0,0 +1,106 @@
+import { CaptureRegion, CaptureScreen } from '@/types/connect'
+import { PointerEvent, useRef, useState } from 'react'
+
+// Corners of the dragged rectangle, as shares of the preview size
+interface Selection {
+  screenId: number
+  fromX: number
+  fromY: number
+  toX: number
+  toY: number
+}
+
+// Shorter drags are taken for clicks
+const MIN_SELECTION = 0.01
+
+function clamp(value: number) {
+  return Math.min(1, Math.max(0, value))
+}
+
+// Screen previews to drag a rectangle on. The rectangle is reported in the
+// screen's logical pixels.
+export function RegionPicker({
+  screens,
+  onSelect,
+}: {
+  screens: CaptureScreen[]
+  onSelect: (region: CaptureRegion) => void
+}) {
+  const [selection, setSelection] = useState<Selection | undefined>()
+  const dragging = useRef(false)
+
+  const position = (e: PointerEvent<HTMLDivElement>) => {
+    const rect = e.currentTarget.getBoundingClientRect()
+    return {
+      x: clamp((e.clientX - rect.left) / rect.width),
+      y: clamp((e.clientY - rect.top) / rect.height),
+    }
+  }
+
+  const finish = (screen: CaptureScreen) => {
+    dragging.current = false
+    if (!selection || selection.screenId !== screen.id) {
+      return
+    }
+    const left = Math.min(selection.fromX, selection.toX)
+    const top = Math.min(selection.fromY, selection.toY)
+    const width = Math.abs(selection.toX - selection.fromX)
+    const height = Math.abs(selection.toY - selection.fromY)
+    if (width < MIN_SELECTION || height < MIN_SELECTION) {
+      setSelection(undefined)
+      return
+    }
+    onSelect({
+      screen_id: screen.id,
+      x: Math.round(left * screen.width),
+      y: Math.round(top * screen.height),
+      width: Math.max(1, Math.round(width * screen.width)),
+      height: Math.max(1, Math.round(height * screen.height)),
+    })
+  }
+
+  return (
+    <div className="flex flex-col gap-2 max-h-96 overflow-y-auto">
+      <div className="text-sm text-muted-foreground">Drag a rectangle around the QR code:</div>
+      {screens.map((screen) => (
+        <div key={screen.id} className="flex flex-col gap-1">
+          {screens.length > 1 && <div className="text-xs">{screen.name}</div>}
+          <div
+            className="relative cursor-crosshair select-none touch-none"
+            onPointerDown={(e) => {
+              const { x, y } = position(e)
+              e.currentTarget.setPointerCapture(e.pointerId)
+              dragging.current = true
+              setSelection({ screenId: screen.id, fromX: x, fromY: y, toX: x, toY: y })
+            }}
+            onPointerMove={(e) => {
+              if (!dragging.current) {
+                return
+              }
+              const { x, y } = position(e)
+              setSelection((s) => s && { ...s, toX: x, toY: y })
+            }}
+            onPointerUp={() => finish(screen)}
+          >
+            <img
+              src={`data:image/png;base64,${screen.preview}`}
+              className="w-full rounded"
+              draggable={false}
+            />
+            {selection?.screenId === screen.id && (
+              <div
+                className="absolute border-2 border-primary bg-primary/20 pointer-events-none"
+                style={{
+                  left: `${Math.min(selection.fromX, selection.toX) * 100}%`,
+                  top: `${Math.min(selection.fromY, selection.toY) * 100}%`,
+                  width: `${Math.abs(selection.toX - selection.fromX) * 100}%`,
+                  height: `${Math.abs(selection.toY - selection.fromY) * 100}%`,
+                }}
+              />
+            )}
+          </div>
+        </div>
+      ))}
+    </div>
+  )
+}
@@ -1,4 +1,5 @@
 import { useState, useEffect, useMemo, useCallback } from 'react'
+import { listen } from '@tauri-apps/api/event'
 import { Card, CardContent, CardDescription, CardHeader, CardTitle } from '../ui/card'
 import { Table, TableBody, TableCell, TableHead, TableHeader, TableRow } from '../ui/table'
 import { Button } from '../ui/button'
@@ -112,6 +113,19 @@ const AddressesList = ({ networkId, networkName, addressBook }: AddressesListPro
     fetchAddresses()
   }, [fetchAddresses, networkId, page])
 
+  // Refetch when dev tooling labels addresses through the echo server
+  useEffect(() => {
+    const unlisten = listen<{ network_ids: number[] }>('address_book_changed', ({ payload }) => {
+      if (payload.network_ids.includes(networkId)) {
+        fetchAddresses()
+      }
+    })
+
+    return () => {
+      unlisten.then((f) => f())
+    }
+  }, [fetchAddresses, networkId])
+
   // Handle search
   const handleSearch = useCallback(() => {
     setPage(1) // Reset to first page
@@ -0,0 +1,215 @@
+import { memo, useCallback, useEffect, useState } from 'react'
+import { invoke } from '@tauri-apps/api/core'
+import { Card, CardContent, CardDescription, CardHeader, CardTitle } from '../ui/card'
+import { Table, TableBody, TableCell, TableHead, TableHeader, TableRow } from '../ui/table'
+import { Button } from '../ui/button'
+import { Input } from '../ui/input'
+import { useToast } from '../ui/use-toast'
+import { FontAwesomeIcon } from '@fortawesome/react-fontawesome'
+import { faPlug, faPlus, faTrash } from '@fortawesome/free-solid-svg-icons'
+import { getDatabase } from '@/db'
+import { Setting } from '@/types/settings'
+
+/** Browser origins allowed to connect to the echo server, as a JSON array */
+const ALLOWED_ORIGINS_SETTING = 'ton_echo_allowed_origins'
+
+async function loadAllowedOrigins(): Promise<string[]> {
+  const db = await getDatabase()
+  const setting = await db<Setting>('settings').where('name', ALLOWED_ORIGINS_SETTING).first()
+  if (!setting) {
+    return []
+  }
+  try {
+    const origins = JSON.parse(setting.value)
+    return Array.isArray(origins) ? origins.filter((o) => typeof o === 'string') : []
+  } catch (e) {
+    console.error('Failed to parse allowed origins', e)
+    return []
+  }
+}
+
+async function saveAllowedOrigins(origins: string[]) {
+  const db = await getDatabase()
+  const value = JSON.stringify(origins)
+  const setting = await db<Setting>('settings').where('name', ALLOWED_ORIGINS_SETTING).first()
+  if (setting) {
+    await db<Setting>('settings').where('name', ALLOWED_ORIGINS_SETTING).update({ value })
+  } else {
+    await db<Setting>('settings').insert({ name: ALLOWED_ORIGINS_SETTING, value })
+  }
+}
+
+/**
+ * The origin of a URL, e.g. `https://dapp.example` or `chrome-extension://<id>`.
+ * Built by hand, as `URL.origin` is `null` for extension schemes.
+ */
+function normalizeOrigin(input: string): string | null {
+  try {
+    const url = new URL(input.trim())
+    return url.host ? `${url.protocol}//${url.host}` : null
+  } catch {
+    return null
+  }
+}
+
+interface EchoClient {
+  client_id: string
+  name: string | null
+  origin: string | null
+  created_at: number
+  last_seen_at: number | null
+}
+
+const EchoClientsSettings = memo(() => {
+  const { toast } = useToast()
+  const [clients, setClients] = useState<EchoClient[]>([])
+  const [origins, setOrigins] = useState<string[]>([])
+  const [newOrigin, setNewOrigin] = useState('')
+
+  const fetchClients = useCallback(async () => {
+    try {
+      setClients(await invoke<EchoClient[]>('list_echo_clients'))
+    } catch (e) {
+      console.log('Could not load paired clients', e)
+    }
+  }, [])
+
+  useEffect(() => {
+    fetchClients()
+    loadAllowedOrigins().then(setOrigins)
+  }, [fetchClients])
+
+  const updateOrigins = useCallback(
+    async (next: string[]) => {
+      try {
+        await saveAllowedOrigins(next)
+        setOrigins(next)
+      } catch (e) {
+        toast({ title: 'Could not save origins', description: String(e), variant: 'destructive' })
+      }
+    },
+    [toast]
+  )
+
+  const addOrigin = useCallback(async () => {
+    const origin = normalizeOrigin(newOrigin)
+    if (!origin) {
+      toast({
+        title: 'Invalid origin',
+        description: 'Enter an origin like https://dapp.example',
+        variant: 'destructive',
+      })
+      return
+    }
+    if (!origins.includes(origin)) {
+      await updateOrigins([...origins, origin])
+    }
+    setNewOrigin('')
+  }, [newOrigin, origins, toast, updateOrigins])
+
+  const revoke = useCallback(
+    async (clientId: string) => {
+      try {
+        await invoke('revoke_echo_client', { clientId })
+        toast({ title: 'Client revoked', description: clientId })
+      } catch (e) {
+        toast({ title: 'Could not revoke client', description: String(e), variant: 'destructive' })
+      }
+      await fetchClients()
+    },
+    [fetchClients, toast]
+  )
+
+  return (
+    <Card className="border shadow-sm overflow-hidden">
+      <CardHeader className="border-b bg-muted/30 pt-6 pb-6">
+        <div className="flex items-center gap-2">
+          <FontAwesomeIcon icon={faPlug} className="text-primary" />
+          <div>
+            <CardTitle className="text-lg">Connected Clients</CardTitle>
+            <CardDescription>
+              Scripts and extensions allowed to send transactions and traces to the wallet
+            </CardDescription>
+          </div>
+        </div>
+      </CardHeader>
+      <CardContent className="p-6">
+        {clients.length === 0 ? (
+          <p className="text-muted-foreground">No clients are paired.</p>
+        ) : (
+          <Table>
+            <TableHeader>
+              <TableRow>
+                <TableHead>Client</TableHead>
+                <TableHead>Origin</TableHead>
+                <TableHead>Last seen</TableHead>
+                <TableHead className="w-[80px]" />
+              </TableRow>
+            </TableHeader>
+            <TableBody>
+              {clients.map((client) => (
+                <TableRow key={client.client_id}>
+                  <TableCell>
+                    <div className="font-medium">{client.name ?? client.client_id}</div>
+                    {client.name && (
+                      <div className="text-xs text-muted-foreground">{client.client_id}</div>
+                    )}
+                  </TableCell>
+                  <TableCell className="text-muted-foreground">{client.origin ?? '-'}</TableCell>
+                  <TableCell className="text-muted-foreground">
+                    {new Date(client.last_seen_at ?? client.created_at).toLocaleString()}
+                  </TableCell>
+                  <TableCell>
+                    <Button variant="ghost" size="sm" onClick={() => revoke(client.client_id)}>
+                      <FontAwesomeIcon icon={faTrash} />
+                    </Button>
+                  </TableCell>
+                </TableRow>
+              ))}
+            </TableBody>
+          </Table>
+        )}
+
+        <div className="mt-6 space-y-3">
+          <div>
+            <div className="font-medium">Allowed origins</div>
+            <p className="text-sm text-muted-foreground">
+              Web pages and browser extensions that may ask to pair. Scripts outside the browser
+              send no origin and do not need to be listed.
+            </p>
+          </div>
+          {origins.map((origin) => (
+            <div key={origin} className="flex items-center justify-between gap-2">
+              <span className="font-mono text-sm">{origin}</span>
+              <Button
+                variant="ghost"
+                size="sm"
+                onClick={() => updateOrigins(origins.filter((o) => o !== origin))}
+              >
+                <FontAwesomeIcon icon={faTrash} />
+              </Button>
+            </div>
+          ))}
+          <div className="flex gap-2">
+            <Input
+              value={newOrigin}
+              placeholder="https://dapp.example"
+              onChange={(e) => setNewOrigin(e.target.value)}
+              onKeyDown={(e) => {
+                if (e.key === 'Enter') addOrigin()
+              }}
+            />
+            <Button variant="outline" onClick={addOrigin} disabled={!newOrigin.trim()}>
+              <FontAwesomeIcon icon={faPlus} className="mr-2" />
+              Add
+            </Button>
+          </div>
+        </div>
+      </CardContent>
+    </Card>
+  )
+})
+
+EchoClientsSettings.displayName = 'EchoClientsSettings'
+
+export default EchoClientsSettings
@@ -5,6 +5,7 @@ import { Tooltip, TooltipContent, TooltipProvider, TooltipTrigger } from '../ui/
 import { cn } from '@/utils/cn'
 import { useState, useEffect } from 'react'
 import { fetch as tFetch } from '@tauri-apps/plugin-http'
+import { invoke } from '@tauri-apps/api/core'
 import { LiteClient, LiteRoundRobinEngine, LiteSingleEngine } from 'ton-lite-client'
 import { tauriState } from '@/store/tauri'
 import { LSConfigData } from '@/types/network'
@@ -81,12 +82,17 @@ const NetworkTestButton = ({
         throw new Error('Tauri state not available')
       }
 
+      // The local proxy only relays to liteservers from trusted configs
+      if (!useCustomHost) {
+        await invoke('trust_proxy_config', { url })
+      }
+
       // Try to connect to at least one liteserver
       for (const liteserver of data.liteservers) {
         const pubkey = encodeURIComponent(liteserver.id.key)
         const host = useCustomHost
           ? customHost! + `/?ip=${liteserver.ip}&port=${liteserver.port}&pubkey=${pubkey}`
-          : `ws://localhost:${tauri!.port.get()}/?ip=${liteserver.ip}&port=${liteserver.port}&pubkey=${pubkey}`
+          : `ws://localhost:${tauri!.port.get()}/?ip=${liteserver.ip}&port=${liteserver.port}&pubkey=${pubkey}&auth=${tauri!.auth.get()}`
 
         const singleEngine = new LiteSingleEngine({
           host,
@@ -2,6 +2,7 @@ import { ChangePasswordPopup } from '../SavedWalletsList/ChangePasswordPopup'
 import NetworkSettings from './NetworkSettings'
 import ExtraCurrencySettings from './ExtraCurrencySettings'
 import AddressBookSettings from './AddressBookSettings'
+import EchoClientsSettings from './EchoClientsSettings'
 import { Tabs, TabsContent, TabsList, TabsTrigger } from '../ui/tabs'
 import { FontAwesomeIcon } from '@fortawesome/react-fontawesome'
 import {
@@ -114,7 +115,7 @@ export function SettingsPage() {
         */}
 
         {/* Security Settings Tab */}
-        <TabsContent value="security" className="pt-6">
+        <TabsContent value="security" className="pt-6 space-y-6">
           <Card className="border shadow-sm overflow-hidden">
             <CardHeader className="border-b bg-muted/30 pt-6 pb-6">
               <div className="flex items-center gap-2">
@@ -129,6 +130,7 @@ export function SettingsPage() {
               <ChangePasswordPopup />
             </CardContent>
           </Card>
+          <EchoClientsSettings />
         </TabsContent>
 
         {/* Networks Settings Tab */}
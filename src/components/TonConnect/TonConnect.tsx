@@ -27,6 +27,8 @@ import { delay } from '@/utils'
 import { randomX25519, secretKeyToED25519 } from '@/utils/ed25519'
 import { SignMessage } from '@/utils/signer'
 import { Key } from '@/types/Key'
+import { CaptureRegion, CaptureScreen, DetectedQrCode, QrError } from '@/types/connect'
+import { toast } from '../ui/use-toast'
 const appWindow = getCurrentWebviewWindow()
 
 export function TonConnect() {
@@ -53,6 +55,12 @@ export function TonConnect() {
         console.log('Found QR code', code)
         setConnectLink(code)
       }
+    } catch (e) {
+      toast({
+        title: 'Could not scan the screen',
+        description: qrErrorMessage(e),
+        variant: 'destructive',
+      })
     } finally {
       setIsDetecting(false)
     }
@@ -353,20 +361,76 @@ export function getImageFromBase64(data: string) {
   })
 }
 
-export async function getQrcodeFromScreen(): Promise<string | undefined> {
-  let res: string[] = []
+// Message of a QrError thrown by the QR commands
+export function qrErrorMessage(e: unknown): string {
+  const error = e as Partial<QrError> | undefined
+  return typeof error?.message === 'string' ? error.message : String(e)
+}
+
+// Codes in a pasted or dropped image. Formats the backend cannot read are
+// decoded by the webview and sent as raw pixels.
+export async function getQrcodesFromBlob(blob: Blob): Promise<DetectedQrCode[]> {
+  try {
+    return await invoke<DetectedQrCode[]>('detect_qr_code_from_image', {
+      data: Buffer.from(await blob.arrayBuffer()).toString('base64'),
+    })
+  } catch (e) {
+    if ((e as Partial<QrError> | undefined)?.code !== 'unsupported_format') {
+      throw e
+    }
+  }
+
+  const bitmap = await createImageBitmap(blob)
+  const canvas = new OffscreenCanvas(bitmap.width, bitmap.height)
+  const context = canvas.getContext('2d')
+  if (!context) {
+    throw new Error('Could not draw the image')
+  }
+  context.drawImage(bitmap, 0, 0)
+  const pixels = context.getImageData(0, 0, bitmap.width, bitmap.height)
+  return invoke<DetectedQrCode[]>('detect_qr_code_from_rgba', {
+    width: pixels.width,
+    height: pixels.height,
+    data: Buffer.from(pixels.data.buffer).toString('base64'),
+  })
+}
+
+// Run a capture with the wallet window out of the way
+async function withWalletHidden<T>(capture: () => Promise<T>): Promise<T> {
   try {
     await appWindow.minimize()
     await delay(64)
-    res = (await invoke('detect_qr_code')) as string[]
+    return await capture()
   } finally {
     await appWindow.unminimize()
     await appWindow.setFocus()
   }
+}
 
-  if (res.length > 0) {
-    return res[0]
-  }
+// Codes on screen, TonConnect links first. The few most recently used
+// remembered regions are scanned first; every screen only when they show no
+// connect code.
+export async function getQrcodesFromScreen(): Promise<DetectedQrCode[]> {
+  return withWalletHidden(async () => {
+    const remembered = await invoke<DetectedQrCode[]>('detect_qr_code_remembered')
+    if (remembered.some((c) => c.kind === 'ton_connect')) {
+      return remembered
+    }
+    return invoke<DetectedQrCode[]>('detect_qr_code')
+  })
+}
 
-  return undefined
+// Screens with previews to choose a region on
+export async function getCaptureScreens(): Promise<CaptureScreen[]> {
+  return withWalletHidden(() => invoke<CaptureScreen[]>('list_capture_screens'))
+}
+
+// Codes in a rectangle of one screen
+export async function getQrcodesFromRegion(region: CaptureRegion): Promise<DetectedQrCode[]> {
+  return withWalletHidden(() => invoke<DetectedQrCode[]>('detect_qr_code_in_region', { region }))
+}
+
+export async function getQrcodeFromScreen(): Promise<string | undefined> {
+  const res = await getQrcodesFromScreen()
+  return res[0]?.text
 }
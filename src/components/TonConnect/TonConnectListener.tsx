@@ -9,7 +9,6 @@ import {
 } from '@/store/tonConnect'
 import {
   Base64,
-  ConnectRequest,
   DisconnectRpcRequest,
   hexToByteArray,
   SEND_TRANSACTION_ERROR_CODES,
@@ -33,11 +32,18 @@ import {
   GetTransfersFromTCMessage,
   sendTonConnectMessage,
 } from '@/utils/tonConnect'
-import { ConnectMessageTransactionMessage } from '@/types/connect'
+import {
+  ConnectMessageTransactionMessage,
+  DetectedQrCode,
+  TonConnectLinkRequest,
+} from '@/types/connect'
 import { secretKeyToED25519, secretKeyToX25519 } from '@/utils/ed25519'
 import { useNavigate } from 'react-router-dom'
 import { listen } from '@tauri-apps/api/event'
 import { detectW5PluginInstallation } from '@/utils/detectW5Plugin'
+import { toast } from '@/components/ui/use-toast'
+import { getQrcodesFromBlob, qrErrorMessage } from './TonConnect'
+import { getCurrentWebview } from '@tauri-apps/api/webview'
 const appWindow = getCurrentWebviewWindow()
 
 // TonConnect v3 signMessage request (not present in @tonconnect/protocol 2.x typings)
@@ -53,6 +59,22 @@ export function TonConnectListener() {
   const tonConnectState = useTonConnectState()
   const navigate = useNavigate()
 
+  // Open the connect popup for the first TonConnect code found
+  const openConnectCode = async (codes: DetectedQrCode[]) => {
+    const code = codes.find((c) => c.kind === 'ton_connect')
+    if (!code) {
+      return false
+    }
+    console.log('Found QR code', codes)
+
+    const password = await getPasswordInteractive()
+    if (password) {
+      tonConnectState.connectArg.set(code.text)
+      tonConnectState.popupOpen.set(true)
+    }
+    return true
+  }
+
   useEffect(() => {
     const listener = (event: ClipboardEvent) => {
       const items = event?.clipboardData?.items
@@ -60,28 +82,18 @@ export function TonConnectListener() {
         const item = items[index]
         if (item.kind === 'file') {
           const blob = item.getAsFile()
-          const reader = new FileReader()
-          reader.onload = async function (event) {
-            if (!event.target?.result) {
-              return
-            }
-            // debugger
-            const result = event?.target?.result as string
-            const res = (await invoke('detect_qr_code_from_image', {
-              data: result.split(',')[1],
-            })) as string[]
-
-            if (res.length > 0) {
-              console.log('Found QR code', res)
-
-              const password = await getPasswordInteractive()
-              if (password) {
-                tonConnectState.connectArg.set(res[0])
-                tonConnectState.popupOpen.set(true)
-              }
-            }
+          if (!blob) {
+            continue
           }
-          reader.readAsDataURL(blob)
+          getQrcodesFromBlob(blob)
+            .then(openConnectCode)
+            .catch((e) => {
+              toast({
+                title: 'Could not read the pasted image',
+                description: qrErrorMessage(e),
+                variant: 'destructive',
+              })
+            })
         } else if (item.kind === 'string' && item.type === 'text/plain') {
           item.getAsString(async (pastedString: string) => {
             if (pastedString.includes('tondevwallet://trace/')) {
@@ -92,26 +104,16 @@ export function TonConnectListener() {
               }
               return
             }
-            if (
-              !pastedString ||
-              !pastedString.includes('id') ||
-              !pastedString.includes('manifestUrl')
-            ) {
+            if (!pastedString || !pastedString.includes('://')) {
               return
             }
             try {
-              const input = pastedString
-              const parsed = new URL(input)
-
-              const clientId = parsed.searchParams.get('id') || '' // '230f1e4df32364888a5dbd92a410266fcb974b73e30ff3e546a654fc8ee2c953'
-              const rString = parsed.searchParams.get('r')
-              const r = rString ? (JSON.parse(rString) as ConnectRequest) : undefined
-              if (r?.manifestUrl && clientId) {
-                const password = await getPasswordInteractive()
-                if (password) {
-                  tonConnectState.connectArg.set(input)
-                  tonConnectState.popupOpen.set(true)
-                }
+              const input = pastedString.trim()
+              await invoke<TonConnectLinkRequest>('parse_connect_link', { link: input })
+              const password = await getPasswordInteractive()
+              if (password) {
+                tonConnectState.connectArg.set(input)
+                tonConnectState.popupOpen.set(true)
               }
             } catch (e) {
               // it's ok
@@ -129,17 +131,38 @@ export function TonConnectListener() {
   useEffect(() => {
     const unlisten = listen('tonconnect_svg', async ({ payload }) => {
       const imageBase64 = (payload as any).data.image as string
-      const res = (await invoke('detect_qr_code_from_image', {
+      const res = await invoke<DetectedQrCode[]>('detect_qr_code_from_image', {
         data: imageBase64,
-      })) as string[]
+      })
+      await openConnectCode(res)
+    })
 
-      if (res.length > 0) {
-        console.log('Found QR code', res)
+    return () => {
+      unlisten.then((f) => f())
+    }
+  }, [])
 
-        const password = await getPasswordInteractive()
-        if (password) {
-          tonConnectState.connectArg.set(res[0])
-          tonConnectState.popupOpen.set(true)
+  useEffect(() => {
+    // Image files dropped on the window
+    const unlisten = getCurrentWebview().onDragDropEvent(async (event) => {
+      if (event.payload.type !== 'drop') {
+        return
+      }
+      for (const path of event.payload.paths) {
+        if (!/\.(png|jpe?g|webp|bmp|gif|tiff?|ico)$/i.test(path)) {
+          continue
+        }
+        try {
+          const res = await invoke<DetectedQrCode[]>('detect_qr_code_from_file', { path })
+          if (await openConnectCode(res)) {
+            return
+          }
+        } catch (e) {
+          toast({
+            title: 'Could not read the dropped image',
+            description: qrErrorMessage(e),
+            variant: 'destructive',
+          })
         }
       }
     })
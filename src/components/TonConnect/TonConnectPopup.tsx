@@ -17,7 +17,7 @@ import { sendTonConnectStartMessage } from './TonConnect'
 import { decryptWalletData, getPasswordInteractive } from '@/store/passwordManager'
 import { KeyPair } from '@ton/crypto'
 import { getDatabase } from '@/db'
-import { LastSelectedWallets } from '@/types/connect'
+import { LastSelectedWallets, TonConnectLinkRequest } from '@/types/connect'
 import { randomX25519 } from '@/utils/ed25519'
 import { AlertDialog, AlertDialogContent } from '@/components/ui/alert-dialog'
 import { Address } from '@ton/core'
@@ -25,6 +25,7 @@ import { GlobalSearch } from '../GlobalSearch/GlobalSearch'
 import { useSearchState } from '@/store/searchState'
 import { AlertDialogDescription, AlertDialogTitle } from '@radix-ui/react-alert-dialog'
 import { Key } from '@/types/Key'
+import { invoke } from '@tauri-apps/api/core'
 
 const optionsMatrix = {
   bounceable: [true, false],
@@ -340,14 +341,15 @@ function useConnectLink(link: string) {
       if (!link) {
         return
       }
-      const parsed = new URL(link.replace('--url=', ''))
-      const clientId = parsed.searchParams.get('id') || ''
-      const rString = parsed.searchParams.get('r')
-      const r = rString ? (JSON.parse(rString) as ConnectRequest) : undefined
-
-      if (!r) {
+      let request: TonConnectLinkRequest
+      try {
+        request = await invoke<TonConnectLinkRequest>('parse_connect_link', { link })
+      } catch (e) {
+        console.log('Invalid connect link', link, e)
         return
       }
+      const clientId = request.client_id
+      const r: ConnectRequest = { manifestUrl: request.manifest_url, items: request.items }
 
       let metaInfo:
         | {
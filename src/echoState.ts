@@ -0,0 +1,78 @@
+import { invoke } from '@tauri-apps/api/core'
+import { useEffect } from 'react'
+import { useLiteclient, useLiteclientState } from './store/liteClient'
+import { useWalletListState } from './store/walletsListState'
+import { useMessagesState } from './store/connectMessages'
+import { getWalletFromKey } from './utils/wallets'
+import { getNetworkChainId } from './types/network'
+
+type EchoStateTopic = 'selected_network' | 'wallets' | 'pending_messages'
+
+function publishEchoState(topic: EchoStateTopic, data: unknown) {
+  invoke('publish_echo_state', { topic, data }).catch((e) =>
+    console.log('Could not publish echo state', topic, e)
+  )
+}
+
+// Keep tools subscribed through the echo server in sync with the app.
+// Only public data is published: no keys or encrypted fields.
+export function useEchoStatePublisher() {
+  const liteClientState = useLiteclientState()
+  const liteClient = useLiteclient()
+  const keys = useWalletListState()
+  const messages = useMessagesState()
+
+  const selectedNetwork = liteClientState.selectedNetwork.get({ noproxy: true })
+  useEffect(() => {
+    if (!selectedNetwork) {
+      return
+    }
+    publishEchoState('selected_network', {
+      network_id: selectedNetwork.network_id,
+      name: selectedNetwork.name,
+      url: selectedNetwork.url,
+      is_testnet: selectedNetwork.is_testnet,
+      chain_id: getNetworkChainId(selectedNetwork),
+    })
+  }, [selectedNetwork])
+
+  const keyList = keys.promised ? undefined : keys.get({ noproxy: true })
+  useEffect(() => {
+    if (!keyList) {
+      return
+    }
+    const wallets = keyList.flatMap((key) =>
+      (key.wallets || []).map((wallet) => ({
+        key_id: key.id,
+        key_name: key.name,
+        public_key: key.public_key,
+        wallet_id: wallet.id,
+        type: wallet.type,
+        name: wallet.name,
+        address: getWalletFromKey(liteClient, key, wallet)?.address.toString({
+          bounceable: false,
+          testOnly: selectedNetwork?.is_testnet,
+        }),
+      }))
+    )
+    publishEchoState('wallets', wallets)
+  }, [keyList, liteClient, selectedNetwork])
+
+  const messageList = messages.promised ? undefined : messages.get({ noproxy: true })
+  useEffect(() => {
+    if (!messageList) {
+      return
+    }
+    publishEchoState(
+      'pending_messages',
+      messageList.map((message) => ({
+        id: message.id,
+        message_type: message.message_type,
+        key_id: message.key_id,
+        wallet_id: message.wallet_id,
+        wallet_address: message.wallet_address,
+        created_at: message.created_at,
+      }))
+    )
+  }, [messageList])
+}
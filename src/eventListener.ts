@@ -8,100 +8,71 @@ import { getMatches } from '@tauri-apps/plugin-cli'
 import { getWallets } from './store/walletsListState'
 import { getWalletFromKey } from './utils/wallets'
 import { getApiClient } from './store/liteClient'
-import { addConnectMessage } from './store/connectMessages'
+import { addConnectMessage, resolveEchoRequest, trackEchoRequest } from './store/connectMessages'
 import { Address } from '@ton/core'
-import { onOpenUrl } from '@tauri-apps/plugin-deep-link'
-import { DeserializeTraceDump } from '@tondevwallet/traces'
-import { addTracerItem } from './store/tracerState'
-import { AddParsedToDumpTransaction } from './utils/txSerializer'
+import { openTraceDump, restoreTraceDumps, TraceDumpInfo } from './store/tracerState'
 import { secretKeyToX25519 } from './utils/ed25519'
+import { confirm } from '@tauri-apps/plugin-dialog'
+import { invoke } from '@tauri-apps/api/core'
+import { OpenUrl, TonConnectLinkRequest } from './types/connect'
 const appWindow = getCurrentWebviewWindow()
 
 export function useTauriEventListener() {
   const navigate = useNavigate()
   const tonConnectState = useTonConnectState()
 
-  const doConnect = async (arg: string) => {
-    console.log('doConnect', arg)
-    if (arg.startsWith('--url=')) {
-      arg.replace('--url=', '')
-    }
+  const handleOpenUrl = async ({ url, connect, error }: OpenUrl) => {
+    console.log('open url', url)
 
-    if (arg.startsWith('tondevwallet://trace/')) {
-      console.log('trace', arg)
-      // const traceId = arg.replace('tondevwallet://trace/', '')
+    if (url.startsWith('tondevwallet://trace/')) {
+      console.log('trace', url)
+      // const traceId = url.replace('tondevwallet://trace/', '')
       // navigate('/app/tracer', { state: { traceId } })
       return
     }
 
-    if (!arg.startsWith('tondevwallet://connect/')) {
-      return
-    }
-
-    const startString = arg.replace('--url=', '')
-
-    if (startString === 'tondevwallet://connect/?ret=back') {
+    if (url === 'tondevwallet://connect/?ret=back') {
       navigate('/app')
       appWindow.unminimize()
       appWindow.setFocus()
       return
     }
 
+    if (!connect) {
+      if (error) {
+        console.log('Invalid connect link', url, error)
+      }
+      return
+    }
+
     appWindow.unminimize()
     appWindow.setFocus()
 
     const password = await getPasswordInteractive()
 
     if (password) {
-      tonConnectState.connectArg.set(startString)
+      tonConnectState.connectArg.set(url)
       tonConnectState.popupOpen.set(true)
     }
   }
 
   useEffect(() => {
-    getMatches().then((matches) => {
+    getMatches().then(async (matches) => {
       if (matches?.args?.start?.value && typeof matches?.args?.start?.value === 'string') {
-        const value = matches.args.start.value
-        doConnect(value)
+        const url = matches.args.start.value.replace(/^--url=/, '')
+        try {
+          const connect = await invoke<TonConnectLinkRequest>('parse_connect_link', { link: url })
+          await handleOpenUrl({ url, connect })
+        } catch (error) {
+          await handleOpenUrl({ url, error: String(error) })
+        }
       }
     })
   }, [])
 
   useEffect(() => {
-    const unlisten = onOpenUrl(async (urls: string[]) => {
-      console.log('deep link:', urls)
-
-      if (!urls || urls.length === 0) {
-        return
-      }
-
-      const payload = urls[0]
-
-      let startString: string
-
-      if (typeof payload === 'string') {
-        startString = payload
-      } else {
-        const args = (
-          payload as {
-            args: string[]
-          }
-        ).args
-
-        if (args.length < 2) {
-          return
-        }
-
-        const urlArg = args[1]
-        if (!urlArg.startsWith('--url=tondevwallet://connect/')) {
-          return
-        }
-
-        startString = urlArg
-      }
-
-      doConnect(startString)
-    })
+    // Deep links and links passed to a second instance, parsed by the backend
+    const unlisten = listen<OpenUrl>('open_url', ({ payload }) => handleOpenUrl(payload))
 
     return () => {
       unlisten.then((f) => f())
@@ -121,7 +92,16 @@ export function useTauriEventListener() {
         return
       }
 
+      // Set when the echo client waits for the user's decision
+      const requestId: number | undefined = data.request_id
+      const reject = async (reason: string) => {
+        if (typeof requestId !== 'undefined') {
+          await resolveEchoRequest(requestId, { status: 'rejected', reason })
+        }
+      }
+
       if (data?.data?.payload?.method !== 'sendTransaction') {
+        await reject(`Unsupported method ${data?.data?.payload?.method}`)
         return
       }
 
@@ -160,6 +140,7 @@ export function useTauriEventListener() {
 
       if (typeof keyId === 'undefined' || typeof walletId === 'undefined') {
         console.log('no key or wallet found')
+        await reject('No wallet found for the sender address')
         return
       }
 
@@ -169,11 +150,12 @@ export function useTauriEventListener() {
         const keyPair = secretKeyToX25519(session.secretKey)
         const localPublicKey = keyPair.publicKey.toString('hex')
         if (localPublicKey === dataPublicKey) {
+          await reject('The transaction belongs to a TonConnect session of this wallet')
           return
         }
       }
 
-      await addConnectMessage({
+      const message = await addConnectMessage({
         connect_event_id: 0,
         connect_session_id: 0,
         payload: info,
@@ -183,6 +165,9 @@ export function useTauriEventListener() {
         wallet_address: fromAddress,
         message_type: 'tx',
       })
+      if (typeof requestId !== 'undefined') {
+        trackEchoRequest(message.id, requestId)
+      }
       appWindow.unminimize()
       appWindow.setFocus()
     })
@@ -193,22 +178,11 @@ export function useTauriEventListener() {
   }, [])
 
   useEffect(() => {
-    const unlisten = listen('transactions_dump', async ({ payload }) => {
-      const data = payload as any
-      if (data.type !== 'transactions_dump') {
-        return
-      }
-
-      const dump = DeserializeTraceDump(data.data)
-
-      const now = new Date()
-      const formattedDate = `${now.toLocaleDateString()} ${now.toLocaleTimeString()}`
-      const traceName = `Trace ${formattedDate}`
+    // Dumps that arrived while the window was closed are already stored
+    restoreTraceDumps().catch((e) => console.log('Could not restore trace dumps', e))
 
-      // Add new tracer item with the current date as name
-      addTracerItem(traceName, {
-        transactions: dump.transactions.map(AddParsedToDumpTransaction),
-      })
+    const unlisten = listen('trace_dump_added', async ({ payload }) => {
+      await openTraceDump(payload as TraceDumpInfo)
 
       // Navigate to tracer page
       navigate('/app/tracer')
@@ -218,4 +192,35 @@ export function useTauriEventListener() {
       unlisten.then((f) => f())
     }
   }, [])
+
+  useEffect(() => {
+    const unlisten = listen('echo_pairing_request', async ({ payload }) => {
+      const request = payload as {
+        pairing_id: number
+        client_id: string
+        name?: string | null
+        origin?: string | null
+      }
+
+      appWindow.unminimize()
+      appWindow.setFocus()
+
+      const client = request.name ? `${request.name} (${request.client_id})` : request.client_id
+      const origin = request.origin ? `\nOrigin: ${request.origin}` : ''
+      const approve = await confirm(
+        `${client} wants to connect to TON DevWallet.${origin}\n\nIt will be able to send transactions for approval and open traces.`,
+        { title: 'New client', kind: 'warning', okLabel: 'Allow', cancelLabel: 'Deny' }
+      )
+
+      try {
+        await invoke('respond_echo_pairing', { pairingId: request.pairing_id, approve })
+      } catch (e) {
+        console.log('Could not answer pairing request', e)
+      }
+    })
+
+    return () => {
+      unlisten.then((f) => f())
+    }
+  }, [])
 }
@@ -3,6 +3,7 @@ import { ConnectMessageTransaction, ConnectMessageTransactionPayload } from '@/t
 import { hookstate, none, useHookstate } from '@hookstate/core'
 import { Cell } from '@ton/core'
 import { SignDataPayload } from '@tonconnect/protocol'
+import { invoke } from '@tauri-apps/api/core'
 
 export interface TonConnectMessage {
   id: number
@@ -137,6 +138,27 @@ export async function addConnectMessage(input: Omit<FullTonConnectMessage, 'id'>
 
   const message = parseDbMessage(res[0])
   messagesState.merge([message])
+  return message
+}
+
+export type EchoTransactionOutcome =
+  | { status: 'approved'; boc: string; hash: string }
+  | { status: 'rejected'; reason: string }
+  | { status: 'failed'; reason: string }
+
+// Messages from echo clients waiting for the result, by message id
+const echoRequests = new Map<number, number>()
+
+export function trackEchoRequest(messageId: number, requestId: number) {
+  echoRequests.set(messageId, requestId)
+}
+
+export async function resolveEchoRequest(requestId: number, result: EchoTransactionOutcome) {
+  try {
+    await invoke('resolve_echo_request', { requestId, result })
+  } catch (e) {
+    console.log('Could not answer echo request', requestId, e)
+  }
 }
 
 export async function changeConnectMessageStatus(
@@ -155,6 +177,24 @@ export async function changeConnectMessageStatus(
       message_cell: messageCell?.toBoc().toString('base64'),
     })
 
+  const requestId = echoRequests.get(messageId)
+  if (typeof requestId !== 'undefined' && newStatus !== 0) {
+    echoRequests.delete(messageId)
+    let outcome: EchoTransactionOutcome
+    if (newStatus === 2) {
+      outcome = { status: 'rejected', reason: 'User rejected' }
+    } else if (messageCell) {
+      outcome = {
+        status: 'approved',
+        boc: messageCell.toBoc().toString('base64'),
+        hash: messageCell.hash().toString('hex'),
+      }
+    } else {
+      outcome = { status: 'failed', reason: 'Approved, but the wallet did not build a message' }
+    }
+    await resolveEchoRequest(requestId, outcome)
+  }
+
   await removeConnectMessages()
 }
 
@@ -1,4 +1,4 @@
-import { LiteClient, LiteRoundRobinEngine, LiteSingleEngine } from 'ton-lite-client'
+import { LiteClient, LiteEngine, LiteRoundRobinEngine, LiteSingleEngine } from 'ton-lite-client'
 import { hookstate, useHookstate } from '@hookstate/core'
 import { tauriState } from './tauri'
 import { getDatabase } from '@/db'
@@ -8,6 +8,7 @@ import { LSConfigData, Network } from '@/types/network'
 import { fetch as tFetch } from '@tauri-apps/plugin-http'
 import { Api, HttpClient } from 'tonapi-sdk-js'
 import { TonapiBlockchainAdapter } from './tonapiBlockchainAdapter'
+import { LiteMuxEngine } from '@/utils/liteMuxEngine'
 
 const LiteClientState = hookstate<{
   liteClient: LiteClient | null
@@ -259,7 +260,7 @@ async function addWorkingEngineToRoundRobin(
   }
 
   let goodEngineFound = false
-  const registerConnect = (engine: LiteSingleEngine) => {
+  const registerConnect = (engine: LiteEngine) => {
     if (goodEngineFound) {
       engine.close()
     } else {
@@ -286,15 +287,18 @@ async function addWorkingEngineToRoundRobin(
     }
 
     const pubkey = encodeURIComponent(ls.id.key)
-    const host = useCustomHost
-      ? customHost! + `/?ip=${ls.ip}&port=${ls.port}&pubkey=${pubkey}`
-      : `ws://localhost:${tauri!.port.get()}/?ip=${ls.ip}&port=${ls.port}&pubkey=${pubkey}`
-
-    const singleEngine = new LiteSingleEngine({
-      host,
-      publicKey: Buffer.from(ls.id.key, 'base64'),
-      client: 'ws',
-    })
+    // The local proxy shares one liteserver connection between all clients
+    // on /mux and does the ADNL handshake itself
+    const singleEngine: LiteEngine = useCustomHost
+      ? new LiteSingleEngine({
+          host: customHost! + `/?ip=${ls.ip}&port=${ls.port}&pubkey=${pubkey}`,
+          publicKey: Buffer.from(ls.id.key, 'base64'),
+          client: 'ws',
+        })
+      : new LiteMuxEngine({
+          url: () =>
+            `ws://localhost:${tauri!.port.get()}/mux?ip=${ls.ip}&port=${ls.port}&pubkey=${pubkey}&auth=${tauri!.auth.get()}`,
+        })
 
     setTimeout(async () => {
       try {
@@ -332,7 +336,7 @@ function shuffle<T>(array: T[]): T[] {
 
 export { LiteClientState }
 
-async function checkEngine(engine: LiteSingleEngine): Promise<boolean> {
+async function checkEngine(engine: LiteEngine): Promise<boolean> {
   let resolve
   const promise = new Promise<boolean>((_resolve) => {
     resolve = _resolve
@@ -1,14 +1,26 @@
 import { hookstate, useHookstate } from '@hookstate/core'
 import { invoke } from '@tauri-apps/api/core'
+import { listen } from '@tauri-apps/api/event'
 
 export const tauriState = hookstate(async () => {
   const port = await invoke<string>('get_ws_port')
+  const auth = await invoke<string>('get_ws_auth')
 
   return {
     port: parseInt(port, 10),
+    auth,
   }
 })
 
+// The proxy can be rebound on a different port after a restart
+listen<{ server: string; port: number }>('server_port_changed', async (event) => {
+  if (event.payload.server !== 'proxy') {
+    return
+  }
+  await tauriState.promise
+  tauriState.port.set(event.payload.port)
+})
+
 export function useTauriState() {
   return useHookstate(tauriState)
 }
@@ -9,6 +9,9 @@ import { RecursivelyParseCellWithBlock } from '@/utils/tlb/cellParser'
 import { LiteClient } from 'ton-lite-client'
 import { LiteClientState } from './liteClient'
 import { getToncenter3Url } from '@/utils/ton'
+import { invoke } from '@tauri-apps/api/core'
+import { DeserializeTraceDump } from '@tondevwallet/traces'
+import { AddParsedToDumpTransaction } from '@/utils/txSerializer'
 
 export interface GraphData {
   transactions: ParsedTransaction[]
@@ -34,6 +37,15 @@ export interface TracerItem {
   graphData: GraphData | null
   // If remoteId exists, this is a remote trace item
   remoteId?: string
+  // If dumpId exists, the trace is stored in the trace_dumps table
+  dumpId?: number
+}
+
+export interface TraceDumpInfo {
+  trace_dump_id: number
+  source: string | null
+  created_at: number
+  size: number
 }
 
 // Expand the state to include remote traces
@@ -112,6 +124,49 @@ export function addTracerItem(name: string, data: GraphData | null, remoteId?: s
   return id
 }
 
+// Open a dump received by the echo server, or switch to its tab if it is open
+export async function openTraceDump(info: TraceDumpInfo) {
+  const existing = state.items.get().find((item) => item.dumpId === info.trace_dump_id)
+  if (existing) {
+    state.activeItemId.set(existing.id)
+    return existing.id
+  }
+
+  const { dump: serializedDump } = await invoke<TraceDumpInfo & { dump: string }>(
+    'get_trace_dump',
+    { traceDumpId: info.trace_dump_id }
+  )
+  const dump = DeserializeTraceDump(serializedDump)
+
+  // Another call may have opened it while the dump was loading
+  const opened = state.items.get().find((item) => item.dumpId === info.trace_dump_id)
+  if (opened) {
+    state.activeItemId.set(opened.id)
+    return opened.id
+  }
+
+  const date = new Date(info.created_at)
+  const id = addTracerItem(`Trace ${date.toLocaleDateString()} ${date.toLocaleTimeString()}`, {
+    transactions: dump.transactions.map(AddParsedToDumpTransaction),
+  })
+  const index = state.items.get().findIndex((item) => item.id === id)
+  state.items[index].dumpId.set(info.trace_dump_id)
+
+  return id
+}
+
+// Reopen dumps kept from previous runs
+export async function restoreTraceDumps() {
+  const dumps = await invoke<TraceDumpInfo[]>('list_trace_dumps')
+  for (const info of dumps) {
+    try {
+      await openTraceDump(info)
+    } catch (e) {
+      console.log('Could not restore trace dump', info.trace_dump_id, e)
+    }
+  }
+}
+
 // Add function to create a remote tracer item
 export function addRemoteTracerItem(name: string, hash: string) {
   // Create a unique remote ID
@@ -170,6 +225,13 @@ export function removeTracerItem(id: string) {
     state.remoteTraces[item.remoteId].set(none)
   }
 
+  // Closing a stored dump deletes it
+  if (typeof item?.dumpId !== 'undefined') {
+    invoke('delete_trace_dump', { traceDumpId: item.dumpId }).catch((e) =>
+      console.log('Could not delete trace dump', item.dumpId, e)
+    )
+  }
+
   const currentIndex = items.findIndex((item) => item.id === id)
 
   const filteredItemsIndices = items.map((item, index) => (item.id === id ? index : undefined))
@@ -63,3 +63,86 @@ export interface LastSelectedWallets {
   key_id: number
   wallet_id: number
 }
+
+// Connect link parsed by the `parse_connect_link` command
+export interface TonConnectLinkRequest {
+  version: number
+  client_id: string
+  manifest_url: string
+  items: ({ name: 'ton_addr' } | { name: 'ton_proof'; payload: string })[]
+  return_strategy: { type: 'back' | 'none' } | { type: 'url'; url: string }
+}
+
+// Link the app was opened with, from the `open_url` event
+export interface OpenUrl {
+  url: string
+  connect?: TonConnectLinkRequest | null
+  error?: string | null
+}
+
+// Code found by `detect_qr_code` or `detect_qr_code_from_image`
+export interface DetectedQrCode {
+  text: string
+  kind: 'ton_connect' | 'ton_transfer' | 'address' | 'other'
+  format: string
+  // Index of the screen, null for images
+  screen: number | null
+  // Display ID of the screen, null for images and windows
+  screen_id: number | null
+  // In pixels of the scanned image
+  bbox: { x: number; y: number; width: number; height: number } | null
+}
+
+// Error of the QR commands
+export interface QrError {
+  code:
+    | 'capture_permission_denied'
+    | 'no_screens'
+    | 'screen_not_found'
+    | 'window_not_found'
+    | 'capture'
+    | 'unsupported_format'
+    | 'decode'
+    | 'read'
+  message: string
+}
+
+// Window to scan, matched by process ID and/or part of its title
+export interface WindowTarget {
+  pid?: number
+  title?: string
+}
+
+// Window listed by `list_capture_windows`
+export interface CaptureWindow {
+  pid: number
+  app_name: string
+  title: string
+}
+
+// Rectangle of one screen, in logical pixels
+export interface CaptureRegion {
+  screen_id: number
+  x: number
+  y: number
+  width: number
+  height: number
+}
+
+// Screen listed by `list_capture_screens`
+export interface CaptureScreen {
+  id: number
+  name: string
+  // Logical pixels, the unit of CaptureRegion
+  width: number
+  height: number
+  // Base64 PNG
+  preview: string
+}
+
+// Where the code of a dapp was last found, listed by `list_qr_targets`
+export interface QrTarget {
+  origin: string
+  target: ({ type: 'region' } & CaptureRegion) | ({ type: 'window' } & WindowTarget)
+  used_at: number
+}
@@ -0,0 +1,200 @@
+import EventEmitter from 'events'
+import { LiteEngine } from 'ton-lite-client'
+import { Functions } from 'ton-lite-client/dist/schema'
+import { TLFunction, TLReadBuffer, TLWriteBuffer } from 'ton-tl'
+import { getRandomBytes } from './ed25519'
+
+// The proxy's /mux endpoint terminates ADNL and shares one liteserver
+// connection between all clients, so queries go over the WebSocket as
+// plaintext adnl.message.query frames and come back as adnl.message.answer.
+
+const ADNL_MESSAGE_QUERY = 0xb48bf97a
+const ADNL_MESSAGE_ANSWER = 0x0fac8416
+const LITE_SERVER_ERROR = 0xbba9e148
+
+// Delay before reopening a socket that closed while the engine is in use
+const RECONNECT_DELAY = 1000
+
+interface PendingQuery {
+  f: TLFunction<unknown, unknown>
+  resolve: (value: unknown) => void
+  reject: (error: Error) => void
+  timer: ReturnType<typeof setTimeout>
+}
+
+// TL `bytes`: a length prefix, the data and padding to 4 bytes
+function tlBytes(data: Buffer): Buffer {
+  const prefix =
+    data.length <= 253
+      ? Buffer.from([data.length])
+      : Buffer.from([254, data.length & 0xff, (data.length >> 8) & 0xff, data.length >> 16])
+  const padding = (4 - ((prefix.length + data.length) % 4)) % 4
+  return Buffer.concat([prefix, data, Buffer.alloc(padding)])
+}
+
+function readTlBytes(data: Buffer, offset: number): Buffer {
+  if (data[offset] <= 253) {
+    return data.subarray(offset + 1, offset + 1 + data[offset])
+  }
+  const length = data.readUIntLE(offset + 1, 3)
+  return data.subarray(offset + 4, offset + 4 + length)
+}
+
+export class LiteMuxEngine extends EventEmitter implements LiteEngine {
+  // Called on every connect, as the proxy may come back on another port
+  readonly url: () => string
+  #socket: WebSocket | null = null
+  #ready = false
+  #closed = false
+  #queries = new Map<string, PendingQuery>()
+  // Frames of queries made before the socket opened
+  #outbox: Buffer[] = []
+
+  constructor({ url }: { url: () => string }) {
+    super()
+    this.url = url
+    this.#connect()
+  }
+
+  async query<REQ, RES>(
+    f: TLFunction<REQ, RES>,
+    req: REQ,
+    args: { timeout: number; awaitSeqno?: number }
+  ): Promise<RES> {
+    if (this.#closed) {
+      throw new Error('Engine is closed')
+    }
+
+    const data = new TLWriteBuffer()
+    if (args.awaitSeqno !== undefined) {
+      Functions.liteServer_waitMasterchainSeqno.encodeRequest(
+        {
+          kind: 'liteServer.waitMasterchainSeqno',
+          seqno: args.awaitSeqno,
+          timeout_ms: args.timeout,
+        },
+        data
+      )
+    }
+    f.encodeRequest(req, data)
+    const query = new TLWriteBuffer()
+    Functions.liteServer_query.encodeRequest(
+      { kind: 'liteServer.query', data: data.build() },
+      query
+    )
+
+    const id = Buffer.from(getRandomBytes(32))
+    const header = Buffer.alloc(4)
+    header.writeUInt32LE(ADNL_MESSAGE_QUERY)
+    const frame = Buffer.concat([header, id, tlBytes(query.build())])
+
+    return new Promise<RES>((resolve, reject) => {
+      const key = id.toString('hex')
+      const timer = setTimeout(() => {
+        this.#queries.delete(key)
+        reject(new Error('Timeout'))
+      }, args.timeout)
+      this.#queries.set(key, {
+        f: f as TLFunction<unknown, unknown>,
+        resolve: resolve as (value: unknown) => void,
+        reject,
+        timer,
+      })
+
+      if (this.#ready && this.#socket) {
+        this.#socket.send(frame)
+      } else {
+        this.#outbox.push(frame)
+      }
+    })
+  }
+
+  isClosed() {
+    return this.#closed
+  }
+
+  isReady() {
+    return this.#ready
+  }
+
+  close() {
+    this.#closed = true
+    this.#ready = false
+    this.#socket?.close()
+    this.#socket = null
+    this.#failAll(new Error('Engine is closed'))
+  }
+
+  #connect() {
+    const socket = new WebSocket(this.url())
+    socket.binaryType = 'arraybuffer'
+    this.#socket = socket
+
+    socket.onopen = () => {
+      if (this.#socket !== socket) {
+        return
+      }
+      this.#ready = true
+      for (const frame of this.#outbox.splice(0)) {
+        socket.send(frame)
+      }
+      this.emit('ready')
+    }
+    socket.onmessage = (event) => {
+      if (event.data instanceof ArrayBuffer) {
+        this.#onFrame(Buffer.from(event.data))
+      }
+    }
+    socket.onclose = () => {
+      if (this.#socket !== socket) {
+        return
+      }
+      this.#ready = false
+      this.#socket = null
+      this.#failAll(new Error('Connection closed'))
+      this.emit('close')
+      if (!this.#closed) {
+        setTimeout(() => {
+          if (!this.#closed) {
+            this.#connect()
+          }
+        }, RECONNECT_DELAY)
+      }
+    }
+  }
+
+  #onFrame(frame: Buffer) {
+    if (frame.length < 36 || frame.readUInt32LE(0) !== ADNL_MESSAGE_ANSWER) {
+      return
+    }
+    const key = frame.subarray(4, 36).toString('hex')
+    const query = this.#queries.get(key)
+    if (!query) {
+      return
+    }
+    this.#queries.delete(key)
+    clearTimeout(query.timer)
+
+    const answer = readTlBytes(frame, 36)
+    try {
+      if (answer.length >= 8 && answer.readUInt32LE(0) === LITE_SERVER_ERROR) {
+        const code = answer.readInt32LE(4)
+        const message = readTlBytes(answer, 8).toString()
+        query.reject(new Error(`${message} (${code})`))
+      } else {
+        query.resolve(query.f.decodeResponse(new TLReadBuffer(answer)))
+      }
+    } catch (e) {
+      query.reject(e instanceof Error ? e : new Error(String(e)))
+    }
+  }
+
+  #failAll(error: Error) {
+    this.#outbox = []
+    for (const query of this.#queries.values()) {
+      clearTimeout(query.timer)
+      query.reject(error)
+    }
+    this.#queries.clear()
+  }
+}